@@ -0,0 +1,138 @@
+#[macro_use]
+extern crate human_panic;
+extern crate bricks;
+extern crate structopt;
+
+use anyhow::Result;
+use bricks::inplace::Preserve;
+use bricks::reporter::FileReporter;
+use cli::Encoding;
+use std::io::{self, Read, Write};
+use structopt::StructOpt;
+
+mod cli {
+
+    use bricks::cli::Filter;
+    use std::path::PathBuf;
+
+    const ENCODINGS: &[&str] = &["utf-8", "utf-16le", "utf-16be", "latin1"];
+
+    #[derive(structopt::StructOpt, Debug)]
+    #[structopt(about = "Convert files between UTF-8, UTF-16 and Latin-1, add or strip BOMs")]
+    #[structopt(setting = structopt::clap::AppSettings::ColoredHelp)]
+    pub struct Encoding {
+        #[structopt(
+            name = "paths",
+            help = "files or directories to process, if none is specified stdin will be processed",
+            parse(from_os_str)
+        )]
+        pub paths: Vec<PathBuf>,
+
+        #[structopt(
+            short = "t",
+            long = "to",
+            default_value = "utf-8",
+            possible_values = ENCODINGS,
+            case_insensitive = true,
+            help = "encoding to convert to"
+        )]
+        pub to: bricks::encoding::Encoding,
+
+        #[structopt(
+            short = "f",
+            long = "from",
+            possible_values = ENCODINGS,
+            case_insensitive = true,
+            help = "encoding to convert from [default: detected]"
+        )]
+        pub from: Option<bricks::encoding::Encoding>,
+
+        #[structopt(
+            short = "b",
+            long = "bom",
+            help = "start with a byte order mark, otherwise byte order marks are stripped"
+        )]
+        pub bom: bool,
+
+        #[structopt(
+            short = "c",
+            long = "check",
+            help = "check the files are encoded as given by --to and --bom instead of converting them"
+        )]
+        pub check: bool,
+
+        #[structopt(
+            short = "d",
+            long = "detect",
+            conflicts_with = "check",
+            help = "print the detected encoding of the files"
+        )]
+        pub detect: bool,
+
+        #[structopt(
+            short = "i",
+            long = "in-place",
+            conflicts_with_all = &["check", "detect"],
+            help = "rewrite the files instead of writing the result to stdout"
+        )]
+        pub in_place: bool,
+
+        #[structopt(flatten)]
+        pub filter: Filter,
+    }
+}
+
+fn main() -> Result<()> {
+    setup_panic!();
+    let config = Encoding::from_args();
+    let (from, to, bom) = (config.from, config.to, config.bom);
+    if bom && to.bom().is_empty() {
+        eprintln!("{} has no byte order mark", to);
+        std::process::exit(2);
+    }
+    let detect = |name: &str, reader: &mut dyn Read, writer: &mut dyn Write| {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        writeln!(writer, "{}: {}", name, bricks::encoding::detect(&bytes))?;
+        Ok(0)
+    };
+    let report = |name: &str, reader: &mut dyn Read, writer: &mut dyn Write| {
+        FileReporter::new(name).report_encoding(reader, writer, to, bom)
+    };
+    let convert = |mut reader: &mut dyn Read, mut writer: &mut dyn Write| {
+        bricks::transformations::convert_encoding(&mut reader, &mut writer, from, to, bom)
+    };
+    let reported_issues = if config.paths.is_empty() {
+        let mut reader = bricks::create_reader("stdin")?;
+        bricks::process(&mut reader, &mut io::stdout(), |reader, writer| {
+            match (config.detect, config.check) {
+                (true, _) => detect("stdin", reader, writer),
+                (_, true) => report("stdin", reader, writer),
+                _ => convert(reader, writer).map(|_| 0),
+            }
+        })?
+    } else {
+        let files = config.filter.walker(&config.paths).utf16(true).files()?;
+        if config.in_place {
+            let preserve = Preserve {
+                permissions: true,
+                times: false,
+            };
+            bricks::walk::rewrite(&files, preserve, |reader, writer| convert(reader, writer))?;
+            0
+        } else {
+            bricks::walk::process(&files, &mut io::stdout(), |path, reader, writer| {
+                let name = path.display().to_string();
+                match (config.detect, config.check) {
+                    (true, _) => detect(&name, reader, writer),
+                    (_, true) => report(&name, reader, writer),
+                    _ => convert(reader, writer).map(|_| 0),
+                }
+            })?
+        }
+    };
+    match reported_issues {
+        0 => std::process::exit(0),
+        _ => std::process::exit(1),
+    }
+}
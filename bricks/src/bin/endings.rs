@@ -0,0 +1,119 @@
+#[macro_use]
+extern crate human_panic;
+extern crate bricks;
+extern crate structopt;
+
+use anyhow::Result;
+use bricks::inplace::Preserve;
+use bricks::reporter::FileReporter;
+use cli::Endings;
+use std::io::{self, Read, Write};
+use structopt::StructOpt;
+
+mod cli {
+
+    use bricks::cli::Filter;
+    use bricks::endings::Ending;
+    use std::path::PathBuf;
+
+    #[derive(structopt::StructOpt, Debug)]
+    #[structopt(about = "Convert line endings and end files with exactly one newline")]
+    #[structopt(setting = structopt::clap::AppSettings::ColoredHelp)]
+    pub struct Endings {
+        #[structopt(
+            name = "paths",
+            help = "files or directories to process, if none is specified stdin will be processed",
+            parse(from_os_str)
+        )]
+        pub paths: Vec<PathBuf>,
+
+        #[structopt(
+            short = "e",
+            long = "ending",
+            possible_values = &["lf", "crlf", "cr"],
+            case_insensitive = true,
+            help = "line ending to use [default: the one used by most lines of a file]"
+        )]
+        pub ending: Option<Ending>,
+
+        #[structopt(
+            short = "k",
+            long = "keep-final-newlines",
+            help = "don't enforce exactly one newline at the end of the files"
+        )]
+        pub keep_final_newlines: bool,
+
+        #[structopt(
+            short = "c",
+            long = "check",
+            help = "check the files for mixed line endings instead of converting them"
+        )]
+        pub check: bool,
+
+        #[structopt(
+            short = "i",
+            long = "in-place",
+            conflicts_with = "check",
+            help = "rewrite the files instead of writing the result to stdout"
+        )]
+        pub in_place: bool,
+
+        #[structopt(flatten)]
+        pub filter: Filter,
+    }
+}
+
+fn main() -> Result<()> {
+    setup_panic!();
+    let config = Endings::from_args();
+    let ending = config.ending;
+    let final_newline = !config.keep_final_newlines;
+    let report = |name: &str, reader: &mut dyn Read, writer: &mut dyn Write| {
+        FileReporter::new(name).report_line_endings(reader, writer, ending, final_newline)
+    };
+    let convert = |mut reader: &mut dyn Read, mut writer: &mut dyn Write| {
+        bricks::transformations::convert_line_endings(
+            &mut reader,
+            &mut writer,
+            ending,
+            final_newline,
+        )
+    };
+    let reported_issues = if config.paths.is_empty() {
+        let mut reader = bricks::create_reader("stdin")?;
+        match config.check {
+            true => bricks::process(&mut reader, &mut io::stdout(), |reader, writer| {
+                report("stdin", reader, writer)
+            })?,
+            false => {
+                bricks::process(&mut reader, &mut io::stdout(), |reader, writer| {
+                    convert(reader, writer)
+                })?;
+                0
+            }
+        }
+    } else {
+        let files = config.filter.walker(&config.paths).files()?;
+        if config.check {
+            bricks::walk::process(&files, &mut io::stdout(), |path, reader, writer| {
+                report(&path.display().to_string(), reader, writer)
+            })?
+        } else if config.in_place {
+            let preserve = Preserve {
+                permissions: true,
+                times: false,
+            };
+            bricks::walk::rewrite(&files, preserve, |reader, writer| convert(reader, writer))?;
+            0
+        } else {
+            bricks::walk::process(&files, &mut io::stdout(), |_, reader, writer| {
+                convert(reader, writer)
+            })?;
+            0
+        }
+    };
+    match reported_issues {
+        0 => std::process::exit(0),
+        _ => std::process::exit(1),
+    }
+}
@@ -0,0 +1,116 @@
+#[macro_use]
+extern crate human_panic;
+extern crate bricks;
+extern crate structopt;
+
+use anyhow::{anyhow, Result};
+use bricks::inplace::Preserve;
+use cli::Inplace;
+use std::path::Path;
+use std::process::{Command, ExitStatus, Stdio};
+use structopt::StructOpt;
+
+mod cli {
+
+    use bricks::cli::Filter;
+    use std::path::PathBuf;
+
+    #[derive(structopt::StructOpt, Debug)]
+    #[structopt(
+        about = "Replace files with the output of a command reading them, e.g. inplace *.rs -- trailing"
+    )]
+    #[structopt(setting = structopt::clap::AppSettings::ColoredHelp)]
+    pub struct Inplace {
+        #[structopt(
+            name = "files",
+            required = true,
+            help = "files or directories to process, every file is passed to the command on stdin",
+            parse(from_os_str)
+        )]
+        pub files: Vec<PathBuf>,
+
+        #[structopt(
+            name = "command",
+            last = true,
+            required = true,
+            help = "command (and its arguments) writing the new content to stdout"
+        )]
+        pub command: Vec<String>,
+
+        #[structopt(
+            short = "p",
+            long = "preserve",
+            help = "keep the permissions and times of the original files"
+        )]
+        pub preserve: bool,
+
+        #[structopt(
+            long = "preserve-permissions",
+            help = "keep the permissions of the files"
+        )]
+        pub preserve_permissions: bool,
+
+        #[structopt(
+            long = "preserve-times",
+            help = "keep the modification and access times of the files"
+        )]
+        pub preserve_times: bool,
+
+        #[structopt(flatten)]
+        pub filter: Filter,
+    }
+}
+
+/// Runs `command` with `path` as stdin and replaces it with the output if the command succeeds.
+fn rewrite(path: &Path, command: &[String], preserve: Preserve) -> Result<ExitStatus> {
+    let mut status = None;
+    let result = bricks::inplace::apply(path, preserve, |reader, writer| {
+        let mut child = Command::new(&command[0])
+            .args(&command[1..])
+            .stdin(Stdio::from(reader.try_clone()?))
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let mut stdout = child.stdout.take().expect("stdout is piped");
+        let copied = std::io::copy(&mut stdout, writer)?;
+        let exit_status = child.wait()?;
+        status = Some(exit_status);
+        match exit_status.success() {
+            true => Ok(copied as usize),
+            false => Err(std::io::Error::other(format!(
+                "{} {}",
+                command[0], exit_status
+            ))),
+        }
+    });
+    match (result, status) {
+        (Ok(_), Some(status)) => Ok(status),
+        (Err(_), Some(status)) if !status.success() => Ok(status),
+        (Err(e), _) => Err(anyhow!("Can't rewrite \"{}\", {}", path.display(), e)),
+        (Ok(_), None) => unreachable!("the command always runs before the file is replaced"),
+    }
+}
+
+fn main() -> Result<()> {
+    setup_panic!();
+    let config = Inplace::from_args();
+    let preserve = Preserve {
+        permissions: config.preserve || config.preserve_permissions,
+        times: config.preserve || config.preserve_times,
+    };
+    let files = config.filter.walker(&config.files).files()?;
+    let statuses = bricks::walk::parallel(&files, |path| rewrite(path, &config.command, preserve));
+    let mut exit_code = 0;
+    for (path, status) in files.iter().zip(statuses) {
+        let status = status?;
+        if !status.success() {
+            eprintln!(
+                "{} left untouched, {} {}",
+                path.display(),
+                config.command[0],
+                status
+            );
+            exit_code = status.code().unwrap_or(1);
+        }
+    }
+    std::process::exit(exit_code);
+}
@@ -0,0 +1,129 @@
+#[macro_use]
+extern crate human_panic;
+extern crate bricks;
+extern crate structopt;
+
+use anyhow::Result;
+use bricks::indentation::Style;
+use bricks::inplace::Preserve;
+use bricks::reporter::FileReporter;
+use cli::Mixed;
+use std::io::{self, Read, Write};
+use structopt::StructOpt;
+
+mod cli {
+
+    use bricks::cli::Filter;
+    use std::path::PathBuf;
+
+    #[derive(structopt::StructOpt, Debug)]
+    #[structopt(about = "Check for lines mixing tabs and spaces within their indentation")]
+    #[structopt(setting = structopt::clap::AppSettings::ColoredHelp)]
+    pub struct Mixed {
+        #[structopt(
+            name = "paths",
+            help = "files or directories to process, if none is specified stdin will be processed",
+            parse(from_os_str)
+        )]
+        pub paths: Vec<PathBuf>,
+
+        #[structopt(
+            short = "f",
+            long = "fix",
+            help = "re-indent all lines in the style used by most of them instead of checking"
+        )]
+        pub fix: bool,
+
+        #[structopt(
+            short = "t",
+            long = "tabs",
+            requires = "fix",
+            help = "re-indent with tabs, regardless of the style used by the file"
+        )]
+        pub tabs: bool,
+
+        #[structopt(
+            short = "s",
+            long = "spaces",
+            requires = "fix",
+            conflicts_with = "tabs",
+            help = "re-indent with spaces, regardless of the style used by the file"
+        )]
+        pub spaces: bool,
+
+        #[structopt(
+            short = "w",
+            long = "width",
+            requires = "fix",
+            help = "columns of one indentation level [default: inferred from the file or 4]"
+        )]
+        pub width: Option<usize>,
+
+        #[structopt(
+            short = "i",
+            long = "in-place",
+            requires = "fix",
+            help = "rewrite the files instead of writing the result to stdout"
+        )]
+        pub in_place: bool,
+
+        #[structopt(flatten)]
+        pub filter: Filter,
+    }
+}
+
+fn main() -> Result<()> {
+    setup_panic!();
+    let config = Mixed::from_args();
+    if config.width == Some(0) {
+        eprintln!("The width must be at least 1");
+        std::process::exit(2);
+    }
+    let style = match (config.tabs, config.spaces) {
+        (true, _) => Some(Style::Tabs),
+        (_, true) => Some(Style::Spaces),
+        _ => None,
+    };
+    let width = config.width;
+    let normalize = |mut reader: &mut dyn Read, mut writer: &mut dyn Write| {
+        bricks::transformations::normalize_indentation(&mut reader, &mut writer, style, width)
+    };
+    let reported_issues = if config.paths.is_empty() {
+        let mut reader = bricks::create_reader("stdin")?;
+        match config.fix {
+            false => bricks::process(&mut reader, &mut io::stdout(), |reader, writer| {
+                FileReporter::new("stdin").report_mixed_indentation(reader, writer)
+            })?,
+            true => {
+                bricks::process(&mut reader, &mut io::stdout(), |reader, writer| {
+                    normalize(reader, writer)
+                })?;
+                0
+            }
+        }
+    } else {
+        let files = config.filter.walker(&config.paths).files()?;
+        if !config.fix {
+            bricks::walk::process(&files, &mut io::stdout(), |path, reader, writer| {
+                FileReporter::new(&path.display().to_string())
+                    .report_mixed_indentation(reader, writer)
+            })?
+        } else if config.in_place {
+            let preserve = Preserve {
+                permissions: true,
+                times: false,
+            };
+            bricks::walk::rewrite(&files, preserve, |reader, writer| normalize(reader, writer))?;
+            0
+        } else {
+            bricks::walk::process(&files, &mut io::stdout(), |_, reader, writer| {
+                normalize(reader, writer)
+            })?;
+            0
+        }
+    };
+    match reported_issues {
+        0 => std::process::exit(0),
+        _ => std::process::exit(1),
+    }
+}
@@ -0,0 +1,170 @@
+use bricks::retry::{Backoff, Condition, Retry};
+use bricks::supervisor::{exit_code, parse_duration, Supervisor};
+use human_panic::setup_panic;
+use regex::bytes::Regex;
+
+use std::process::exit;
+use std::str::FromStr;
+
+mod cli {
+
+    use structopt::clap::{App, AppSettings, Arg};
+
+    pub fn create_arg_parser<'a, 'b>() -> App<'a, 'b> {
+        App::new("retry")
+            .about("Rerun a command until it succeeds or runs out of attempts.")
+            .usage("retry [OPTIONS] <COMMAND> [ARGS]...")
+            .setting(AppSettings::AllowExternalSubcommands)
+            .setting(AppSettings::ArgRequiredElseHelp)
+            .setting(AppSettings::UnifiedHelpMessage)
+            .arg(
+                Arg::with_name("attempts")
+                    .short("n")
+                    .long("attempts")
+                    .default_value("3")
+                    .takes_value(true)
+                    .help("maximum number of attempts, including the first one"),
+            )
+            .arg(
+                Arg::with_name("backoff")
+                    .short("b")
+                    .long("backoff")
+                    .default_value("exponential")
+                    .possible_values(&["fixed", "exponential", "jittered"])
+                    .takes_value(true)
+                    .help("how the delay between attempts grows"),
+            )
+            .arg(
+                Arg::with_name("delay")
+                    .short("w")
+                    .long("delay")
+                    .default_value("1s")
+                    .takes_value(true)
+                    .help("delay after the first failed attempt, e.g. 500ms, 1.5s or 2m"),
+            )
+            .arg(
+                Arg::with_name("max-delay")
+                    .short("m")
+                    .long("max-delay")
+                    .default_value("1m")
+                    .takes_value(true)
+                    .help("maximum delay between attempts"),
+            )
+            .arg(
+                Arg::with_name("on-exit")
+                    .short("e")
+                    .long("on-exit")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("only retry attempts exiting with this code (124 if they time out)"),
+            )
+            .arg(
+                Arg::with_name("on-output")
+                    .short("p")
+                    .long("on-output")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("only retry attempts whose stdout or stderr matches this regex"),
+            )
+            .arg(
+                Arg::with_name("timeout")
+                    .short("t")
+                    .long("timeout")
+                    .default_value("0")
+                    .takes_value(true)
+                    .help("time every attempt has (0 disables the timeout)"),
+            )
+            .arg(
+                Arg::with_name("kill-after")
+                    .short("k")
+                    .long("kill-after")
+                    .default_value("5s")
+                    .takes_value(true)
+                    .help("time an attempt has to exit after SIGTERM before it is killed"),
+            )
+    }
+}
+
+fn parse<T, E>(
+    matches: &structopt::clap::ArgMatches,
+    name: &str,
+    parse: fn(&str) -> Result<T, E>,
+) -> Vec<T>
+where
+    E: std::fmt::Display,
+{
+    matches
+        .values_of(name)
+        .into_iter()
+        .flatten()
+        .map(|value| {
+            parse(value).unwrap_or_else(|e| {
+                eprintln!("Invalid value \"{}\" for --{}, {}", value, name, e);
+                exit(exit_code::FAILED)
+            })
+        })
+        .collect()
+}
+
+fn value<T, E>(
+    matches: &structopt::clap::ArgMatches,
+    name: &str,
+    parse: fn(&str) -> Result<T, E>,
+) -> T
+where
+    E: std::fmt::Display,
+{
+    self::parse(matches, name, parse).remove(0)
+}
+
+#[tokio::main]
+async fn main() {
+    setup_panic!();
+    let matches = cli::create_arg_parser().get_matches();
+    let mut supervisor = match matches.subcommand() {
+        (name, Some(matches)) => {
+            let args: Vec<&str> = matches
+                .values_of("")
+                .map(|v| v.collect())
+                .unwrap_or_default();
+            Supervisor::new(name, &args)
+        }
+        _ => {
+            eprintln!("{}", matches.usage());
+            exit(exit_code::FAILED)
+        }
+    };
+    supervisor
+        .timeout(Some(value(&matches, "timeout", parse_duration)))
+        .kill_after(Some(value(&matches, "kill-after", parse_duration)));
+    let mut retry = Retry::new(supervisor);
+    retry
+        .attempts(value(&matches, "attempts", u32::from_str))
+        .backoff(Backoff {
+            strategy: value(&matches, "backoff", FromStr::from_str),
+            delay: value(&matches, "delay", parse_duration),
+            max: value(&matches, "max-delay", parse_duration),
+        })
+        .condition(Condition {
+            codes: parse(&matches, "on-exit", i32::from_str),
+            patterns: parse(&matches, "on-output", Regex::new),
+        });
+
+    let retried = |attempt, outcome: &bricks::supervisor::Outcome, delay| {
+        eprintln!(
+            "Attempt {} failed with exit code {}, retrying in {:?}",
+            attempt,
+            outcome.exit_code(false),
+            delay
+        );
+    };
+    match retry.run(retried).await {
+        Err(e) => {
+            eprintln!("Error while executing command, details: {}", e);
+            exit(exit_code::of_error(&e));
+        }
+        Ok(outcome) => exit(outcome.exit_code(false)),
+    }
+}
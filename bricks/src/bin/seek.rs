@@ -0,0 +1,87 @@
+#[macro_use]
+extern crate human_panic;
+extern crate bricks;
+extern crate structopt;
+
+use anyhow::Result;
+use bricks::selection::{Selection, Unit};
+use cli::Seek;
+use std::io::{BufWriter, Write};
+use structopt::StructOpt;
+
+mod cli {
+
+    use bricks::cli::{Input, Output};
+    use bricks::selection::Range;
+
+    #[derive(structopt::StructOpt, Debug)]
+    #[structopt(about = "Select parts of a file, similar to head and tail")]
+    #[structopt(setting = structopt::clap::AppSettings::ColoredHelp)]
+    pub struct Seek {
+        #[structopt(name = "input", default_value = "-")]
+        #[structopt(help = "file to select from, - selects stdin")]
+        pub input: Input,
+
+        #[structopt(name = "output", default_value = "-")]
+        #[structopt(help = "where the selected part shall be written to, - selects stdout")]
+        pub output: Output,
+
+        #[structopt(
+            short = "H",
+            long = "head",
+            conflicts_with_all = &["tail", "skip", "range"],
+            help = "select the first n lines (bytes)"
+        )]
+        pub head: Option<usize>,
+
+        #[structopt(
+            short = "T",
+            long = "tail",
+            conflicts_with_all = &["skip", "range"],
+            help = "select the last n lines (bytes)"
+        )]
+        pub tail: Option<usize>,
+
+        #[structopt(
+            short = "s",
+            long = "skip",
+            conflicts_with = "range",
+            help = "skip the first n lines (bytes), i.e. start at line n + 1 (byte offset n)"
+        )]
+        pub skip: Option<usize>,
+
+        #[structopt(
+            short = "r",
+            long = "range",
+            help = "select lines (bytes) start:end (e.g. 10:20), counted from 0 like offsets, the end is excluded"
+        )]
+        pub range: Option<Range>,
+
+        #[structopt(short = "b", long = "bytes", help = "count bytes instead of lines")]
+        pub bytes: bool,
+    }
+}
+
+fn main() -> Result<()> {
+    setup_panic!();
+    let config = Seek::from_args();
+    let unit = if config.bytes {
+        Unit::Bytes
+    } else {
+        Unit::Lines
+    };
+    let selection = match (config.head, config.tail, config.skip, config.range) {
+        (Some(n), _, _, _) => Selection::Head(n),
+        (_, Some(n), _, _) => Selection::Tail(n),
+        (_, _, Some(n), _) => Selection::Skip(n),
+        (_, _, _, Some(range)) => Selection::Range(range),
+        _ => Selection::Skip(0),
+    };
+    let mut input = config.input;
+    let mut output = BufWriter::new(config.output);
+    bricks::process(&mut input, &mut output, |reader, writer| {
+        bricks::selection::select(reader, writer, selection, unit)
+    })?;
+    output.flush()?;
+    Ok(())
+}
@@ -1,25 +1,30 @@
+#[macro_use]
+extern crate human_panic;
 extern crate bricks;
 extern crate structopt;
 
+use anyhow::Result;
+use bricks::inplace::Preserve;
+use bricks::reporter::FileReporter;
 use cli::Spaces;
-use std::io;
-use std::path::PathBuf;
+use std::io::{self, Read, Write};
 use structopt::StructOpt;
 
 mod cli {
 
-    use super::*;
+    use bricks::cli::Filter;
+    use std::path::PathBuf;
 
     #[derive(structopt::StructOpt, Debug)]
     #[structopt(about = "Replace tabs with spaces")]
     #[structopt(setting = structopt::clap::AppSettings::ColoredHelp)]
     pub struct Spaces {
         #[structopt(
-            name = "file",
-            help = "file to process, if none is specified stdin will be processed",
+            name = "paths",
+            help = "files or directories to process, if none is specified stdin will be processed",
             parse(from_os_str)
         )]
-        pub file: Option<PathBuf>,
+        pub paths: Vec<PathBuf>,
 
         #[structopt(
             short = "n",
@@ -28,18 +33,86 @@ mod cli {
             help = "of spaces which shall be used to replace one tab"
         )]
         pub number_of_spaces: usize,
-    }
 
+        #[structopt(
+            short = "c",
+            long = "check",
+            help = "check the file for tabs instead of replacing them"
+        )]
+        pub check: bool,
+
+        #[structopt(
+            short = "u",
+            long = "unexpand",
+            conflicts_with = "check",
+            help = "replace the spaces used for indentation with tabs instead"
+        )]
+        pub unexpand: bool,
+
+        #[structopt(
+            short = "i",
+            long = "in-place",
+            conflicts_with = "check",
+            help = "rewrite the files instead of writing the result to stdout"
+        )]
+        pub in_place: bool,
+
+        #[structopt(flatten)]
+        pub filter: Filter,
+    }
 }
 
-fn main() -> io::Result<()> {
+fn main() -> Result<()> {
+    setup_panic!();
     let config = Spaces::from_args();
-    let _input_file: String = if let Some(path) = config.file {
-        path.to_str().unwrap().to_string()
+    if config.number_of_spaces == 0 {
+        eprintln!("The number of spaces must be at least 1");
+        std::process::exit(2);
+    }
+    let tab_width = config.number_of_spaces;
+    let unexpand = config.unexpand;
+    let replace = |mut reader: &mut dyn Read, mut writer: &mut dyn Write| {
+        if unexpand {
+            bricks::transformations::unexpand_tabs(&mut reader, &mut writer, tab_width)
+        } else {
+            bricks::transformations::expand_tabs(&mut reader, &mut writer, tab_width)
+        }
+    };
+    let reported_issues = if config.paths.is_empty() {
+        let mut reader = bricks::create_reader("stdin")?;
+        match config.check {
+            true => bricks::process(&mut reader, &mut io::stdout(), |reader, writer| {
+                FileReporter::new("stdin").report_tabs(reader, writer)
+            })?,
+            false => {
+                bricks::process(&mut reader, &mut io::stdout(), |reader, writer| {
+                    replace(reader, writer)
+                })?;
+                0
+            }
+        }
     } else {
-        String::from("stdin")
+        let files = config.filter.walker(&config.paths).files()?;
+        if config.check {
+            bricks::walk::process(&files, &mut io::stdout(), |path, reader, writer| {
+                FileReporter::new(&path.display().to_string()).report_tabs(reader, writer)
+            })?
+        } else if config.in_place {
+            let preserve = Preserve {
+                permissions: true,
+                times: false,
+            };
+            bricks::walk::rewrite(&files, preserve, |reader, writer| replace(reader, writer))?;
+            0
+        } else {
+            bricks::walk::process(&files, &mut io::stdout(), |_, reader, writer| {
+                replace(reader, writer)
+            })?;
+            0
+        }
     };
-    // TODO: Add actual implementation/functionality
-    // let mut reader = bricks::create_reader(&input_file)?;
-    std::process::exit(1);
+    match reported_issues {
+        0 => std::process::exit(0),
+        _ => std::process::exit(1),
+    }
 }
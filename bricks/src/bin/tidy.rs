@@ -0,0 +1,94 @@
+#[macro_use]
+extern crate human_panic;
+extern crate bricks;
+extern crate structopt;
+
+use anyhow::Result;
+use bricks::editorconfig::Resolver;
+use bricks::rules::Runner;
+use cli::Tidy;
+use std::io::Write;
+use structopt::StructOpt;
+
+mod cli {
+
+    use bricks::cli::Filter;
+    use bricks::rules::Format;
+    use std::path::PathBuf;
+
+    #[derive(structopt::StructOpt, Debug)]
+    #[structopt(about = "Fix or check files with the rules of several bricks")]
+    #[structopt(setting = structopt::clap::AppSettings::ColoredHelp)]
+    pub struct Tidy {
+        #[structopt(
+            name = "paths",
+            default_value = ".",
+            help = "files or directories to process",
+            parse(from_os_str)
+        )]
+        pub paths: Vec<PathBuf>,
+
+        #[structopt(
+            short = "c",
+            long = "check",
+            help = "check the files instead of fixing them, exits with 1 if there are issues"
+        )]
+        pub check: bool,
+
+        #[structopt(
+            short = "r",
+            long = "rule",
+            help = "rule to apply, can be given multiple times [default: all rules]"
+        )]
+        pub rules: Vec<String>,
+
+        #[structopt(
+            short = "f",
+            long = "format",
+            default_value = "human",
+            possible_values = &["human", "json", "sarif", "github"],
+            help = "how issues are reported"
+        )]
+        pub format: Format,
+
+        #[structopt(
+            short = "e",
+            long = "editorconfig",
+            conflicts_with = "rules",
+            help = "apply the rules configured by the .editorconfig files of every file instead"
+        )]
+        pub editorconfig: bool,
+
+        #[structopt(short = "l", long = "list", help = "list the available rules")]
+        pub list: bool,
+
+        #[structopt(flatten)]
+        pub filter: Filter,
+    }
+}
+
+fn main() -> Result<()> {
+    setup_panic!();
+    let config = Tidy::from_args();
+    let mut runner = match (config.editorconfig, config.rules.is_empty()) {
+        (true, _) => Runner::new(bricks::editorconfig::available()),
+        (false, true) => Runner::new(bricks::rules::all()),
+        (false, false) => Runner::select(&config.rules)?,
+    };
+    if config.editorconfig {
+        runner.editorconfig(Resolver::new());
+    }
+    let stdout = std::io::stdout();
+    let mut stdout = stdout.lock();
+    if config.list {
+        for rule in runner.rules() {
+            writeln!(stdout, "{:<24}{}", rule.id(), rule.description())?;
+        }
+        return Ok(());
+    }
+    let files = config.filter.walker(&config.paths).utf16(true).files()?;
+    let report = runner.run(&files, !config.check);
+    report.write(config.format, runner.rules(), &mut stdout)?;
+    stdout.flush()?;
+    std::process::exit(report.exit_code());
+}
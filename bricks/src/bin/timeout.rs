@@ -1,13 +1,10 @@
-use clap::value_t;
+use anyhow::Error;
+use bricks::supervisor::{exit_code, parse_duration, parse_size, Limits, Supervisor};
 use human_panic::setup_panic;
-use tokio::time::timeout;
 
+use std::fs::File;
+use std::io::Write;
 use std::process::exit;
-use std::time::Duration;
-
-pub const SUCCESS: i32 = 0;
-pub const TIMEOUT: i32 = 1;
-pub const ERROR: i32 = 2;
 
 mod cli {
 
@@ -26,58 +23,123 @@ mod cli {
                     .long("duration")
                     .default_value("5")
                     .takes_value(true)
-                    .help("specify the duration in seconds"),
+                    .help("time the command has, e.g. 10, 1.5s, 2m or 1h (0 disables the timeout)"),
+            )
+            .arg(
+                Arg::with_name("kill-after")
+                    .short("k")
+                    .long("kill-after")
+                    .default_value("5s")
+                    .takes_value(true)
+                    .help("time the command has to exit after SIGTERM before it is killed"),
+            )
+            .arg(
+                Arg::with_name("preserve-status")
+                    .long("preserve-status")
+                    .help("exit with the status of the command, even if it timed out"),
+            )
+            .arg(
+                Arg::with_name("foreground").long("foreground").help(
+                    "let the command read from the terminal and get its signals (e.g. Ctrl-C)",
+                ),
             )
+            .arg(
+                Arg::with_name("memory")
+                    .long("memory")
+                    .takes_value(true)
+                    .help("maximum address space of the command, e.g. 512M or 2G"),
+            )
+            .arg(
+                Arg::with_name("cpu")
+                    .long("cpu")
+                    .takes_value(true)
+                    .help("CPU time the command has before it is killed, e.g. 30s"),
+            )
+            .arg(
+                Arg::with_name("files")
+                    .long("files")
+                    .takes_value(true)
+                    .help("maximum number of files the command can open"),
+            )
+            .arg(
+                Arg::with_name("summary")
+                    .short("s")
+                    .long("summary")
+                    .takes_value(true)
+                    .help("write a JSON summary of the exit status, wall and CPU time and peak memory usage to this file (- for stderr)"),
+            )
+    }
+}
+
+fn value<T>(
+    matches: &structopt::clap::ArgMatches,
+    name: &str,
+    parse: fn(&str) -> Result<T, Error>,
+) -> Option<T> {
+    matches.value_of(name).map(|value| {
+        parse(value).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            exit(exit_code::FAILED)
+        })
+    })
+}
+
+fn write_summary(path: &str, summary: &serde_json::Value) -> Result<(), Error> {
+    let summary = serde_json::to_string_pretty(summary)?;
+    match path {
+        "-" => eprintln!("{}", summary),
+        _ => writeln!(File::create(path)?, "{}", summary)?,
     }
+    Ok(())
 }
 
 #[tokio::main]
 async fn main() {
     setup_panic!();
     let matches = cli::create_arg_parser().get_matches();
-    let duration = value_t!(matches.value_of("duration"), u64).unwrap_or_else(|e| e.exit());
-    let mut command = match matches.subcommand() {
+    let timeout = value(&matches, "duration", parse_duration);
+    let kill_after = value(&matches, "kill-after", parse_duration);
+    let limits = Limits {
+        memory: value(&matches, "memory", parse_size),
+        cpu: value(&matches, "cpu", parse_duration),
+        files: value(&matches, "files", |v| Ok(v.parse()?)),
+    };
+    let mut supervisor = match matches.subcommand() {
         (name, Some(matches)) => {
-            let mut c = tokio::process::Command::new(name);
-            let args = matches
+            let args: Vec<&str> = matches
                 .values_of("")
                 .map(|v| v.collect())
-                .unwrap_or(Vec::new());
-            for argument in args {
-                c.arg(argument);
-            }
-            c
+                .unwrap_or_default();
+            Supervisor::new(name, &args)
         }
         _ => {
             eprintln!("{}", matches.usage());
-            exit(ERROR)
+            exit(exit_code::FAILED)
         }
     };
+    supervisor
+        .timeout(timeout)
+        .kill_after(kill_after)
+        .foreground(matches.is_present("foreground"))
+        .limits(limits);
 
-    match command.spawn() {
+    match supervisor.run().await {
         Err(e) => {
-            eprintln!("Error while constructing command, details:\n {}", e);
+            eprintln!("Error while executing command, details: {}", e);
+            exit(exit_code::of_error(&e));
         }
-        Ok(child) => {
-            let timed_command = timeout(Duration::from_secs(duration), child.wait_with_output());
-            timed_command
-                .await
-                .map(|r| {
-                    // TODO NiCo: find a good alterantive for the unwrap
-                    r.map(|output| {
-                        eprint!("{}", std::str::from_utf8(&output.stderr).unwrap());
-                        print!("{}", std::str::from_utf8(&output.stdout).unwrap());
-                        exit(SUCCESS);
-                    })
-                    .map_err(|e| {
-                        eprintln!("Error while executing command, details: {}", e);
-                        exit(ERROR);
-                    })
-                })
-                .map_err(|_e| {
-                    eprintln!("Timeout, command did not finish in time");
-                    exit(TIMEOUT);
-                });
+        Ok(outcome) => {
+            if outcome.timed_out {
+                eprintln!("Timeout, command did not finish in time");
+            }
+            if let Some(path) = matches.value_of("summary") {
+                let summary = supervisor.summary(&outcome);
+                if let Err(e) = write_summary(path, &summary) {
+                    eprintln!("Can't write the summary to \"{}\", {}", path, e);
+                    exit(exit_code::FAILED);
+                }
+            }
+            exit(outcome.exit_code(matches.is_present("preserve-status")));
         }
     }
 }
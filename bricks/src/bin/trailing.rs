@@ -4,25 +4,29 @@ extern crate human_panic;
 extern crate bricks;
 extern crate structopt;
 
+use anyhow::Result;
+use bricks::inplace::Preserve;
+use bricks::reporter::FileReporter;
+use bricks::transformations::remove_trailing_whitespaces;
 use cli::Trailing;
 use std::io;
-use std::path::PathBuf;
 use structopt::StructOpt;
 
 mod cli {
 
-    use super::*;
+    use bricks::cli::Filter;
+    use std::path::PathBuf;
 
     #[derive(structopt::StructOpt, Debug)]
     #[structopt(about = "Remove trailing whitespaces")]
     #[structopt(setting = structopt::clap::AppSettings::ColoredHelp)]
     pub struct Trailing {
         #[structopt(
-            name = "file",
-            help = "file to process, if none is specified stdin will be processed",
+            name = "paths",
+            help = "files or directories to process, if none is specified stdin will be processed",
             parse(from_os_str)
         )]
-        pub file: Option<PathBuf>,
+        pub paths: Vec<PathBuf>,
 
         #[structopt(
             short = "c",
@@ -30,35 +34,57 @@ mod cli {
             help = "check the file for trailing ws instead of fixing it"
         )]
         pub check: bool,
-    }
 
+        #[structopt(
+            short = "i",
+            long = "in-place",
+            conflicts_with = "check",
+            help = "rewrite the files instead of writing the result to stdout"
+        )]
+        pub in_place: bool,
+
+        #[structopt(flatten)]
+        pub filter: Filter,
+    }
 }
 
-fn main() -> io::Result<()> {
+fn main() -> Result<()> {
     setup_panic!();
     let config = Trailing::from_args();
-    let input_file = if let Some(path) = config.file {
-        path.to_str().unwrap().to_string()
+    let reported_issues = if config.paths.is_empty() {
+        let mut reader = bricks::create_reader("stdin")?;
+        match config.check {
+            true => bricks::process(&mut reader, &mut io::stdout(), |reader, writer| {
+                FileReporter::new("stdin").report_trailing_whitespaces(reader, writer)
+            })?,
+            false => {
+                bricks::process(&mut reader, &mut io::stdout(), remove_trailing_whitespaces)?;
+                0
+            }
+        }
     } else {
-        String::from("stdin")
-    };
-    let mut reader = bricks::create_reader(&input_file)?;
-    if config.check {
-        let file_reporter = bricks::reporter::FileReporter::new(&input_file);
-        let reported_issues =
-            bricks::process(&mut reader, &mut io::stdout(), |mut reader, mut writer| {
-                file_reporter.report_trailing_whitespaces(&mut reader, &mut writer)
+        let files = config.filter.walker(&config.paths).files()?;
+        if config.check {
+            bricks::walk::process(&files, &mut io::stdout(), |path, reader, writer| {
+                FileReporter::new(&path.display().to_string())
+                    .report_trailing_whitespaces(reader, writer)
+            })?
+        } else if config.in_place {
+            let preserve = Preserve {
+                permissions: true,
+                times: false,
+            };
+            bricks::walk::rewrite(&files, preserve, remove_trailing_whitespaces)?;
+            0
+        } else {
+            bricks::walk::process(&files, &mut io::stdout(), |_, reader, writer| {
+                remove_trailing_whitespaces(reader, writer)
             })?;
-        match reported_issues {
-            0 => std::process::exit(0),
-            _ => std::process::exit(1),
+            0
         }
-    } else {
-        let _bytes_written = bricks::process(
-            &mut reader,
-            &mut io::stdout(),
-            bricks::transformations::remove_trailing_whitespaces,
-        )?;
-        std::process::exit(0);
+    };
+    match reported_issues {
+        0 => std::process::exit(0),
+        _ => std::process::exit(1),
     }
 }
@@ -0,0 +1,365 @@
+//! Resolves the [EditorConfig](https://editorconfig.org) properties of files and the [Rule]s
+//! enforcing them.
+//!
+//! The `.editorconfig` files in the directory of a file and above apply to it, up to the first
+//! one declaring `root = true`. Closer files and later sections take precedence.
+use crate::endings::Ending;
+use crate::indentation::{Indentation, Style, DEFAULT_WIDTH};
+use crate::rules::{
+    Charset, FinalNewline, LineEndings, MaxLineLength, MixedIndentation, Rule, TrailingWhitespace,
+};
+use anyhow::{anyhow, Error};
+use globset::{GlobBuilder, GlobMatcher};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+pub const FILE_NAME: &str = ".editorconfig";
+
+/// The supported properties of a file, `None` if they are unset.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Properties {
+    pub indent_style: Option<Style>,
+    pub indent_size: Option<usize>,
+    pub tab_width: Option<usize>,
+    pub end_of_line: Option<Ending>,
+    pub trim_trailing_whitespace: Option<bool>,
+    pub insert_final_newline: Option<bool>,
+    pub charset: Option<String>,
+    pub max_line_length: Option<usize>,
+}
+
+impl Properties {
+    /// Interprets the (lower case) pairs of all sections matching a file, invalid values are
+    /// ignored like unset ones.
+    fn from_pairs(pairs: &HashMap<String, String>) -> Self {
+        let value = |key: &str| pairs.get(key).map(String::as_str);
+        let number = |key: &str| value(key).and_then(|v| v.parse().ok()).filter(|n| *n > 0);
+        let boolean = |key: &str| match value(key) {
+            Some("true") => Some(true),
+            Some("false") => Some(false),
+            _ => None,
+        };
+        let indent_style = match value("indent_style") {
+            Some("tab") => Some(Style::Tabs),
+            Some("space") => Some(Style::Spaces),
+            _ => None,
+        };
+        let tab_width = number("tab_width");
+        let indent_size = match value("indent_size") {
+            Some("tab") => tab_width,
+            _ => number("indent_size"),
+        };
+        // Defaults of the specification, tabs are one level of indentation wide.
+        let indent_size = match (indent_size, indent_style) {
+            (None, Some(Style::Tabs)) => tab_width,
+            (indent_size, _) => indent_size,
+        };
+        Properties {
+            indent_style,
+            indent_size,
+            tab_width: tab_width.or(indent_size),
+            end_of_line: value("end_of_line").and_then(|v| v.parse().ok()),
+            trim_trailing_whitespace: boolean("trim_trailing_whitespace"),
+            insert_final_newline: boolean("insert_final_newline"),
+            charset: value("charset").map(str::to_string),
+            max_line_length: number("max_line_length"),
+        }
+    }
+
+    /// Columns between tab stops, [DEFAULT_WIDTH] if neither `tab_width` nor `indent_size`
+    /// tell.
+    pub fn tab_width(&self) -> usize {
+        self.tab_width.unwrap_or(DEFAULT_WIDTH)
+    }
+}
+
+/// The rules enforcing `properties`, in the order their fixes are applied.
+///
+/// `insert_final_newline = false` is not enforced, like most editors it is taken as "don't
+/// care".
+pub fn rules(properties: &Properties) -> Vec<Box<dyn Rule>> {
+    let mut rules: Vec<Box<dyn Rule>> = Vec::new();
+    if let Some(charset) = &properties.charset {
+        rules.push(Box::new(Charset {
+            charset: charset.clone(),
+        }));
+    }
+    if let Some(style) = properties.indent_style {
+        rules.push(Box::new(MixedIndentation {
+            indentation: Some(Indentation {
+                style,
+                width: properties.tab_width(),
+            }),
+        }));
+    }
+    if properties.trim_trailing_whitespace == Some(true) {
+        rules.push(Box::new(TrailingWhitespace));
+    }
+    if let Some(ending) = properties.end_of_line {
+        rules.push(Box::new(LineEndings {
+            ending: Some(ending),
+        }));
+    }
+    if properties.insert_final_newline == Some(true) {
+        rules.push(Box::new(FinalNewline));
+    }
+    if let Some(max) = properties.max_line_length {
+        rules.push(Box::new(MaxLineLength {
+            max,
+            tab_width: properties.tab_width(),
+        }));
+    }
+    rules
+}
+
+/// One rule of every kind [rules] configures, e.g. to describe them.
+pub fn available() -> Vec<Box<dyn Rule>> {
+    rules(&Properties {
+        indent_style: Some(Style::Spaces),
+        end_of_line: Some(Ending::Lf),
+        trim_trailing_whitespace: Some(true),
+        insert_final_newline: Some(true),
+        charset: Some("utf-8".to_string()),
+        max_line_length: Some(80),
+        ..Properties::default()
+    })
+}
+
+/// A parsed `.editorconfig` file.
+#[derive(Debug, Default)]
+struct Config {
+    root: bool,
+    sections: Vec<Section>,
+}
+
+#[derive(Debug)]
+struct Section {
+    /// `None` if the glob is invalid, such sections match no file.
+    glob: Option<GlobMatcher>,
+    pairs: Vec<(String, String)>,
+}
+
+impl Config {
+    fn parse(text: &str) -> Self {
+        let mut config = Config::default();
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if let Some(pattern) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                config.sections.push(Section {
+                    glob: glob(pattern),
+                    pairs: Vec::new(),
+                });
+                continue;
+            }
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim().to_lowercase(), value.trim().to_lowercase()),
+                None => continue,
+            };
+            match config.sections.last_mut() {
+                Some(section) => section.pairs.push((key, value)),
+                None if key == "root" => config.root = value == "true",
+                None => {}
+            }
+        }
+        config
+    }
+}
+
+/// Matches paths relative to the directory of the `.editorconfig` file, patterns without a
+/// `/` match files in any directory below it.
+fn glob(pattern: &str) -> Option<GlobMatcher> {
+    let pattern = match pattern.strip_prefix('/') {
+        Some(pattern) => pattern.to_string(),
+        None if pattern.contains('/') => pattern.to_string(),
+        None => format!("**/{}", pattern),
+    };
+    GlobBuilder::new(&pattern)
+        .literal_separator(true)
+        .backslash_escape(true)
+        .build()
+        .ok()
+        .map(|glob| glob.compile_matcher())
+}
+
+/// Finds the `.editorconfig` files of files, every file is parsed once.
+#[derive(Debug, Default)]
+pub struct Resolver {
+    configs: Mutex<HashMap<PathBuf, Option<Arc<Config>>>>,
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Resolver::default()
+    }
+
+    /// The properties of the file at `path`.
+    pub fn properties(&self, path: &Path) -> Result<Properties, Error> {
+        let path = std::path::absolute(path)?;
+        let mut configs = Vec::new();
+        for directory in path.ancestors().skip(1) {
+            if let Some(config) = self.config(directory)? {
+                let root = config.root;
+                configs.push((directory, config));
+                if root {
+                    break;
+                }
+            }
+        }
+        let mut pairs = HashMap::new();
+        for (directory, config) in configs.iter().rev() {
+            let relative = path.strip_prefix(directory)?;
+            let sections = config
+                .sections
+                .iter()
+                .filter(|s| s.glob.as_ref().is_some_and(|g| g.is_match(relative)));
+            for (key, value) in sections.flat_map(|s| &s.pairs) {
+                match value.as_str() {
+                    "unset" => pairs.remove(key),
+                    _ => pairs.insert(key.clone(), value.clone()),
+                };
+            }
+        }
+        Ok(Properties::from_pairs(&pairs))
+    }
+
+    fn config(&self, directory: &Path) -> Result<Option<Arc<Config>>, Error> {
+        if let Some(config) = self.configs.lock().unwrap().get(directory) {
+            return Ok(config.clone());
+        }
+        let path = directory.join(FILE_NAME);
+        let config = match std::fs::read_to_string(&path) {
+            Ok(text) => Some(Arc::new(Config::parse(&text))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(anyhow!("Can't read \"{}\", {}", path.display(), e)),
+        };
+        self.configs
+            .lock()
+            .unwrap()
+            .insert(directory.to_path_buf(), config.clone());
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree(files: &[(&str, &str)]) -> tempfile::TempDir {
+        let root = tempfile::tempdir().unwrap();
+        for (path, content) in files {
+            let path = root.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn closer_files_and_later_sections_take_precedence() -> anyhow::Result<()> {
+        let tree = tree(&[
+            (
+                ".editorconfig",
+                "root = true\n\n[*]\nindent_style = space\nindent_size = 4\n\
+                     end_of_line = lf\ninsert_final_newline = true\n\n\
+                     # Makefiles need tabs\n[Makefile]\nindent_style = tab\n\n\
+                     [{*.md,*.txt}]\ntrim_trailing_whitespace = false\n\n\
+                     [/docs/*.md]\nmax_line_length = 100\n",
+            ),
+            (
+                "src/.editorconfig",
+                "[*.rs]\nIndent_Size = 2\ntab_width = 8\nend_of_line = unset\n\
+                     charset = UTF-8\n",
+            ),
+        ]);
+        let root = tree.path();
+        let resolver = Resolver::new();
+        let properties = resolver.properties(&root.join("src/nested/main.rs"))?;
+        assert_eq!(
+            properties,
+            Properties {
+                indent_style: Some(Style::Spaces),
+                indent_size: Some(2),
+                tab_width: Some(8),
+                insert_final_newline: Some(true),
+                charset: Some("utf-8".to_string()),
+                ..Properties::default()
+            }
+        );
+        let properties = resolver.properties(&root.join("src/Makefile"))?;
+        assert_eq!(
+            (properties.indent_style, properties.tab_width()),
+            (Some(Style::Tabs), 4)
+        );
+        let properties = resolver.properties(&root.join("docs/index.md"))?;
+        assert_eq!(
+            (
+                properties.trim_trailing_whitespace,
+                properties.max_line_length
+            ),
+            (Some(false), Some(100))
+        );
+        let properties = resolver.properties(&root.join("docs/api/index.md"))?;
+        assert_eq!(properties.max_line_length, None);
+        Ok(())
+    }
+
+    #[test]
+    fn tabs_default_to_the_indent_size() {
+        let pairs = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<HashMap<String, String>>()
+        };
+        let properties =
+            Properties::from_pairs(&pairs(&[("indent_style", "tab"), ("tab_width", "8")]));
+        assert_eq!(properties.indent_size, Some(8));
+        let properties = Properties::from_pairs(&pairs(&[("indent_size", "3")]));
+        assert_eq!(properties.tab_width(), 3);
+        let properties = Properties::from_pairs(&pairs(&[
+            ("indent_size", "tab"),
+            ("max_line_length", "off"),
+            ("end_of_line", "crlf"),
+        ]));
+        assert_eq!(
+            properties,
+            Properties {
+                end_of_line: Some(Ending::CrLf),
+                ..Properties::default()
+            }
+        );
+    }
+
+    #[test]
+    fn rules_enforce_the_properties() {
+        let properties = Properties {
+            indent_style: Some(Style::Tabs),
+            tab_width: Some(4),
+            end_of_line: Some(Ending::CrLf),
+            trim_trailing_whitespace: Some(true),
+            insert_final_newline: Some(true),
+            ..Properties::default()
+        };
+        let rules = rules(&properties);
+        let ids: Vec<&str> = rules.iter().map(|r| r.id()).collect();
+        assert_eq!(
+            ids,
+            vec![
+                "mixed-indentation",
+                "trailing-whitespace",
+                "line-endings",
+                "final-newline"
+            ]
+        );
+        let fixed = rules
+            .iter()
+            .fold("a \n    b\n\n".to_string(), |text, rule| {
+                rule.fix(&text).unwrap_or(text)
+            });
+        assert_eq!(fixed, "a\r\n\tb\r\n");
+        assert!(rules.iter().all(|rule| rule.check(&fixed).is_empty()));
+    }
+}
@@ -6,19 +6,29 @@
 //! ## Tools
 //!
 //! - [x] **trailing**: remove or check for trailing whitespaces.
-//! - [ ] **spaces**: replaces all tabs with spaces or checks if tabs are used for indentation.
-//! - [ ] **seek**: skip/select only parts of a file (similar to `head` and `tail`).
-//! - [ ] **mixed**: checks if the file uses mixed intendation (tabs and spaces).
-//! - [ ] **inplace**:helper tool "execute" changes to a file "inplace".
+//! - [x] **spaces**: replaces all tabs with spaces (or vice versa) or checks if tabs are used.
+//! - [x] **seek**: skip/select only parts of a file (similar to `head` and `tail`).
+//! - [x] **mixed**: checks if the file uses mixed intendation (tabs and spaces).
+//! - [x] **endings**: converts line endings and ends files with exactly one newline.
+//! - [x] **encoding**: converts between UTF-8, UTF-16 and Latin-1, adds or strips BOMs.
+//! - [x] **inplace**:helper tool "execute" changes to a file "inplace".
+//! - [x] **tidy**: runs the checks (see [rules]) of several bricks on many files at once, or
+//!   the ones configured by [editorconfig] files.
 use std::io::Read;
 use std::io::Write;
 
+pub mod editorconfig;
+pub mod retry;
+pub mod rules;
+pub mod supervisor;
+
 pub mod cli {
 
     use anyhow::{anyhow, Error};
+    use std::fs::{File, OpenOptions};
     use std::io::{Read, Write};
+    use std::sync::atomic::{AtomicUsize, Ordering};
     use std::{
-        convert::Into,
         path::{Path, PathBuf},
         str::FromStr,
     };
@@ -48,10 +58,67 @@ pub mod cli {
         }
     }
 
+    // Options selecting the files found when walking directories, see [crate::walk::Walker]. Not
+    // a doc comment, structopt would use it as the about text of the flattening tools.
+    #[derive(structopt::StructOpt, Debug, Clone, Default)]
+    pub struct Filter {
+        #[structopt(
+            short = "g",
+            long = "glob",
+            help = "only process files matching the glob, or skip them if it starts with '!'"
+        )]
+        pub globs: Vec<String>,
+
+        #[structopt(long = "no-ignore", help = "don't honor .gitignore and .ignore files")]
+        pub no_ignore: bool,
+
+        #[structopt(long = "binary", help = "don't skip binary files")]
+        pub binary: bool,
+    }
+
+    impl Filter {
+        pub fn walker<P: AsRef<Path>>(&self, paths: &[P]) -> crate::walk::Walker {
+            let mut walker = crate::walk::Walker::new(paths);
+            walker.ignore(!self.no_ignore).binary(self.binary);
+            for glob in &self.globs {
+                walker.glob(glob);
+            }
+            walker
+        }
+    }
+
     #[derive(Debug)]
     pub enum Output {
         Stdout(std::io::Stdout),
         File(std::fs::File),
+        /// A temporary file which replaces the destination once the output is finished.
+        Atomic(AtomicFile),
+    }
+
+    impl Output {
+        pub fn options() -> OutputOptions {
+            OutputOptions::default()
+        }
+
+        /// The file written to, `None` for stdout.
+        pub fn file(&self) -> Option<&File> {
+            match self {
+                Output::Stdout(_) => None,
+                Output::File(f) => Some(f),
+                Output::Atomic(a) => Some(&a.file),
+            }
+        }
+
+        /// Flushes the output, an [Output::Atomic] replaces its destination only now.
+        ///
+        /// Outputs which are dropped without being finished leave an atomic destination untouched.
+        pub fn finish(self) -> std::io::Result<()> {
+            match self {
+                Output::Stdout(mut s) => s.flush(),
+                Output::File(mut f) => f.flush(),
+                Output::Atomic(a) => a.persist(),
+            }
+        }
     }
 
     impl Write for Output {
@@ -59,6 +126,7 @@ pub mod cli {
             match self {
                 Output::Stdout(s) => s.write(buf),
                 Output::File(f) => f.write(buf),
+                Output::Atomic(a) => a.file.write(buf),
             }
         }
 
@@ -66,19 +134,216 @@ pub mod cli {
             match self {
                 Output::Stdout(s) => s.flush(),
                 Output::File(f) => f.flush(),
+                Output::Atomic(a) => a.file.flush(),
             }
         }
     }
 
+    /// Paths are opened with the default [OutputOptions], `-` selects stdout.
     impl FromStr for Output {
+        type Err = Error;
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            OutputOptions::default().open(s)
+        }
+    }
+
+    /// How an existing output file is treated.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Mode {
+        /// Create a new file, fail if it already exists.
+        Create,
+        /// Create the file or discard its current content.
+        Truncate,
+        /// Create the file or write behind its current content.
+        Append,
+    }
+
+    impl FromStr for Mode {
         type Err = Error;
         fn from_str(s: &str) -> Result<Self, Self::Err> {
             match s {
-                "-" => Ok(Output::Stdout(std::io::stdout())),
-                path => Ok(Output::File(std::fs::File::open(path)?)),
+                "create" => Ok(Mode::Create),
+                "truncate" => Ok(Mode::Truncate),
+                "append" => Ok(Mode::Append),
+                mode => Err(anyhow!(
+                    "Unknown mode \"{}\", expected create, truncate or append",
+                    mode
+                )),
+            }
+        }
+    }
+
+    /// Options to open an [Output], similar to [OpenOptions].
+    ///
+    /// ```no_run
+    /// use bricks::cli::{Mode, Output};
+    /// use std::io::Write;
+    ///
+    /// let mut output = Output::options()
+    ///     .mode(Mode::Append)
+    ///     .atomic(true)
+    ///     .create_parents(true)
+    ///     .open("logs/today.log")?;
+    /// output.write_all(b"done\n")?;
+    /// output.finish()?;
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    #[derive(Debug, Clone)]
+    pub struct OutputOptions {
+        mode: Mode,
+        atomic: bool,
+        create_parents: bool,
+        permissions: bool,
+    }
+
+    impl Default for OutputOptions {
+        fn default() -> Self {
+            OutputOptions {
+                mode: Mode::Truncate,
+                atomic: false,
+                create_parents: false,
+                permissions: true,
+            }
+        }
+    }
+
+    impl OutputOptions {
+        pub fn mode(&mut self, mode: Mode) -> &mut Self {
+            self.mode = mode;
+            self
+        }
+
+        /// Write to a temporary file next to the destination and rename it on [Output::finish].
+        pub fn atomic(&mut self, atomic: bool) -> &mut Self {
+            self.atomic = atomic;
+            self
+        }
+
+        /// Give an atomic output the permissions of the file it replaces (the default).
+        pub fn permissions(&mut self, permissions: bool) -> &mut Self {
+            self.permissions = permissions;
+            self
+        }
+
+        /// Create missing parent directories of the destination.
+        pub fn create_parents(&mut self, create_parents: bool) -> &mut Self {
+            self.create_parents = create_parents;
+            self
+        }
+
+        /// Opens `path` as output, `-` selects stdout.
+        pub fn open<P: AsRef<Path>>(&self, path: P) -> Result<Output, Error> {
+            let path = path.as_ref();
+            if path == Path::new("-") {
+                return Ok(Output::Stdout(std::io::stdout()));
+            }
+            if self.create_parents {
+                if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                    std::fs::create_dir_all(parent)?;
+                }
+            }
+            if self.atomic {
+                return Ok(Output::Atomic(AtomicFile::create(
+                    path,
+                    self.mode,
+                    self.permissions,
+                )?));
+            }
+            let file = match self.mode {
+                Mode::Create => OpenOptions::new().write(true).create_new(true).open(path),
+                Mode::Truncate => File::create(path),
+                Mode::Append => OpenOptions::new().append(true).create(true).open(path),
+            };
+            file.map(Output::File)
+                .map_err(|e| anyhow!("Can't open \"{}\", {}", path.display(), e))
+        }
+    }
+
+    /// A file written next to its destination, see [OutputOptions::atomic].
+    #[derive(Debug)]
+    pub struct AtomicFile {
+        file: File,
+        temporary: PathBuf,
+        destination: PathBuf,
+        mode: Mode,
+        persisted: bool,
+    }
+
+    impl AtomicFile {
+        /// Creates a temporary file next to `destination`, with the permissions of the file it
+        /// replaces if `permissions` is set. The destination is only touched by [Self::persist].
+        pub fn create(destination: &Path, mode: Mode, permissions: bool) -> Result<Self, Error> {
+            if mode == Mode::Create && destination.exists() {
+                return Err(anyhow!("\"{}\" already exists", destination.display()));
+            }
+            let temporary = temporary_path(destination);
+            let file = OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&temporary)?;
+            // from here on dropping `atomic` removes the temporary file again
+            let mut atomic = AtomicFile {
+                file,
+                temporary,
+                destination: destination.to_path_buf(),
+                mode,
+                persisted: false,
+            };
+            if let Ok(metadata) = std::fs::metadata(destination) {
+                if permissions {
+                    atomic.file.set_permissions(metadata.permissions())?;
+                }
+                if mode == Mode::Append {
+                    std::io::copy(&mut File::open(destination)?, &mut atomic.file)?;
+                }
+            }
+            Ok(atomic)
+        }
+
+        /// The temporary file written to.
+        pub fn file(&self) -> &File {
+            &self.file
+        }
+
+        /// Replaces the destination with the temporary file, dropping an atomic file without
+        /// persisting it removes the temporary file.
+        pub fn persist(mut self) -> std::io::Result<()> {
+            self.file.flush()?;
+            self.file.sync_all()?;
+            if self.mode == Mode::Create {
+                // unlike a rename, linking fails if the destination was created in the meantime
+                std::fs::hard_link(&self.temporary, &self.destination)?;
+                self.persisted = true;
+                return std::fs::remove_file(&self.temporary);
+            }
+            std::fs::rename(&self.temporary, &self.destination)?;
+            self.persisted = true;
+            Ok(())
+        }
+    }
+
+    impl Drop for AtomicFile {
+        fn drop(&mut self) {
+            if !self.persisted {
+                let _ = std::fs::remove_file(&self.temporary);
             }
         }
     }
+
+    /// A hidden, unique sibling of `destination`, renames within a directory are atomic.
+    fn temporary_path(destination: &Path) -> PathBuf {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let name = destination
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        destination.with_file_name(format!(
+            ".{}.{}.{}.tmp",
+            name,
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ))
+    }
 }
 
 pub fn create_reader(path: &str) -> std::io::Result<Box<dyn std::io::Read>> {
@@ -102,107 +367,1255 @@ where
     Ok(bytes_written)
 }
 
-pub mod reporter {
-    use std::io::BufRead;
-    use std::io::Read;
-    use std::io::Write;
+pub mod indentation {
+    use std::collections::HashMap;
+    use std::fmt;
 
-    pub struct FileReporter {
-        file_name: String,
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Style {
+        Tabs,
+        Spaces,
     }
 
-    impl FileReporter {
-        pub fn new(file_name: &str) -> Self {
-            FileReporter {
-                file_name: String::from(file_name),
+    /// How the lines of a file are indented, `width` is the number of columns per level.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Indentation {
+        pub style: Style,
+        pub width: usize,
+    }
+
+    impl fmt::Display for Indentation {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self.style {
+                Style::Tabs => write!(f, "tabs (width {})", self.width),
+                Style::Spaces => write!(f, "{} spaces", self.width),
             }
         }
+    }
 
-        // TODO: make it generic -> call it report and pass a closure for the work
-        pub fn report_trailing_whitespaces<R: ?Sized, W: ?Sized>(
-            &self,
-            reader: &mut R,
-            writer: &mut W,
-        ) -> std::io::Result<usize>
-        where
-            R: Read,
-            W: Write,
-        {
-            let mut reported_issues = 0usize;
-            for (line_no, line) in std::io::BufReader::new(reader).lines().enumerate() {
-                let l = line?;
-                if l != l.trim_end() {
-                    writer.write_fmt(format_args!(
-                        "Trailing whitespace detected, File: {}, Line: {}\n",
-                        self.file_name,
-                        line_no + 1
-                    ))?;
-                    reported_issues += 1;
+    /// Width assumed if a file doesn't tell, e.g. because it is indented with tabs only.
+    pub const DEFAULT_WIDTH: usize = 4;
+
+    impl Indentation {
+        /// Infers the indentation used by most lines of `text`, `None` if no line is indented.
+        ///
+        /// The width is the most common (positive) difference between the indentation of
+        /// consecutive lines indented with spaces.
+        pub fn infer(text: &[u8]) -> Option<Self> {
+            let (mut tabs, mut spaces) = (0usize, 0usize);
+            let mut steps: HashMap<usize, usize> = HashMap::new();
+            let mut previous = 0usize;
+            for line in text.split(|b| *b == b'\n').filter(|l| !is_blank(l)) {
+                let indentation = leading(line);
+                if indentation.iter().all(|b| *b == b' ') {
+                    if indentation.len() > previous {
+                        *steps.entry(indentation.len() - previous).or_insert(0) += 1;
+                    }
+                    previous = indentation.len();
+                    spaces += !indentation.is_empty() as usize;
+                } else if indentation.iter().all(|b| *b == b'\t') {
+                    tabs += 1;
                 }
             }
-            Ok(reported_issues)
+            if tabs == 0 && spaces == 0 {
+                return None;
+            }
+            let width = steps
+                .into_iter()
+                .filter(|(step, _)| (2..=8).contains(step))
+                .max_by_key(|(step, count)| (*count, std::cmp::Reverse(*step)))
+                .map_or(DEFAULT_WIDTH, |(step, _)| step);
+            let style = if tabs > spaces {
+                Style::Tabs
+            } else {
+                Style::Spaces
+            };
+            Some(Indentation { style, width })
+        }
+
+        /// Whether `line` is indented in this style, tabs may be followed by less than
+        /// `width` spaces to align text.
+        pub fn matches(&self, line: &[u8]) -> bool {
+            let indentation = leading(line);
+            match self.style {
+                Style::Spaces => !indentation.contains(&b'\t'),
+                Style::Tabs => {
+                    let tabs = indentation.iter().take_while(|b| **b == b'\t').count();
+                    let spaces = &indentation[tabs..];
+                    spaces.len() < self.width && !spaces.contains(&b'\t')
+                }
+            }
+        }
+
+        /// Re-indents `line` in this style, the indentation keeps its width in columns.
+        pub fn apply(&self, line: &[u8], output: &mut Vec<u8>) {
+            let indentation = leading(line);
+            let columns = columns(indentation, self.width);
+            match self.style {
+                Style::Tabs => {
+                    output.resize(output.len() + columns / self.width, b'\t');
+                    output.resize(output.len() + columns % self.width, b' ');
+                }
+                Style::Spaces => output.resize(output.len() + columns, b' '),
+            }
+            output.extend_from_slice(&line[indentation.len()..]);
         }
     }
+
+    /// The leading tabs and spaces of `line`.
+    pub fn leading(line: &[u8]) -> &[u8] {
+        let end = line
+            .iter()
+            .position(|b| *b != b' ' && *b != b'\t')
+            .unwrap_or(line.len());
+        &line[..end]
+    }
+
+    /// Columns covered by `whitespace` if tab stops are `tab_width` columns apart.
+    pub fn columns(whitespace: &[u8], tab_width: usize) -> usize {
+        whitespace.iter().fold(0, |column, b| match b {
+            b'\t' => column + tab_width - column % tab_width,
+            _ => column + 1,
+        })
+    }
+
+    /// Lines consisting of whitespace only aren't indented, they are the business of `trailing`.
+    pub fn is_blank(line: &[u8]) -> bool {
+        line.iter().all(u8::is_ascii_whitespace)
+    }
 }
 
-pub mod transformations {
+pub mod endings {
+    use anyhow::{anyhow, Error};
+    use std::fmt;
+    use std::str::FromStr;
 
-    use std::io::BufRead;
-    use std::io::Read;
-    use std::io::Write;
+    /// The bytes terminating a line.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Ending {
+        Lf,
+        CrLf,
+        Cr,
+    }
 
-    /// example
+    impl Ending {
+        pub fn as_bytes(self) -> &'static [u8] {
+            match self {
+                Ending::Lf => b"\n",
+                Ending::CrLf => b"\r\n",
+                Ending::Cr => b"\r",
+            }
+        }
+
+        /// The ending of most lines in `text`, ties are decided in favor of LF, then CRLF.
+        pub fn infer(text: &[u8]) -> Option<Self> {
+            let mut counts = [0usize; 3];
+            for (_, ending) in lines(text) {
+                if let Some(ending) = ending {
+                    counts[ending as usize] += 1;
+                }
+            }
+            [Ending::Cr, Ending::CrLf, Ending::Lf]
+                .iter()
+                .copied()
+                .filter(|ending| counts[*ending as usize] > 0)
+                .max_by_key(|ending| counts[*ending as usize])
+        }
+    }
+
+    impl fmt::Display for Ending {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                Ending::Lf => write!(f, "LF"),
+                Ending::CrLf => write!(f, "CRLF"),
+                Ending::Cr => write!(f, "CR"),
+            }
+        }
+    }
+
+    impl FromStr for Ending {
+        type Err = Error;
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s.to_ascii_lowercase().as_str() {
+                "lf" => Ok(Ending::Lf),
+                "crlf" => Ok(Ending::CrLf),
+                "cr" => Ok(Ending::Cr),
+                _ => Err(anyhow!(
+                    "Unknown line ending \"{}\", expected lf, crlf or cr",
+                    s
+                )),
+            }
+        }
+    }
+
+    /// Splits `text` into lines and their endings, only the last line may lack one.
+    pub fn lines(text: &[u8]) -> Lines<'_> {
+        Lines { text }
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct Lines<'t> {
+        text: &'t [u8],
+    }
+
+    impl<'t> Iterator for Lines<'t> {
+        type Item = (&'t [u8], Option<Ending>);
+
+        fn next(&mut self) -> Option<Self::Item> {
+            if self.text.is_empty() {
+                return None;
+            }
+            let end = self
+                .text
+                .iter()
+                .position(|b| *b == b'\n' || *b == b'\r')
+                .unwrap_or(self.text.len());
+            let ending = match &self.text[end..] {
+                [b'\r', b'\n', ..] => Some(Ending::CrLf),
+                [b'\r', ..] => Some(Ending::Cr),
+                [b'\n', ..] => Some(Ending::Lf),
+                _ => None,
+            };
+            let line = &self.text[..end];
+            self.text = &self.text[end + ending.map_or(0, |e| e.as_bytes().len())..];
+            Some((line, ending))
+        }
+    }
+
+    /// A line ending at the wrong place, or of the wrong kind.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Issue {
+        Unexpected {
+            expected: Ending,
+            found: Ending,
+        },
+        MissingFinalNewline,
+        /// Empty lines at the end of the file.
+        ExtraFinalNewlines,
+    }
+
+    impl fmt::Display for Issue {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                Issue::Unexpected { expected, found } => {
+                    write!(f, "Unexpected line ending {}, expected {}", found, expected)
+                }
+                Issue::MissingFinalNewline => write!(f, "Missing final newline"),
+                Issue::ExtraFinalNewlines => write!(f, "Extra final newlines"),
+            }
+        }
+    }
+
+    /// The issues of `text` and the lines (starting at 1) they are found on.
     ///
-    /// ```
-    /// let x = 1;
-    /// assert_eq!(x, 1);
+    /// Line endings other than `expected` (by default the one inferred by [Ending::infer])
+    /// are issues, with `final_newline` also anything but exactly one final line ending.
+    pub fn check(
+        text: &[u8],
+        expected: Option<Ending>,
+        final_newline: bool,
+    ) -> Vec<(usize, Issue)> {
+        let lines: Vec<_> = lines(text).collect();
+        let mut issues = Vec::new();
+        if let Some(expected) = expected.or_else(|| Ending::infer(text)) {
+            for (index, (_, ending)) in lines.iter().enumerate() {
+                match ending {
+                    Some(found) if *found != expected => issues.push((
+                        index + 1,
+                        Issue::Unexpected {
+                            expected,
+                            found: *found,
+                        },
+                    )),
+                    _ => {}
+                }
+            }
+        }
+        if !final_newline {
+            return issues;
+        }
+        let empty = lines
+            .iter()
+            .rev()
+            .take_while(|(line, _)| line.is_empty())
+            .count();
+        match lines.last() {
+            Some((_, None)) => issues.push((lines.len(), Issue::MissingFinalNewline)),
+            Some(_) if empty > 0 => {
+                issues.push((lines.len() - empty + 1, Issue::ExtraFinalNewlines))
+            }
+            _ => {}
+        }
+        issues
+    }
+}
+
+pub mod encoding {
+    use anyhow::{anyhow, Error};
+    use std::convert::TryFrom;
+    use std::fmt;
+    use std::str::FromStr;
+
+    /// The character encodings the bricks can detect and convert.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Encoding {
+        Utf8,
+        Utf16Le,
+        Utf16Be,
+        /// ISO-8859-1, every byte is the code point of a character.
+        Latin1,
+    }
+
+    impl Encoding {
+        /// The byte order mark, Latin-1 has none.
+        pub fn bom(self) -> &'static [u8] {
+            match self {
+                Encoding::Utf8 => b"\xEF\xBB\xBF",
+                Encoding::Utf16Le => b"\xFF\xFE",
+                Encoding::Utf16Be => b"\xFE\xFF",
+                Encoding::Latin1 => b"",
+            }
+        }
+
+        /// Decodes `bytes`, a byte order mark becomes a leading U+FEFF.
+        pub fn decode(self, bytes: &[u8]) -> Result<String, Error> {
+            let units = |to_unit: fn([u8; 2]) -> u16| {
+                if !bytes.len().is_multiple_of(2) {
+                    return Err(anyhow!("Invalid {}, odd number of bytes", self));
+                }
+                let units = bytes.chunks_exact(2).map(|b| to_unit([b[0], b[1]]));
+                char::decode_utf16(units)
+                    .collect::<Result<String, _>>()
+                    .map_err(|e| anyhow!("Invalid {}, {}", self, e))
+            };
+            match self {
+                Encoding::Utf8 => String::from_utf8(bytes.to_vec())
+                    .map_err(|e| anyhow!("Invalid {}, {}", self, e.utf8_error())),
+                Encoding::Utf16Le => units(u16::from_le_bytes),
+                Encoding::Utf16Be => units(u16::from_be_bytes),
+                Encoding::Latin1 => Ok(bytes.iter().map(|b| char::from(*b)).collect()),
+            }
+        }
+
+        /// Encodes `text`, a leading U+FEFF becomes the byte order mark.
+        pub fn encode(self, text: &str) -> Result<Vec<u8>, Error> {
+            match self {
+                Encoding::Utf8 => Ok(text.as_bytes().to_vec()),
+                Encoding::Utf16Le => Ok(text.encode_utf16().flat_map(u16::to_le_bytes).collect()),
+                Encoding::Utf16Be => Ok(text.encode_utf16().flat_map(u16::to_be_bytes).collect()),
+                Encoding::Latin1 => text
+                    .chars()
+                    .map(|c| {
+                        u8::try_from(u32::from(c))
+                            .map_err(|_| anyhow!("Can't encode {:?} in {}", c, self))
+                    })
+                    .collect(),
+            }
+        }
+    }
+
+    impl fmt::Display for Encoding {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                Encoding::Utf8 => write!(f, "UTF-8"),
+                Encoding::Utf16Le => write!(f, "UTF-16LE"),
+                Encoding::Utf16Be => write!(f, "UTF-16BE"),
+                Encoding::Latin1 => write!(f, "Latin-1"),
+            }
+        }
+    }
+
+    /// Accepts the names used by EditorConfig's `charset`, e.g. `utf-16le` or `latin1`.
+    impl FromStr for Encoding {
+        type Err = Error;
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s.to_ascii_lowercase().as_str() {
+                "utf-8" | "utf8" => Ok(Encoding::Utf8),
+                "utf-16le" | "utf16le" => Ok(Encoding::Utf16Le),
+                "utf-16be" | "utf16be" => Ok(Encoding::Utf16Be),
+                "latin1" | "latin-1" | "iso-8859-1" => Ok(Encoding::Latin1),
+                _ => Err(anyhow!(
+                    "Unknown encoding \"{}\", expected utf-8, utf-16le, utf-16be or latin1",
+                    s
+                )),
+            }
+        }
+    }
+
+    /// The encoding of a text and whether it starts with a byte order mark.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Detection {
+        pub encoding: Encoding,
+        pub bom: bool,
+    }
+
+    impl fmt::Display for Detection {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self.bom {
+                true => write!(f, "{} with BOM", self.encoding),
+                false => write!(f, "{}", self.encoding),
+            }
+        }
+    }
+
+    /// Detects the encoding of `bytes` by its byte order mark, otherwise UTF-16 is assumed if
+    /// every other byte is mostly zero, then UTF-8 if it is valid and Latin-1 as last resort.
+    pub fn detect(bytes: &[u8]) -> Detection {
+        let with_bom = [Encoding::Utf8, Encoding::Utf16Le, Encoding::Utf16Be]
+            .iter()
+            .copied()
+            .find(|encoding| bytes.starts_with(encoding.bom()));
+        let encoding =
+            with_bom
+                .or_else(|| utf16(bytes))
+                .unwrap_or_else(|| match std::str::from_utf8(bytes) {
+                    Ok(_) => Encoding::Utf8,
+                    Err(_) => Encoding::Latin1,
+                });
+        Detection {
+            encoding,
+            bom: with_bom.is_some(),
+        }
+    }
+
+    /// UTF-16 without byte order mark, ASCII characters have a zero byte.
+    pub fn utf16(bytes: &[u8]) -> Option<Encoding> {
+        if bytes.is_empty() || !bytes.len().is_multiple_of(2) {
+            return None;
+        }
+        let units = bytes.len() / 2;
+        let (mut even, mut odd) = (0usize, 0usize);
+        for unit in bytes.chunks_exact(2) {
+            even += (unit[0] == 0) as usize;
+            odd += (unit[1] == 0) as usize;
+        }
+        match (even, odd) {
+            (0, odd) if odd * 2 >= units => Some(Encoding::Utf16Le),
+            (even, 0) if even * 2 >= units => Some(Encoding::Utf16Be),
+            _ => None,
+        }
+    }
+}
+
+pub mod inplace {
+    use crate::cli::Output;
+    use anyhow::{anyhow, Error};
+    use std::fs::{File, FileTimes};
+    use std::io::BufWriter;
+    use std::path::Path;
+
+    /// Attributes of the original file the rewritten one shall keep.
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub struct Preserve {
+        pub permissions: bool,
+        /// Modification and access time.
+        pub times: bool,
+    }
+
+    /// Rewrites the file at `path` with what `process` writes while reading it.
     ///
-    /// ```
-    pub fn remove_trailing_whitespaces<R: Read, W: Write>(
-        reader: &mut R,
-        writer: &mut W,
-    ) -> std::io::Result<usize> {
-        let mut bytes_written = 0usize;
-        for line in std::io::BufReader::new(reader).lines() {
-            let mut output_line = String::from(line?.trim_end());
-            output_line.push('\n');
-            writer.write_all(output_line.as_bytes())?;
-            bytes_written += output_line.len();
+    /// The output goes to a temporary file next to `path` which replaces the original by an
+    /// atomic rename once `process` succeeded. If it fails the original stays untouched.
+    pub fn apply<F>(path: &Path, preserve: Preserve, mut process: F) -> Result<usize, Error>
+    where
+        F: FnMut(&mut File, &mut BufWriter<Output>) -> std::io::Result<usize>,
+    {
+        let mut reader =
+            File::open(path).map_err(|e| anyhow!("Can't open \"{}\", {}", path.display(), e))?;
+        let metadata = reader.metadata()?;
+        let output = Output::options()
+            .atomic(true)
+            .permissions(preserve.permissions)
+            .open(path)?;
+        let mut writer = BufWriter::new(output);
+        let bytes_written = crate::process(&mut reader, &mut writer, &mut process)?;
+        let output = writer.into_inner().map_err(|e| e.into_error())?;
+        if let (true, Some(file)) = (preserve.times, output.file()) {
+            file.set_times(
+                FileTimes::new()
+                    .set_accessed(metadata.accessed()?)
+                    .set_modified(metadata.modified()?),
+            )?;
         }
+        output.finish()?;
         Ok(bytes_written)
     }
 }
 
-#[cfg(test)]
-mod tests {
+pub mod walk {
+    use crate::cli::Output;
+    use crate::encoding::{self, Encoding};
+    use crate::inplace::{self, Preserve};
+    use anyhow::{anyhow, Error};
+    use globset::{Glob, GlobSet, GlobSetBuilder};
+    use ignore::WalkBuilder;
+    use rayon::prelude::*;
+    use std::fs::File;
+    use std::io::{BufWriter, Read, Write};
+    use std::path::{Path, PathBuf};
 
-    use super::*;
-    use std::io::Cursor;
+    /// Bytes inspected to tell binary from text files.
+    const INSPECTED_BYTES: u64 = 8 * 1024;
 
-    #[test]
-    fn remove_trailing_whitespaces_transformation_test() {
-        let mut reader = Cursor::new(b"aa   \n    \n a\t\n".to_vec());
-        let mut writer = Cursor::new(vec![0; 0]);
-        let expected_data: Vec<u8> = b"aa\n\n a\n".to_vec();
-        let expected_bytes_written = 7usize;
-        let result = process(
-            &mut reader,
-            &mut writer,
-            transformations::remove_trailing_whitespaces,
-        );
-        assert!(result.is_ok());
-        assert_eq!(result.ok(), Some(expected_bytes_written));
-        assert_eq!(writer.into_inner(), expected_data);
+    /// Collects the files below a set of paths.
+    ///
+    /// Directories are walked recursively, skipping hidden entries, entries ignored by
+    /// `.gitignore` or `.ignore` files and binary files. Paths naming files are always
+    /// part of the result.
+    #[derive(Debug, Clone)]
+    pub struct Walker {
+        paths: Vec<PathBuf>,
+        globs: Vec<String>,
+        ignore: bool,
+        binary: bool,
+        utf16: bool,
     }
-    #[test]
-    fn report_trailing_whitespaces() {
-        let mut reader = Cursor::new(b"aa\n    a   \t\n".to_vec());
-        let mut writer = Cursor::new(vec![0; 0]);
-        let reporter = reporter::FileReporter::new("f.txt");
-        let result = process(&mut reader, &mut writer, |mut reader, mut writer| {
-            let reported_items = reporter.report_trailing_whitespaces(&mut reader, &mut writer)?;
-            Ok(reported_items)
-        });
+
+    impl Walker {
+        pub fn new<P: AsRef<Path>>(paths: &[P]) -> Self {
+            Walker {
+                paths: paths.iter().map(|p| p.as_ref().to_path_buf()).collect(),
+                globs: Vec::new(),
+                ignore: true,
+                binary: false,
+                utf16: false,
+            }
+        }
+
+        /// Only keep the files found in directories which match `glob`, or skip them if it
+        /// starts with `!`. Globs match the path relative to the walked directory.
+        pub fn glob(&mut self, glob: &str) -> &mut Self {
+            self.globs.push(glob.to_string());
+            self
+        }
+
+        /// Honor `.gitignore` and `.ignore` files, defaults to `true`.
+        pub fn ignore(&mut self, ignore: bool) -> &mut Self {
+            self.ignore = ignore;
+            self
+        }
+
+        /// Keep binary files found in directories, defaults to `false`.
+        pub fn binary(&mut self, binary: bool) -> &mut Self {
+            self.binary = binary;
+            self
+        }
+
+        /// Keep UTF-16 encoded files found in directories, defaults to `false`. Their zero
+        /// bytes make them binary files for bricks working on bytes.
+        pub fn utf16(&mut self, utf16: bool) -> &mut Self {
+            self.utf16 = utf16;
+            self
+        }
+
+        /// The files in the order of the paths, files within a directory are sorted by path.
+        pub fn files(&self) -> Result<Vec<PathBuf>, Error> {
+            let (include, exclude) = self.globs()?;
+            let mut files = Vec::new();
+            for path in &self.paths {
+                if !path.is_dir() {
+                    files.push(path.clone());
+                    continue;
+                }
+                let walk = WalkBuilder::new(path)
+                    .git_ignore(self.ignore)
+                    .git_global(self.ignore)
+                    .git_exclude(self.ignore)
+                    .ignore(self.ignore)
+                    .parents(self.ignore)
+                    .require_git(false)
+                    .sort_by_file_name(|a, b| a.cmp(b))
+                    .build();
+                for entry in walk {
+                    let entry = entry?;
+                    if !entry.file_type().is_some_and(|t| t.is_file()) {
+                        continue;
+                    }
+                    let relative = entry.path().strip_prefix(path).unwrap_or(entry.path());
+                    let included = include.is_empty() || include.is_match(relative);
+                    if !included || exclude.is_match(relative) {
+                        continue;
+                    }
+                    if !self.binary && self.is_binary(entry.path())? {
+                        continue;
+                    }
+                    files.push(entry.into_path());
+                }
+            }
+            Ok(files)
+        }
+
+        fn is_binary(&self, path: &Path) -> std::io::Result<bool> {
+            let start = start(path)?;
+            let utf16 = || match encoding::detect(&start).encoding {
+                Encoding::Utf16Le | Encoding::Utf16Be => true,
+                Encoding::Utf8 | Encoding::Latin1 => false,
+            };
+            Ok(start.contains(&0) && !(self.utf16 && utf16()))
+        }
+
+        fn globs(&self) -> Result<(GlobSet, GlobSet), Error> {
+            let mut include = GlobSetBuilder::new();
+            let mut exclude = GlobSetBuilder::new();
+            for glob in &self.globs {
+                let (set, pattern) = match glob.strip_prefix('!') {
+                    Some(pattern) => (&mut exclude, pattern),
+                    None => (&mut include, glob.as_str()),
+                };
+                set.add(
+                    Glob::new(pattern).map_err(|e| anyhow!("Invalid glob \"{}\", {}", glob, e))?,
+                );
+            }
+            Ok((include.build()?, exclude.build()?))
+        }
+    }
+
+    /// Whether the start of the file contains a NUL byte, which text files don't.
+    pub fn is_binary(path: &Path) -> std::io::Result<bool> {
+        Ok(start(path)?.contains(&0))
+    }
+
+    fn start(path: &Path) -> std::io::Result<Vec<u8>> {
+        let mut start = Vec::new();
+        File::open(path)?
+            .take(INSPECTED_BYTES)
+            .read_to_end(&mut start)?;
+        Ok(start)
+    }
+
+    /// Applies `process` to all files in parallel, the results keep the order of `files`.
+    pub fn parallel<T, F>(files: &[PathBuf], process: F) -> Vec<T>
+    where
+        T: Send,
+        F: Fn(&Path) -> T + Sync,
+    {
+        files.par_iter().map(|path| process(path)).collect()
+    }
+
+    /// Runs `process` on every file in parallel and writes what it wrote for every file to
+    /// `writer`, in the order of `files`.
+    ///
+    /// Returns the sum of the results of `process`, or the first error.
+    pub fn process<W, F>(files: &[PathBuf], writer: &mut W, process: F) -> Result<usize, Error>
+    where
+        W: ?Sized + Write,
+        F: Fn(&Path, &mut File, &mut Vec<u8>) -> std::io::Result<usize> + Sync,
+    {
+        let results = parallel(files, |path| {
+            let mut output = Vec::new();
+            File::open(path)
+                .and_then(|mut reader| process(path, &mut reader, &mut output))
+                .map(|count| (count, output))
+                .map_err(|e| anyhow!("Can't process \"{}\", {}", path.display(), e))
+        });
+        let mut total = 0;
+        for result in results {
+            let (count, output) = result?;
+            writer.write_all(&output)?;
+            total += count;
+        }
+        Ok(total)
+    }
+
+    /// Rewrites every file in parallel with what `process` writes while reading it, see
+    /// [inplace::apply].
+    ///
+    /// Returns the number of bytes written, or the first error.
+    pub fn rewrite<F>(files: &[PathBuf], preserve: Preserve, process: F) -> Result<usize, Error>
+    where
+        F: Fn(&mut File, &mut BufWriter<Output>) -> std::io::Result<usize> + Sync,
+    {
+        parallel(files, |path| inplace::apply(path, preserve, &process))
+            .into_iter()
+            .sum()
+    }
+}
+
+pub mod selection {
+    use anyhow::{anyhow, Error};
+    use std::collections::VecDeque;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::str::FromStr;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Unit {
+        Lines,
+        Bytes,
+    }
+
+    /// Part of a stream to select, counts and offsets are in [Unit]s.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Selection {
+        /// The first `n` units.
+        Head(usize),
+        /// The last `n` units.
+        Tail(usize),
+        /// Everything after the first `n` units.
+        Skip(usize),
+        /// The units in [Range].
+        Range(Range),
+    }
+
+    /// A range of units counted from 0 for lines and bytes alike, the end is excluded and `None`
+    /// selects until the end of the stream.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Range {
+        pub start: usize,
+        pub end: Option<usize>,
+    }
+
+    /// Parses `start:end` where both bounds are optional, e.g. `10:20`, `10:` or `:20`.
+    impl FromStr for Range {
+        type Err = Error;
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let (start, end) = s
+                .split_once(':')
+                .ok_or_else(|| anyhow!("Invalid range \"{}\", expected start:end", s))?;
+            let bound = |bound: &str| match bound.trim() {
+                "" => Ok(None),
+                bound => bound
+                    .parse::<usize>()
+                    .map(Some)
+                    .map_err(|e| anyhow!("Invalid bound \"{}\" of range \"{}\", {}", bound, s, e)),
+            };
+            let range = Range {
+                start: bound(start)?.unwrap_or(0),
+                end: bound(end)?,
+            };
+            match range.end {
+                Some(end) if end < range.start => {
+                    Err(anyhow!("The range \"{}\" ends before it starts", s))
+                }
+                _ => Ok(range),
+            }
+        }
+    }
+
+    /// Copies the selected part of `reader` to `writer`, only [Selection::Tail] buffers
+    /// (the last `n` units).
+    pub fn select<R: Read, W: Write>(
+        reader: &mut R,
+        writer: &mut W,
+        selection: Selection,
+        unit: Unit,
+    ) -> std::io::Result<usize> {
+        let range = match selection {
+            Selection::Head(n) => Range {
+                start: 0,
+                end: Some(n),
+            },
+            Selection::Skip(n) => Range {
+                start: n,
+                end: None,
+            },
+            Selection::Range(range) => range,
+            Selection::Tail(n) => {
+                return match unit {
+                    Unit::Lines => tail_lines(reader, writer, n),
+                    Unit::Bytes => tail_bytes(reader, writer, n),
+                }
+            }
+        };
+        match unit {
+            Unit::Lines => range_of_lines(reader, writer, range),
+            Unit::Bytes => range_of_bytes(reader, writer, range),
+        }
+    }
+
+    fn range_of_lines<R: Read, W: Write>(
+        reader: &mut R,
+        writer: &mut W,
+        range: Range,
+    ) -> std::io::Result<usize> {
+        let mut reader = BufReader::new(reader);
+        let mut line = Vec::new();
+        let mut bytes_written = 0usize;
+        let mut index = 0usize;
+        while !matches!(range.end, Some(end) if index >= end)
+            && reader.read_until(b'\n', &mut line)? > 0
+        {
+            if index >= range.start {
+                writer.write_all(&line)?;
+                bytes_written += line.len();
+            }
+            line.clear();
+            index += 1;
+        }
+        Ok(bytes_written)
+    }
+
+    fn range_of_bytes<R: Read, W: Write>(
+        reader: &mut R,
+        writer: &mut W,
+        range: Range,
+    ) -> std::io::Result<usize> {
+        std::io::copy(
+            &mut reader.by_ref().take(range.start as u64),
+            &mut std::io::sink(),
+        )?;
+        let copied = match range.end {
+            Some(end) => std::io::copy(&mut reader.take((end - range.start) as u64), writer)?,
+            None => std::io::copy(reader, writer)?,
+        };
+        Ok(copied as usize)
+    }
+
+    fn tail_lines<R: Read, W: Write>(
+        reader: &mut R,
+        writer: &mut W,
+        n: usize,
+    ) -> std::io::Result<usize> {
+        let mut reader = BufReader::new(reader);
+        let mut lines: VecDeque<Vec<u8>> = VecDeque::new();
+        loop {
+            let mut line = Vec::new();
+            if reader.read_until(b'\n', &mut line)? == 0 {
+                break;
+            }
+            lines.push_back(line);
+            if lines.len() > n {
+                lines.pop_front();
+            }
+        }
+        let mut bytes_written = 0usize;
+        for line in lines {
+            writer.write_all(&line)?;
+            bytes_written += line.len();
+        }
+        Ok(bytes_written)
+    }
+
+    fn tail_bytes<R: Read, W: Write>(
+        reader: &mut R,
+        writer: &mut W,
+        n: usize,
+    ) -> std::io::Result<usize> {
+        let mut last: VecDeque<u8> = VecDeque::new();
+        let mut buffer = [0u8; 8192];
+        loop {
+            let read = match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            last.extend(&buffer[..read]);
+            let excess = last.len().saturating_sub(n);
+            last.drain(..excess);
+        }
+        let (front, back) = last.as_slices();
+        writer.write_all(front)?;
+        writer.write_all(back)?;
+        Ok(last.len())
+    }
+}
+
+pub mod reporter {
+    use crate::encoding::{self, Encoding};
+    use crate::endings::{self, Ending, Issue};
+    use crate::indentation::{is_blank, Indentation};
+    use std::io::BufRead;
+    use std::io::Read;
+    use std::io::Write;
+
+    pub struct FileReporter {
+        file_name: String,
+    }
+
+    impl FileReporter {
+        pub fn new(file_name: &str) -> Self {
+            FileReporter {
+                file_name: String::from(file_name),
+            }
+        }
+
+        pub fn report_trailing_whitespaces<R: ?Sized, W: ?Sized>(
+            &self,
+            reader: &mut R,
+            writer: &mut W,
+        ) -> std::io::Result<usize>
+        where
+            R: Read,
+            W: Write,
+        {
+            self.report(reader, writer, "Trailing whitespace", |l| l != l.trim_end())
+        }
+
+        pub fn report_tabs<R: ?Sized + Read, W: ?Sized + Write>(
+            &self,
+            reader: &mut R,
+            writer: &mut W,
+        ) -> std::io::Result<usize> {
+            self.report(reader, writer, "Tab", |l| l.contains('\t'))
+        }
+
+        /// Reports lines whose indentation doesn't match the one used by most lines of the file.
+        ///
+        /// Lines indented by tabs and spaces are always reported. Unlike the other reports the
+        /// whole input is read first, to infer the indentation of the file.
+        pub fn report_mixed_indentation<R: ?Sized + Read, W: ?Sized + Write>(
+            &self,
+            reader: &mut R,
+            writer: &mut W,
+        ) -> std::io::Result<usize> {
+            let mut text = Vec::new();
+            reader.read_to_end(&mut text)?;
+            let indentation = match Indentation::infer(&text) {
+                Some(indentation) => indentation,
+                None => return Ok(0),
+            };
+            let mut reported_issues = 0usize;
+            for (line_no, line) in text.split(|b| *b == b'\n').enumerate() {
+                if !is_blank(line) && !indentation.matches(line) {
+                    writer.write_fmt(format_args!(
+                        "Mixed indentation detected, File: {}, Line: {}, Expected: {}\n",
+                        self.file_name,
+                        line_no + 1,
+                        indentation
+                    ))?;
+                    reported_issues += 1;
+                }
+            }
+            Ok(reported_issues)
+        }
+
+        /// Reports line endings other than `expected` (by default the one used by most lines)
+        /// and, with `final_newline`, files not ending with exactly one line ending.
+        pub fn report_line_endings<R: ?Sized + Read, W: ?Sized + Write>(
+            &self,
+            reader: &mut R,
+            writer: &mut W,
+            expected: Option<Ending>,
+            final_newline: bool,
+        ) -> std::io::Result<usize> {
+            let mut text = Vec::new();
+            reader.read_to_end(&mut text)?;
+            let issues = endings::check(&text, expected, final_newline);
+            for (line_no, issue) in &issues {
+                match issue {
+                    Issue::Unexpected { expected, found } => writer.write_fmt(format_args!(
+                        "Unexpected line ending detected, File: {}, Line: {}, Expected: {}, Found: {}\n",
+                        self.file_name, line_no, expected, found
+                    ))?,
+                    issue => writer.write_fmt(format_args!(
+                        "{} detected, File: {}, Line: {}\n",
+                        issue, self.file_name, line_no
+                    ))?,
+                }
+            }
+            Ok(issues.len())
+        }
+
+        /// Reports files not encoded in `expected` and, depending on `bom`, byte order marks or
+        /// their absence.
+        pub fn report_encoding<R: ?Sized + Read, W: ?Sized + Write>(
+            &self,
+            reader: &mut R,
+            writer: &mut W,
+            expected: Encoding,
+            bom: bool,
+        ) -> std::io::Result<usize> {
+            let mut text = Vec::new();
+            reader.read_to_end(&mut text)?;
+            let detection = encoding::detect(&text);
+            let mut reported_issues = 0usize;
+            if detection.encoding != expected {
+                writer.write_fmt(format_args!(
+                    "Unexpected encoding detected, File: {}, Expected: {}, Found: {}\n",
+                    self.file_name, expected, detection.encoding
+                ))?;
+                reported_issues += 1;
+            }
+            let issue = match (detection.bom, bom) {
+                (true, false) => "Byte order mark",
+                (false, true) => "Missing byte order mark",
+                _ => return Ok(reported_issues),
+            };
+            writer.write_fmt(format_args!(
+                "{} detected, File: {}\n",
+                issue, self.file_name
+            ))?;
+            Ok(reported_issues + 1)
+        }
+
+        /// Reports every line for which `is_issue` holds, returns the number of reported lines.
+        ///
+        /// Lines which aren't valid UTF-8 are checked with their invalid bytes replaced.
+        pub fn report<R, W, F>(
+            &self,
+            reader: &mut R,
+            writer: &mut W,
+            issue: &str,
+            is_issue: F,
+        ) -> std::io::Result<usize>
+        where
+            R: ?Sized + Read,
+            W: ?Sized + Write,
+            F: Fn(&str) -> bool,
+        {
+            let mut reported_issues = 0usize;
+            for (line_no, line) in std::io::BufReader::new(reader).split(b'\n').enumerate() {
+                let line = line?;
+                let line = line.strip_suffix(b"\r").unwrap_or(&line);
+                if is_issue(&String::from_utf8_lossy(line)) {
+                    writer.write_fmt(format_args!(
+                        "{} detected, File: {}, Line: {}\n",
+                        issue,
+                        self.file_name,
+                        line_no + 1
+                    ))?;
+                    reported_issues += 1;
+                }
+            }
+            Ok(reported_issues)
+        }
+    }
+}
+
+pub mod transformations {
+
+    use crate::encoding::{self, Encoding};
+    use crate::endings::{self, Ending};
+    use crate::indentation::{is_blank, Indentation, Style, DEFAULT_WIDTH};
+    use std::io::BufRead;
+    use std::io::Read;
+    use std::io::Write;
+
+    /// example
+    ///
+    /// ```
+    /// let x = 1;
+    /// assert_eq!(x, 1);
+    ///
+    /// ```
+    pub fn remove_trailing_whitespaces<R: Read, W: Write>(
+        reader: &mut R,
+        writer: &mut W,
+    ) -> std::io::Result<usize> {
+        transform_lines(reader, writer, |line, output| {
+            let content = match std::str::from_utf8(line) {
+                Ok(line) => line.trim_end().as_bytes(),
+                Err(_) => line.trim_ascii_end(),
+            };
+            output.extend_from_slice(content);
+        })
+    }
+
+    /// Replaces tabs with the spaces needed to reach the next tab stop (every `tab_width` columns).
+    pub fn expand_tabs<R: Read, W: Write>(
+        reader: &mut R,
+        writer: &mut W,
+        tab_width: usize,
+    ) -> std::io::Result<usize> {
+        transform_lines(reader, writer, |line, output| {
+            let mut column = 0usize;
+            for byte in line {
+                match byte {
+                    b'\t' => {
+                        let spaces = tab_width - column % tab_width;
+                        output.resize(output.len() + spaces, b' ');
+                        column += spaces;
+                    }
+                    _ => {
+                        output.push(*byte);
+                        column += is_char_start(*byte) as usize;
+                    }
+                }
+            }
+        })
+    }
+
+    /// Replaces the spaces (and tabs) indenting a line with tabs, remaining columns stay spaces.
+    ///
+    /// Like `unexpand` only the indentation is changed, tabs can't be told apart from spaces
+    /// within the text of a line.
+    pub fn unexpand_tabs<R: Read, W: Write>(
+        reader: &mut R,
+        writer: &mut W,
+        tab_width: usize,
+    ) -> std::io::Result<usize> {
+        let indentation = Indentation {
+            style: Style::Tabs,
+            width: tab_width,
+        };
+        transform_lines(reader, writer, |line, output| {
+            indentation.apply(line, output)
+        })
+    }
+
+    /// Re-indents all lines in the style used by most of them (see [Indentation::infer]).
+    ///
+    /// A given `style` or `width` takes precedence over the inferred one. Blank lines are
+    /// left as they are.
+    pub fn normalize_indentation<R: Read, W: Write>(
+        reader: &mut R,
+        writer: &mut W,
+        style: Option<Style>,
+        width: Option<usize>,
+    ) -> std::io::Result<usize> {
+        let mut text = Vec::new();
+        reader.read_to_end(&mut text)?;
+        let inferred = Indentation::infer(&text);
+        let indentation = Indentation {
+            style: style
+                .or_else(|| inferred.map(|i| i.style))
+                .unwrap_or(Style::Spaces),
+            width: width
+                .or_else(|| inferred.map(|i| i.width))
+                .unwrap_or(DEFAULT_WIDTH),
+        };
+        transform_lines(&mut text.as_slice(), writer, |line, output| {
+            if is_blank(line) {
+                output.extend_from_slice(line);
+            } else {
+                indentation.apply(line, output);
+            }
+        })
+    }
+
+    /// Terminates every line with `ending`, by default the one used by most lines (see
+    /// [Ending::infer]).
+    ///
+    /// With `final_newline` the output ends with exactly one line ending, unless it is empty.
+    pub fn convert_line_endings<R: Read, W: Write>(
+        reader: &mut R,
+        writer: &mut W,
+        ending: Option<Ending>,
+        final_newline: bool,
+    ) -> std::io::Result<usize> {
+        let mut text = Vec::new();
+        reader.read_to_end(&mut text)?;
+        let ending = ending
+            .or_else(|| Ending::infer(&text))
+            .unwrap_or(Ending::Lf);
+        let mut content = text.as_slice();
+        if final_newline {
+            while let Some((b'\n', rest)) | Some((b'\r', rest)) = content.split_last() {
+                content = rest;
+            }
+        }
+        let mut output = Vec::with_capacity(text.len());
+        for (line, line_ending) in endings::lines(content) {
+            output.extend_from_slice(line);
+            if line_ending.is_some() || final_newline {
+                output.extend_from_slice(ending.as_bytes());
+            }
+        }
+        writer.write_all(&output)?;
+        Ok(output.len())
+    }
+
+    /// Re-encodes the text from `from` (by default the detected encoding, see
+    /// [encoding::detect]) to `to`, starting with a byte order mark if `bom` is set.
+    pub fn convert_encoding<R: Read, W: Write>(
+        reader: &mut R,
+        writer: &mut W,
+        from: Option<Encoding>,
+        to: Encoding,
+        bom: bool,
+    ) -> std::io::Result<usize> {
+        let invalid = |e: anyhow::Error| std::io::Error::new(std::io::ErrorKind::InvalidData, e);
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let from = from.unwrap_or_else(|| encoding::detect(&bytes).encoding);
+        let text = from.decode(&bytes).map_err(invalid)?;
+        let text = text.strip_prefix('\u{feff}').unwrap_or(&text);
+        if bom && to.bom().is_empty() {
+            return Err(invalid(anyhow::anyhow!("{} has no byte order mark", to)));
+        }
+        let mut output = match bom {
+            true => to.bom().to_vec(),
+            false => Vec::new(),
+        };
+        output.extend(to.encode(text).map_err(invalid)?);
+        writer.write_all(&output)?;
+        Ok(output.len())
+    }
+
+    /// Ends the text with exactly one line ending, the one used by most lines (or LF).
+    ///
+    /// Empty texts stay empty, other line endings are kept as they are.
+    pub fn ensure_final_newline<R: Read, W: Write>(
+        reader: &mut R,
+        writer: &mut W,
+    ) -> std::io::Result<usize> {
+        let mut text = Vec::new();
+        reader.read_to_end(&mut text)?;
+        let ending = Ending::infer(&text).unwrap_or(Ending::Lf);
+        let mut content = text.as_slice();
+        while let Some((b'\n', rest)) | Some((b'\r', rest)) = content.split_last() {
+            content = rest;
+        }
+        if content.is_empty() {
+            return Ok(0);
+        }
+        writer.write_all(content)?;
+        writer.write_all(ending.as_bytes())?;
+        Ok(content.len() + ending.as_bytes().len())
+    }
+
+    /// Applies `transform` to the content of every line, line endings are kept as they are.
+    fn transform_lines<R: Read, W: Write, F>(
+        reader: &mut R,
+        writer: &mut W,
+        mut transform: F,
+    ) -> std::io::Result<usize>
+    where
+        F: FnMut(&[u8], &mut Vec<u8>),
+    {
+        let mut reader = std::io::BufReader::new(reader);
+        let mut line = Vec::new();
+        let mut output = Vec::new();
+        let mut bytes_written = 0usize;
+        while reader.read_until(b'\n', &mut line)? > 0 {
+            for (content, ending) in endings::lines(&line) {
+                transform(content, &mut output);
+                output.extend_from_slice(ending.map_or(&[], |e| e.as_bytes()));
+            }
+            writer.write_all(&output)?;
+            bytes_written += output.len();
+            line.clear();
+            output.clear();
+        }
+        Ok(bytes_written)
+    }
+
+    /// Continuation bytes of UTF-8 encoded characters don't occupy a column.
+    fn is_char_start(byte: u8) -> bool {
+        byte & 0xC0 != 0x80
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn remove_trailing_whitespaces_transformation_test() {
+        let mut reader = Cursor::new(b"aa   \n    \n a\t\n".to_vec());
+        let mut writer = Cursor::new(vec![0; 0]);
+        let expected_data: Vec<u8> = b"aa\n\n a\n".to_vec();
+        let expected_bytes_written = 7usize;
+        let result = process(
+            &mut reader,
+            &mut writer,
+            transformations::remove_trailing_whitespaces,
+        );
+        assert!(result.is_ok());
+        assert_eq!(result.ok(), Some(expected_bytes_written));
+        assert_eq!(writer.into_inner(), expected_data);
+    }
+    #[test]
+    fn report_trailing_whitespaces() {
+        let mut reader = Cursor::new(b"aa\n    a   \t\n".to_vec());
+        let mut writer = Cursor::new(vec![0; 0]);
+        let reporter = reporter::FileReporter::new("f.txt");
+        let result = process(&mut reader, &mut writer, |mut reader, mut writer| {
+            let reported_items = reporter.report_trailing_whitespaces(&mut reader, &mut writer)?;
+            Ok(reported_items)
+        });
         assert!(result.is_ok());
         assert_eq!(result.ok(), Some(1));
         assert_eq!(
@@ -210,4 +1623,635 @@ mod tests {
             "Trailing whitespace detected, File: f.txt, Line: 2\n"
         );
     }
+
+    #[test]
+    fn expand_tabs_to_tab_stops() {
+        let mut reader = Cursor::new("\ta\tbc\td\r\nä\t|\n  \t".as_bytes().to_vec());
+        let mut writer = Cursor::new(vec![0; 0]);
+        let result = process(&mut reader, &mut writer, |reader, writer| {
+            transformations::expand_tabs(reader, writer, 4)
+        });
+        let expected = "    a   bc  d\r\nä   |\n    ";
+        assert_eq!(result.ok(), Some(expected.len()));
+        assert_eq!(String::from_utf8_lossy(&writer.into_inner()), expected);
+    }
+
+    #[test]
+    fn unexpand_indentation() {
+        let mut reader = Cursor::new(b"        a    b\n   \t    c\n      d\n".to_vec());
+        let mut writer = Cursor::new(vec![0; 0]);
+        let result = process(&mut reader, &mut writer, |reader, writer| {
+            transformations::unexpand_tabs(reader, writer, 4)
+        });
+        assert!(result.is_ok());
+        assert_eq!(
+            String::from_utf8_lossy(&writer.into_inner()),
+            "\t\ta    b\n\t\tc\n\t  d\n"
+        );
+    }
+
+    #[test]
+    fn report_tabs() {
+        let mut reader = Cursor::new(b"\ta\n    b\nc\td\n".to_vec());
+        let mut writer = Cursor::new(vec![0; 0]);
+        let reporter = reporter::FileReporter::new("f.txt");
+        let result = process(&mut reader, &mut writer, |reader, writer| {
+            reporter.report_tabs(reader, writer)
+        });
+        assert_eq!(result.ok(), Some(2));
+        assert_eq!(
+            String::from_utf8_lossy(&writer.into_inner()),
+            "Tab detected, File: f.txt, Line: 1\nTab detected, File: f.txt, Line: 3\n"
+        );
+    }
+
+    #[test]
+    fn infer_indentation() {
+        use indentation::{Indentation, Style};
+        let infer = |text: &str| Indentation::infer(text.as_bytes());
+        let spaces = |width| Indentation {
+            style: Style::Spaces,
+            width,
+        };
+        assert_eq!(infer("a\n  b\n    c\n  d\n\te\n"), Some(spaces(2)));
+        let tabs = Indentation {
+            style: Style::Tabs,
+            width: 4,
+        };
+        assert_eq!(infer("a\n\tb\n\t\tc\n    d\n"), Some(tabs));
+        assert_eq!(infer("a\n   b\n      c\n"), Some(spaces(3)));
+        assert_eq!(infer("a\n  \nb\n"), None);
+    }
+
+    #[test]
+    fn report_mixed_indentation() {
+        let mut reader = Cursor::new(b"a\n    b\n\tc\n  \td\n        e\n\t\n".to_vec());
+        let mut writer = Cursor::new(vec![0; 0]);
+        let reporter = reporter::FileReporter::new("f.txt");
+        let result = process(&mut reader, &mut writer, |reader, writer| {
+            reporter.report_mixed_indentation(reader, writer)
+        });
+        assert_eq!(result.ok(), Some(2));
+        assert_eq!(
+            String::from_utf8_lossy(&writer.into_inner()),
+            "Mixed indentation detected, File: f.txt, Line: 3, Expected: 4 spaces\n\
+             Mixed indentation detected, File: f.txt, Line: 4, Expected: 4 spaces\n"
+        );
+    }
+
+    #[test]
+    fn normalize_indentation() {
+        let mut reader =
+            Cursor::new(b"a\n\tb\n\t\tc\n\t\ty\n\tx\n    d\r\n        e\n      f\n \n".to_vec());
+        let mut writer = Cursor::new(vec![0; 0]);
+        let result = process(&mut reader, &mut writer, |reader, writer| {
+            transformations::normalize_indentation(reader, writer, None, None)
+        });
+        assert!(result.is_ok());
+        assert_eq!(
+            String::from_utf8_lossy(&writer.into_inner()),
+            "a\n\tb\n\t\tc\n\t\ty\n\tx\n\td\r\n\t\te\n\t  f\n \n"
+        );
+    }
+
+    #[test]
+    fn split_and_infer_line_endings() {
+        use endings::{lines, Ending};
+        let text = b"a\r\nb\rc\n\nd";
+        let split: Vec<(&[u8], Option<Ending>)> = lines(text).collect();
+        assert_eq!(
+            split,
+            vec![
+                (&b"a"[..], Some(Ending::CrLf)),
+                (&b"b"[..], Some(Ending::Cr)),
+                (&b"c"[..], Some(Ending::Lf)),
+                (&b""[..], Some(Ending::Lf)),
+                (&b"d"[..], None),
+            ]
+        );
+        assert_eq!(Ending::infer(text), Some(Ending::Lf));
+        assert_eq!(Ending::infer(b"a\r\nb\r\nc\n"), Some(Ending::CrLf));
+        assert_eq!(Ending::infer(b"a\rb\r\n"), Some(Ending::CrLf));
+        assert_eq!(Ending::infer(b"abc"), None);
+        assert_eq!("CRLF".parse::<Ending>().ok(), Some(Ending::CrLf));
+        assert!("crcr".parse::<Ending>().is_err());
+    }
+
+    #[test]
+    fn report_line_endings() {
+        let mut reader = Cursor::new(b"a\r\nb\nc\r\n\r\n\r\n".to_vec());
+        let mut writer = Cursor::new(vec![0; 0]);
+        let reporter = reporter::FileReporter::new("f.txt");
+        let result = process(&mut reader, &mut writer, |reader, writer| {
+            reporter.report_line_endings(reader, writer, None, true)
+        });
+        assert_eq!(result.ok(), Some(2));
+        assert_eq!(
+            String::from_utf8_lossy(&writer.into_inner()),
+            "Unexpected line ending detected, File: f.txt, Line: 2, Expected: CRLF, Found: LF\n\
+             Extra final newlines detected, File: f.txt, Line: 4\n"
+        );
+        let mut writer = Cursor::new(vec![0; 0]);
+        let result = process(&mut Cursor::new(b"a\nb"), &mut writer, |reader, writer| {
+            reporter.report_line_endings(reader, writer, Some(endings::Ending::Lf), true)
+        });
+        assert_eq!(result.ok(), Some(1));
+        assert_eq!(
+            String::from_utf8_lossy(&writer.into_inner()),
+            "Missing final newline detected, File: f.txt, Line: 2\n"
+        );
+    }
+
+    #[test]
+    fn convert_line_endings() {
+        let convert = |text: &[u8], ending, final_newline| {
+            let mut writer = Cursor::new(vec![0; 0]);
+            process(&mut Cursor::new(text), &mut writer, |reader, writer| {
+                transformations::convert_line_endings(reader, writer, ending, final_newline)
+            })
+            .unwrap();
+            String::from_utf8(writer.into_inner()).unwrap()
+        };
+        use endings::Ending;
+        assert_eq!(convert(b"a\r\nb\nc\r\n", None, false), "a\r\nb\r\nc\r\n");
+        assert_eq!(convert(b"a\r\nb\rc", Some(Ending::Lf), false), "a\nb\nc");
+        assert_eq!(convert(b"a\r\nb\rc", Some(Ending::Lf), true), "a\nb\nc\n");
+        assert_eq!(convert(b"a\n\n\r\n", None, true), "a\n");
+        assert_eq!(convert(b"\n\n", None, true), "");
+    }
+
+    #[test]
+    fn trailing_whitespaces_keep_line_endings() {
+        let mut reader = Cursor::new(b"a \r\nb\t\rc  \nd ".to_vec());
+        let mut writer = Cursor::new(vec![0; 0]);
+        let result = process(
+            &mut reader,
+            &mut writer,
+            transformations::remove_trailing_whitespaces,
+        );
+        assert_eq!(result.ok(), Some(8));
+        assert_eq!(writer.into_inner(), b"a\r\nb\rc\nd");
+    }
+
+    #[test]
+    fn detect_encodings() {
+        use encoding::{detect, Detection, Encoding};
+        let detected = |bytes: &[u8]| {
+            let Detection { encoding, bom } = detect(bytes);
+            (encoding, bom)
+        };
+        assert_eq!(detected(b""), (Encoding::Utf8, false));
+        assert_eq!(detected("ä\n".as_bytes()), (Encoding::Utf8, false));
+        assert_eq!(detected(b"\xEF\xBB\xBFa"), (Encoding::Utf8, true));
+        assert_eq!(detected(b"\xFF\xFEa\0"), (Encoding::Utf16Le, true));
+        assert_eq!(detected(b"\xFE\xFF\0a"), (Encoding::Utf16Be, true));
+        assert_eq!(detected(b"a\0b\0\xE4\0"), (Encoding::Utf16Le, false));
+        assert_eq!(detected(b"\0a\0b\0\n"), (Encoding::Utf16Be, false));
+        assert_eq!(detected(b"caf\xE9\n"), (Encoding::Latin1, false));
+        assert_eq!("UTF-16LE".parse::<Encoding>().ok(), Some(Encoding::Utf16Le));
+        assert!("ebcdic".parse::<Encoding>().is_err());
+    }
+
+    #[test]
+    fn decode_and_encode() -> anyhow::Result<()> {
+        use encoding::Encoding;
+        let text = "\u{feff}aä€😀\n";
+        for encoding in &[Encoding::Utf8, Encoding::Utf16Le, Encoding::Utf16Be] {
+            let bytes = encoding.encode(text)?;
+            assert!(bytes.starts_with(encoding.bom()));
+            assert_eq!(encoding.decode(&bytes)?, text);
+        }
+        assert_eq!(Encoding::Latin1.decode(b"caf\xE9")?, "café");
+        assert_eq!(Encoding::Latin1.encode("café")?, b"caf\xE9");
+        assert!(Encoding::Latin1.encode("€").is_err());
+        assert!(Encoding::Utf16Le.decode(b"a\0b").is_err());
+        assert!(Encoding::Utf16Le.decode(b"\x00\xD8a\0").is_err());
+        assert!(Encoding::Utf8.decode(b"caf\xE9").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn convert_encodings() {
+        use encoding::Encoding;
+        let convert = |bytes: &[u8], from, to, bom| {
+            let mut writer = Cursor::new(vec![0; 0]);
+            process(&mut Cursor::new(bytes), &mut writer, |reader, writer| {
+                transformations::convert_encoding(reader, writer, from, to, bom)
+            })
+            .map(|_| writer.into_inner())
+        };
+        let utf16 = b"\xFF\xFEc\0a\0f\0\xE9\0".to_vec();
+        assert_eq!(
+            convert(&utf16, None, Encoding::Utf8, false).ok(),
+            Some("café".as_bytes().to_vec())
+        );
+        assert_eq!(
+            convert(b"caf\xE9", None, Encoding::Utf16Le, true).ok(),
+            Some(utf16)
+        );
+        assert_eq!(
+            convert("\u{feff}café".as_bytes(), None, Encoding::Latin1, false).ok(),
+            Some(b"caf\xE9".to_vec())
+        );
+        assert_eq!(
+            convert(b"\xC3\xA9", Some(Encoding::Latin1), Encoding::Utf8, false).ok(),
+            Some("Ã©".as_bytes().to_vec())
+        );
+        assert!(convert(b"a", None, Encoding::Latin1, true).is_err());
+        assert!(convert("€".as_bytes(), None, Encoding::Latin1, false).is_err());
+    }
+
+    #[test]
+    fn report_encodings() {
+        let reporter = reporter::FileReporter::new("f.txt");
+        let report = |bytes: &[u8], bom| {
+            let mut writer = Cursor::new(vec![0; 0]);
+            let result = process(&mut Cursor::new(bytes), &mut writer, |reader, writer| {
+                reporter.report_encoding(reader, writer, encoding::Encoding::Utf8, bom)
+            });
+            (result.ok(), String::from_utf8(writer.into_inner()).unwrap())
+        };
+        assert_eq!(report(b"a\n", false), (Some(0), String::new()));
+        assert_eq!(
+            report(b"\xFF\xFEa\0", false),
+            (
+                Some(2),
+                "Unexpected encoding detected, File: f.txt, Expected: UTF-8, Found: UTF-16LE\n\
+                 Byte order mark detected, File: f.txt\n"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            report(b"a\n", true),
+            (
+                Some(1),
+                "Missing byte order mark detected, File: f.txt\n".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn reports_handle_invalid_utf8() {
+        let mut reader = Cursor::new(b"caf\xE9 \n\xFF\tb\r\n".to_vec());
+        let mut writer = Cursor::new(vec![0; 0]);
+        let reporter = reporter::FileReporter::new("f.txt");
+        let result = process(&mut reader, &mut writer, |reader, writer| {
+            reporter.report_trailing_whitespaces(reader, writer)
+        });
+        assert_eq!(result.ok(), Some(1));
+        let mut reader = Cursor::new(b"caf\xE9 \n\xFF\tb\r\n".to_vec());
+        let result = process(&mut reader, &mut writer, |reader, writer| {
+            reporter.report_tabs(reader, writer)
+        });
+        assert_eq!(result.ok(), Some(1));
+    }
+
+    #[test]
+    fn select_lines() {
+        use selection::{Range, Selection, Unit};
+        let text = b"1\n2\n3\n4\n5";
+        let select = |selection| {
+            let mut writer = Cursor::new(vec![0; 0]);
+            let result = process(&mut Cursor::new(text), &mut writer, |reader, writer| {
+                selection::select(reader, writer, selection, Unit::Lines)
+            });
+            assert!(result.is_ok());
+            String::from_utf8(writer.into_inner()).unwrap()
+        };
+        assert_eq!(select(Selection::Head(2)), "1\n2\n");
+        assert_eq!(select(Selection::Tail(2)), "4\n5");
+        assert_eq!(select(Selection::Tail(0)), "");
+        assert_eq!(select(Selection::Tail(usize::MAX)), "1\n2\n3\n4\n5");
+        assert_eq!(select(Selection::Skip(3)), "4\n5");
+        assert_eq!(select(Selection::Range("1:3".parse().unwrap())), "2\n3\n");
+        assert_eq!(select(Selection::Range("3:".parse().unwrap())), "4\n5");
+        assert_eq!(
+            select(Selection::Range(Range {
+                start: 4,
+                end: Some(10)
+            })),
+            "5"
+        );
+    }
+
+    #[test]
+    fn select_bytes() {
+        use selection::{Selection, Unit};
+        let select = |selection| {
+            let mut writer = Cursor::new(vec![0; 0]);
+            let data = (0u8..=255).cycle().take(20_000).collect::<Vec<u8>>();
+            let result = process(&mut Cursor::new(data), &mut writer, |reader, writer| {
+                selection::select(reader, writer, selection, Unit::Bytes)
+            });
+            let selected = writer.into_inner();
+            assert_eq!(result.ok(), Some(selected.len()));
+            selected
+        };
+        assert_eq!(select(Selection::Head(3)), [0, 1, 2]);
+        assert_eq!(select(Selection::Tail(2)), [30, 31]);
+        assert_eq!(select(Selection::Tail(usize::MAX)).len(), 20_000);
+        assert_eq!(select(Selection::Skip(19_998)), [30, 31]);
+        assert_eq!(select(Selection::Range(":2".parse().unwrap())), [0, 1]);
+        assert_eq!(select(Selection::Range("256:258".parse().unwrap())), [0, 1]);
+    }
+
+    #[test]
+    fn parse_ranges() {
+        use selection::Range;
+        assert_eq!(
+            ":".parse::<Range>().unwrap(),
+            Range {
+                start: 0,
+                end: None
+            }
+        );
+        assert!("10".parse::<Range>().is_err());
+        assert!("20:10".parse::<Range>().is_err());
+        assert!("a:10".parse::<Range>().is_err());
+    }
+
+    mod inplace {
+        use super::super::inplace::{apply, Preserve};
+        use std::io::{Read, Write};
+        use std::time::{Duration, SystemTime};
+
+        /// A file in a fresh directory, which is removed with the returned guard.
+        fn file(content: &[u8]) -> (tempfile::TempDir, std::path::PathBuf) {
+            let directory = tempfile::tempdir().unwrap();
+            let path = directory.path().join("file.txt");
+            std::fs::write(&path, content).unwrap();
+            (directory, path)
+        }
+
+        fn upper_case<R: Read, W: Write>(reader: &mut R, writer: &mut W) -> std::io::Result<usize> {
+            let mut text = String::new();
+            reader.read_to_string(&mut text)?;
+            writer.write_all(text.to_uppercase().as_bytes())?;
+            Ok(text.len())
+        }
+
+        #[test]
+        fn file_is_replaced() -> anyhow::Result<()> {
+            let (_directory, path) = file(b"abc\n");
+            let bytes_written = apply(&path, Preserve::default(), upper_case)?;
+            assert_eq!(bytes_written, 4);
+            assert_eq!(std::fs::read(&path)?, b"ABC\n");
+            assert_eq!(std::fs::read_dir(path.parent().unwrap())?.count(), 1);
+            Ok(())
+        }
+
+        #[test]
+        fn original_is_kept_if_processing_fails() -> anyhow::Result<()> {
+            let (_directory, path) = file(b"abc\n");
+            let result = apply(&path, Preserve::default(), |_, writer| {
+                writer.write_all(b"partial")?;
+                Err(std::io::Error::other("failed"))
+            });
+            assert!(result.is_err());
+            assert_eq!(std::fs::read(&path)?, b"abc\n");
+            assert_eq!(std::fs::read_dir(path.parent().unwrap())?.count(), 1);
+            Ok(())
+        }
+
+        #[test]
+        fn attributes_are_preserved_on_demand() -> anyhow::Result<()> {
+            let (_directory, path) = file(b"abc\n");
+            let modified = SystemTime::now() - Duration::from_secs(3600);
+            std::fs::File::options()
+                .write(true)
+                .open(&path)?
+                .set_modified(modified)?;
+            let mut permissions = std::fs::metadata(&path)?.permissions();
+            permissions.set_readonly(true);
+            std::fs::set_permissions(&path, permissions)?;
+
+            let preserve = Preserve {
+                permissions: true,
+                times: true,
+            };
+            apply(&path, preserve, upper_case)?;
+            let metadata = std::fs::metadata(&path)?;
+            assert!(metadata.permissions().readonly());
+            assert_eq!(metadata.modified()?, modified);
+            assert_eq!(std::fs::read(&path)?, b"ABC\n");
+
+            let (_directory, path) = file(b"abc\n");
+            std::fs::File::options()
+                .write(true)
+                .open(&path)?
+                .set_modified(modified)?;
+            apply(&path, Preserve::default(), upper_case)?;
+            assert!(std::fs::metadata(&path)?.modified()? > modified);
+            Ok(())
+        }
+    }
+
+    mod output {
+        use super::cli::{Mode, Output};
+        use std::io::Write;
+        use tempfile::tempdir;
+
+        fn write(output: anyhow::Result<Output>, data: &[u8]) -> anyhow::Result<()> {
+            let mut output = output?;
+            output.write_all(data)?;
+            output.finish()?;
+            Ok(())
+        }
+
+        #[test]
+        fn paths_are_created_and_truncated() -> anyhow::Result<()> {
+            let directory = tempdir()?;
+            let path = directory.path().join("out.txt");
+            write(path.to_str().unwrap().parse(), b"first")?;
+            write(path.to_str().unwrap().parse(), b"2nd")?;
+            assert_eq!(std::fs::read(&path)?, b"2nd");
+            Ok(())
+        }
+
+        #[test]
+        fn modes() -> anyhow::Result<()> {
+            let directory = tempdir()?;
+            let path = directory.path().join("out.txt");
+            write(Output::options().mode(Mode::Create).open(&path), b"a")?;
+            assert!(write(Output::options().mode(Mode::Create).open(&path), b"b").is_err());
+            write(Output::options().mode(Mode::Append).open(&path), b"c")?;
+            assert_eq!(std::fs::read(&path)?, b"ac");
+            assert_eq!("append".parse::<Mode>()?, Mode::Append);
+            assert!("overwrite".parse::<Mode>().is_err());
+            Ok(())
+        }
+
+        #[test]
+        fn parents_are_created_on_demand() -> anyhow::Result<()> {
+            let directory = tempdir()?;
+            let path = directory.path().join("a").join("b").join("out.txt");
+            assert!(Output::options().open(&path).is_err());
+            write(Output::options().create_parents(true).open(&path), b"x")?;
+            assert_eq!(std::fs::read(&path)?, b"x");
+            Ok(())
+        }
+
+        #[test]
+        fn atomic_output_replaces_destination_when_finished() -> anyhow::Result<()> {
+            let directory = tempdir()?;
+            let path = directory.path().join("out.txt");
+            std::fs::write(&path, b"old")?;
+
+            let mut output = Output::options().atomic(true).open(&path)?;
+            output.write_all(b"new")?;
+            assert_eq!(std::fs::read(&path)?, b"old");
+            output.finish()?;
+            assert_eq!(std::fs::read(&path)?, b"new");
+
+            let mut output = Output::options()
+                .mode(Mode::Append)
+                .atomic(true)
+                .open(&path)?;
+            output.write_all(b"er")?;
+            drop(output);
+            assert_eq!(std::fs::read(&path)?, b"new");
+            write(
+                Output::options()
+                    .mode(Mode::Append)
+                    .atomic(true)
+                    .open(&path),
+                b"er",
+            )?;
+            assert_eq!(std::fs::read(&path)?, b"newer");
+
+            // no temporary files are left behind
+            assert_eq!(std::fs::read_dir(directory.path())?.count(), 1);
+            Ok(())
+        }
+
+        #[test]
+        fn atomic_output_does_not_replace_files_created_meanwhile() -> anyhow::Result<()> {
+            let directory = tempdir()?;
+            let path = directory.path().join("out.txt");
+
+            let mut output = Output::options()
+                .mode(Mode::Create)
+                .atomic(true)
+                .open(&path)?;
+            output.write_all(b"late")?;
+            std::fs::write(&path, b"early")?;
+            assert!(output.finish().is_err());
+            assert_eq!(std::fs::read(&path)?, b"early");
+            assert_eq!(std::fs::read_dir(directory.path())?.count(), 1);
+
+            std::fs::remove_file(&path)?;
+            write(
+                Output::options()
+                    .mode(Mode::Create)
+                    .atomic(true)
+                    .open(&path),
+                b"new",
+            )?;
+            assert_eq!(std::fs::read(&path)?, b"new");
+            assert_eq!(std::fs::read_dir(directory.path())?.count(), 1);
+            Ok(())
+        }
+    }
+
+    mod walk {
+        use super::super::walk::{self, Walker};
+        use std::io::{Read, Write};
+        use std::path::{Path, PathBuf};
+
+        /// A tree with ignored, hidden and binary files in a fresh directory, which is removed
+        /// with the returned guard.
+        fn tree() -> tempfile::TempDir {
+            let tree = tempfile::tempdir().unwrap();
+            let root = tree.path();
+            let files: &[(&str, &[u8])] = &[
+                (".gitignore", b"target/\n*.log\n"),
+                (".hidden/a.txt", b"a\n"),
+                ("b.rs", b"b\n"),
+                ("build.log", b"log\n"),
+                ("image.png", b"\x89PNG\r\n\x1a\n\x00\x00"),
+                ("src/a.rs", b"a\n"),
+                ("src/c.txt", b"c\n"),
+                ("src/.ignore", b"c.txt\n"),
+                ("target/debug.rs", b"d\n"),
+            ];
+            for (path, content) in files {
+                let path = root.join(path);
+                std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+                std::fs::write(path, content).unwrap();
+            }
+            tree
+        }
+
+        fn relative(root: &Path, files: Vec<PathBuf>) -> Vec<String> {
+            files
+                .iter()
+                .map(|f| f.strip_prefix(root).unwrap_or(f).display().to_string())
+                .collect()
+        }
+
+        #[test]
+        fn ignored_hidden_and_binary_files_are_skipped() -> anyhow::Result<()> {
+            let tree = tree();
+            let root = tree.path();
+            let files = Walker::new(&[root]).files()?;
+            assert_eq!(relative(root, files), vec!["b.rs", "src/a.rs"]);
+            std::fs::write(root.join("utf16.txt"), b"\xFF\xFEa\0\n\0")?;
+            let files = Walker::new(&[root]).utf16(true).files()?;
+            assert_eq!(relative(root, files), vec!["b.rs", "src/a.rs", "utf16.txt"]);
+            std::fs::remove_file(root.join("utf16.txt"))?;
+            let files = Walker::new(&[root]).ignore(false).binary(true).files()?;
+            assert_eq!(
+                relative(root, files),
+                vec![
+                    "b.rs",
+                    "build.log",
+                    "image.png",
+                    "src/a.rs",
+                    "src/c.txt",
+                    "target/debug.rs"
+                ]
+            );
+            Ok(())
+        }
+
+        #[test]
+        fn globs_and_explicit_files() -> anyhow::Result<()> {
+            let tree = tree();
+            let root = tree.path();
+            let files = Walker::new(&[root.join("image.png"), root.to_path_buf()])
+                .glob("*.rs")
+                .glob("!src/**")
+                .files()?;
+            assert_eq!(relative(root, files), vec!["image.png", "b.rs"]);
+            assert!(Walker::new(&[root]).glob("[").files().is_err());
+            Ok(())
+        }
+
+        #[test]
+        fn output_keeps_the_order_of_the_files() -> anyhow::Result<()> {
+            let tree = tree();
+            let root = tree.path();
+            let files: Vec<PathBuf> = (0..32)
+                .map(|i| {
+                    let path = root.join(format!("{}.txt", i));
+                    std::fs::write(&path, format!("{}\n", i)).unwrap();
+                    path
+                })
+                .collect();
+            let mut output = Vec::new();
+            let lines = walk::process(&files, &mut output, |_, reader, writer| {
+                let mut text = String::new();
+                reader.read_to_string(&mut text)?;
+                writer.write_all(text.as_bytes())?;
+                Ok(1)
+            })?;
+            let expected: String = (0..32).map(|i| format!("{}\n", i)).collect();
+            assert_eq!(lines, 32);
+            assert_eq!(String::from_utf8(output)?, expected);
+            assert!(
+                walk::process(&[root.join("missing")], &mut Vec::new(), |_, _, _| Ok(0)).is_err()
+            );
+            Ok(())
+        }
+    }
 }
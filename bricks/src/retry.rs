@@ -0,0 +1,326 @@
+//! Reruns failing commands, the plumbing of the `retry` brick.
+//!
+//! Every attempt is run by a [Supervisor], so it can have a timeout of its own. Between the
+//! attempts the [Backoff] is waited.
+use crate::supervisor::{Outcome, Supervisor};
+use anyhow::{anyhow, Error};
+use regex::bytes::Regex;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::signal::unix::{signal, SignalKind};
+
+/// How the delay between attempts grows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// Always wait the same time.
+    Fixed,
+    /// Double the delay after every attempt.
+    Exponential,
+    /// Wait a random time up to the exponential delay, so clients failing together don't
+    /// retry together.
+    Jittered,
+}
+
+impl FromStr for Strategy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fixed" => Ok(Strategy::Fixed),
+            "exponential" => Ok(Strategy::Exponential),
+            "jittered" => Ok(Strategy::Jittered),
+            _ => Err(anyhow!("Unknown backoff strategy \"{}\"", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Backoff {
+    pub strategy: Strategy,
+    /// The delay after the first attempt.
+    pub delay: Duration,
+    /// The delay never exceeds it.
+    pub max: Duration,
+}
+
+impl Backoff {
+    /// The time to wait after the `attempt`th (starting at 1) attempt failed.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponential = || {
+            let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+            self.delay.saturating_mul(factor)
+        };
+        let delay = match self.strategy {
+            Strategy::Fixed => self.delay,
+            Strategy::Exponential => exponential(),
+            Strategy::Jittered => exponential().min(self.max).mul_f64(rand::random::<f64>()),
+        };
+        delay.min(self.max)
+    }
+}
+
+/// Which failed attempts are retried, every one if neither codes nor patterns are given.
+///
+/// If both are given an attempt must satisfy both. Successful attempts are never retried.
+#[derive(Debug, Clone, Default)]
+pub struct Condition {
+    /// Exit codes to retry, [crate::supervisor::exit_code::TIMED_OUT] for attempts which
+    /// timed out.
+    pub codes: Vec<i32>,
+    /// Patterns of which one must be found in the stdout or stderr of the attempt.
+    pub patterns: Vec<Regex>,
+}
+
+impl Condition {
+    pub fn matches(&self, outcome: &Outcome) -> bool {
+        let code = outcome.exit_code(false);
+        let output = |pattern: &Regex| {
+            pattern.is_match(&outcome.stdout) || pattern.is_match(&outcome.stderr)
+        };
+        code != 0
+            && (self.codes.is_empty() || self.codes.contains(&code))
+            && (self.patterns.is_empty() || self.patterns.iter().any(output))
+    }
+}
+
+/// A command which is rerun until it succeeds, fails for good or runs out of attempts.
+#[derive(Debug, Clone)]
+pub struct Retry {
+    supervisor: Supervisor,
+    attempts: u32,
+    backoff: Backoff,
+    condition: Condition,
+}
+
+impl Retry {
+    /// Runs the command of `supervisor` up to three times, waiting a second after the first
+    /// failure and twice as long after every further one.
+    pub fn new(supervisor: Supervisor) -> Self {
+        Retry {
+            supervisor,
+            attempts: 3,
+            backoff: Backoff {
+                strategy: Strategy::Exponential,
+                delay: Duration::from_secs(1),
+                max: Duration::from_secs(60),
+            },
+            condition: Condition::default(),
+        }
+    }
+
+    /// The maximum number of attempts, including the first one.
+    pub fn attempts(&mut self, attempts: u32) -> &mut Self {
+        self.attempts = attempts.max(1);
+        self
+    }
+
+    pub fn backoff(&mut self, backoff: Backoff) -> &mut Self {
+        self.backoff = backoff;
+        self
+    }
+
+    pub fn condition(&mut self, condition: Condition) -> &mut Self {
+        self.condition = condition;
+        self
+    }
+
+    /// Runs the command until it doesn't need to be retried and returns the last outcome.
+    ///
+    /// `on_retry` is called with the number of the failed attempt, its outcome and the delay
+    /// before the next one. Terminating signals received during an attempt or while waiting
+    /// stop retrying.
+    pub async fn run<F>(&self, mut on_retry: F) -> std::io::Result<Outcome>
+    where
+        F: FnMut(u32, &Outcome, Duration),
+    {
+        let mut supervisor = self.supervisor.clone();
+        supervisor.capture(!self.condition.patterns.is_empty());
+        let mut attempt = 1;
+        loop {
+            let outcome = supervisor.run().await?;
+            // the command failing because it was passed a signal to stop isn't to be retried
+            if outcome.interrupted || attempt >= self.attempts || !self.condition.matches(&outcome)
+            {
+                return Ok(outcome);
+            }
+            let delay = self.backoff.delay(attempt);
+            on_retry(attempt, &outcome, delay);
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {},
+                interrupted = interrupted() => {
+                    interrupted?;
+                    return Ok(outcome);
+                },
+            }
+            attempt += 1;
+        }
+    }
+}
+
+/// Completes once a terminating signal is received. Needed as the signal handlers installed to
+/// forward signals to the command replace the default action of terminating.
+async fn interrupted() -> std::io::Result<()> {
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let mut hangup = signal(SignalKind::hangup())?;
+    tokio::select! {
+        _ = terminate.recv() => {},
+        _ = interrupt.recv() => {},
+        _ = hangup.recv() => {},
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::supervisor::exit_code;
+
+    fn run(retry: &Retry) -> (Outcome, Vec<u32>) {
+        let mut retried = Vec::new();
+        let outcome = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(retry.run(|attempt, _, _| retried.push(attempt)))
+            .unwrap();
+        (outcome, retried)
+    }
+
+    fn backoff(strategy: Strategy) -> Backoff {
+        Backoff {
+            strategy,
+            delay: Duration::from_millis(100),
+            max: Duration::from_millis(500),
+        }
+    }
+
+    #[test]
+    fn delays_grow_up_to_the_maximum() {
+        let delays = |backoff: Backoff| (1..=5).map(|a| backoff.delay(a)).collect::<Vec<_>>();
+        let ms = |ms: &[u64]| {
+            ms.iter()
+                .map(|ms| Duration::from_millis(*ms))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(delays(backoff(Strategy::Fixed)), ms(&[100; 5]));
+        assert_eq!(
+            delays(backoff(Strategy::Exponential)),
+            ms(&[100, 200, 400, 500, 500])
+        );
+        let exponential = delays(backoff(Strategy::Exponential));
+        for (jittered, exponential) in delays(backoff(Strategy::Jittered)).iter().zip(exponential) {
+            assert!(*jittered <= exponential);
+        }
+        assert_eq!(
+            backoff(Strategy::Exponential).delay(u32::MAX),
+            Duration::from_millis(500)
+        );
+    }
+
+    #[test]
+    fn strategies_are_parsed() {
+        assert_eq!(
+            "jittered".parse::<Strategy>().ok(),
+            Some(Strategy::Jittered)
+        );
+        assert!("linear".parse::<Strategy>().is_err());
+    }
+
+    #[test]
+    fn commands_are_retried_until_they_succeed() {
+        let directory = tempfile::tempdir().unwrap();
+        let counter = directory.path().join("counter");
+        let script = format!("echo >> {0}; test $(wc -l < {0}) -ge 3", counter.display());
+        let mut retry = Retry::new(Supervisor::new("sh", &["-c", &script]));
+        retry.attempts(5).backoff(Backoff {
+            delay: Duration::from_millis(10),
+            ..backoff(Strategy::Fixed)
+        });
+        let (outcome, retried) = run(&retry);
+        assert_eq!((outcome.code(), retried), (0, vec![1, 2]));
+    }
+
+    #[test]
+    fn only_matching_failures_are_retried() {
+        let fast = Backoff {
+            delay: Duration::from_millis(10),
+            ..backoff(Strategy::Fixed)
+        };
+        let retry = |script: &str, condition: Condition| {
+            let mut retry = Retry::new(Supervisor::new("sh", &["-c", script]));
+            retry.backoff(fast.clone()).condition(condition);
+            let (outcome, retried) = run(&retry);
+            (outcome.code(), retried)
+        };
+        let codes = |codes: &[i32]| Condition {
+            codes: codes.to_vec(),
+            ..Condition::default()
+        };
+        assert_eq!(retry("exit 2", codes(&[])), (2, vec![1, 2]));
+        assert_eq!(retry("exit 2", codes(&[1, 2])), (2, vec![1, 2]));
+        assert_eq!(retry("exit 3", codes(&[1, 2])), (3, vec![]));
+        let patterns = Condition {
+            patterns: vec![Regex::new("connection (reset|refused)").unwrap()],
+            ..Condition::default()
+        };
+        assert_eq!(
+            retry("echo connection reset >&2; exit 1", patterns.clone()),
+            (1, vec![1, 2])
+        );
+        assert_eq!(
+            retry("echo no space left; exit 1", patterns.clone()),
+            (1, vec![])
+        );
+        assert_eq!(retry("echo connection refused", patterns), (0, vec![]));
+    }
+
+    /// Run by [signals_stop_retrying] in a process of its own, as the signal reaches every
+    /// test running in the process.
+    #[test]
+    #[ignore = "run by signals_stop_retrying"]
+    fn signaled_attempt() {
+        let mut retry = Retry::new(Supervisor::new("sleep", &["3"]));
+        retry.attempts(4).backoff(Backoff {
+            delay: Duration::from_millis(10),
+            ..backoff(Strategy::Fixed)
+        });
+        let (outcome, retried) = run(&retry);
+        assert!(outcome.interrupted);
+        assert_eq!((outcome.code(), retried), (128 + libc::SIGTERM, vec![]));
+    }
+
+    #[test]
+    fn signals_stop_retrying() {
+        let start = std::time::Instant::now();
+        let mut test = std::process::Command::new(std::env::current_exe().unwrap())
+            .args(["retry::tests::signaled_attempt", "--exact", "--ignored"])
+            .stdout(std::process::Stdio::null())
+            .spawn()
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(500));
+        unsafe { libc::kill(test.id() as libc::pid_t, libc::SIGTERM) };
+        assert!(test.wait().unwrap().success());
+        assert!(start.elapsed() < Duration::from_secs(3));
+    }
+
+    #[test]
+    fn attempts_can_time_out() {
+        let mut supervisor = Supervisor::new("sleep", &["10"]);
+        supervisor.timeout(Some(Duration::from_millis(50)));
+        let mut retry = Retry::new(supervisor);
+        retry
+            .attempts(2)
+            .backoff(Backoff {
+                delay: Duration::from_millis(10),
+                ..backoff(Strategy::Fixed)
+            })
+            .condition(Condition {
+                codes: vec![exit_code::TIMED_OUT],
+                ..Condition::default()
+            });
+        let (outcome, retried) = run(&retry);
+        assert_eq!(
+            (outcome.exit_code(false), retried),
+            (exit_code::TIMED_OUT, vec![1])
+        );
+    }
+}
@@ -0,0 +1,803 @@
+//! Checks (and fixes) for files, run by one [Runner] and reported in several [Format]s.
+//!
+//! A [Rule] either looks at single lines or at a whole file, rules which know how to repair
+//! what they found also provide a fix.
+use crate::editorconfig::Resolver;
+use crate::encoding;
+use crate::endings::{self, Ending, Issue};
+use crate::indentation::{is_blank, Indentation};
+use crate::inplace::{self, Preserve};
+use crate::transformations;
+use crate::walk;
+use anyhow::{anyhow, Error};
+use serde::Serialize;
+use serde_json::json;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// A violation of a rule within a file, lines and columns start at 1.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Finding {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+pub trait Rule: Send + Sync {
+    /// Identifies the rule on the command line and in reports, e.g. `trailing-whitespace`.
+    fn id(&self) -> &'static str;
+
+    fn description(&self) -> &'static str;
+
+    /// Checks a line (without its line ending), returns the column and message of a violation.
+    fn check_line(&self, _line: &str) -> Option<(usize, String)> {
+        None
+    }
+
+    /// Checks a whole file, by default every line is checked on its own.
+    fn check(&self, text: &str) -> Vec<Finding> {
+        text.lines()
+            .enumerate()
+            .filter_map(|(index, line)| {
+                self.check_line(line).map(|(column, message)| Finding {
+                    line: index + 1,
+                    column,
+                    message,
+                })
+            })
+            .collect()
+    }
+
+    /// The fixed `text`, `None` if the rule can't fix its violations.
+    fn fix(&self, _text: &str) -> Option<String> {
+        None
+    }
+}
+
+/// Applies a transformation of [crate::transformations] to `text`.
+fn transform<'t, F>(text: &'t str, mut transformation: F) -> Option<String>
+where
+    F: FnMut(&mut &'t [u8], &mut Vec<u8>) -> std::io::Result<usize>,
+{
+    let mut output = Vec::new();
+    transformation(&mut text.as_bytes(), &mut output).ok()?;
+    String::from_utf8(output).ok()
+}
+
+/// Columns count characters, not bytes.
+fn column_of(line: &str, byte_offset: usize) -> usize {
+    line[..byte_offset].chars().count() + 1
+}
+
+pub struct TrailingWhitespace;
+
+impl Rule for TrailingWhitespace {
+    fn id(&self) -> &'static str {
+        "trailing-whitespace"
+    }
+
+    fn description(&self) -> &'static str {
+        "Lines must not end with whitespace"
+    }
+
+    fn check_line(&self, line: &str) -> Option<(usize, String)> {
+        let content = line.trim_end();
+        match content.len() < line.len() {
+            true => Some((
+                column_of(line, content.len()),
+                "Trailing whitespace".to_string(),
+            )),
+            false => None,
+        }
+    }
+
+    fn fix(&self, text: &str) -> Option<String> {
+        transform(text, transformations::remove_trailing_whitespaces)
+    }
+}
+
+/// Tabs are replaced by spaces up to the next tab stop, every `width` columns.
+pub struct Tabs {
+    pub width: usize,
+}
+
+impl Rule for Tabs {
+    fn id(&self) -> &'static str {
+        "tabs"
+    }
+
+    fn description(&self) -> &'static str {
+        "Lines must not contain tabs"
+    }
+
+    fn check_line(&self, line: &str) -> Option<(usize, String)> {
+        line.find('\t')
+            .map(|offset| (column_of(line, offset), "Tab".to_string()))
+    }
+
+    fn fix(&self, text: &str) -> Option<String> {
+        transform(text, |reader, writer| {
+            transformations::expand_tabs(reader, writer, self.width)
+        })
+    }
+}
+
+/// Lines have to be indented like most lines of their file (see [Indentation::infer]), or
+/// like the given `indentation`.
+pub struct MixedIndentation {
+    pub indentation: Option<Indentation>,
+}
+
+impl Rule for MixedIndentation {
+    fn id(&self) -> &'static str {
+        "mixed-indentation"
+    }
+
+    fn description(&self) -> &'static str {
+        "Lines must be indented like the rest of their file"
+    }
+
+    fn check(&self, text: &str) -> Vec<Finding> {
+        let (indentation, issue) = match (self.indentation, Indentation::infer(text.as_bytes())) {
+            (Some(indentation), _) => (indentation, "Unexpected indentation"),
+            (None, Some(indentation)) => (indentation, "Mixed indentation"),
+            (None, None) => return Vec::new(),
+        };
+        text.lines()
+            .enumerate()
+            .filter(|(_, line)| !is_blank(line.as_bytes()) && !indentation.matches(line.as_bytes()))
+            .map(|(index, _)| Finding {
+                line: index + 1,
+                column: 1,
+                message: format!("{}, expected {}", issue, indentation),
+            })
+            .collect()
+    }
+
+    fn fix(&self, text: &str) -> Option<String> {
+        let style = self.indentation.map(|i| i.style);
+        let width = self.indentation.map(|i| i.width);
+        transform(text, |reader, writer| {
+            transformations::normalize_indentation(reader, writer, style, width)
+        })
+    }
+}
+
+/// Lines have to end like most lines of their file, or with the given `ending`.
+pub struct LineEndings {
+    pub ending: Option<Ending>,
+}
+
+impl Rule for LineEndings {
+    fn id(&self) -> &'static str {
+        "line-endings"
+    }
+
+    fn description(&self) -> &'static str {
+        "Lines must end alike"
+    }
+
+    fn check(&self, text: &str) -> Vec<Finding> {
+        endings_findings(text, endings::check(text.as_bytes(), self.ending, false))
+    }
+
+    fn fix(&self, text: &str) -> Option<String> {
+        transform(text, |reader, writer| {
+            transformations::convert_line_endings(reader, writer, self.ending, false)
+        })
+    }
+}
+
+/// Files have to end with exactly one line ending.
+pub struct FinalNewline;
+
+impl Rule for FinalNewline {
+    fn id(&self) -> &'static str {
+        "final-newline"
+    }
+
+    fn description(&self) -> &'static str {
+        "Files must end with exactly one newline"
+    }
+
+    fn check(&self, text: &str) -> Vec<Finding> {
+        let issues = endings::check(text.as_bytes(), None, true)
+            .into_iter()
+            .filter(|(_, issue)| !matches!(issue, Issue::Unexpected { .. }))
+            .collect();
+        endings_findings(text, issues)
+    }
+
+    fn fix(&self, text: &str) -> Option<String> {
+        transform(text, transformations::ensure_final_newline)
+    }
+}
+
+/// Issues are located at the line ending, or the start of superfluous empty lines.
+fn endings_findings(text: &str, issues: Vec<(usize, Issue)>) -> Vec<Finding> {
+    let lines: Vec<_> = endings::lines(text.as_bytes()).collect();
+    issues
+        .into_iter()
+        .map(|(line, issue)| {
+            let column = match issue {
+                Issue::ExtraFinalNewlines => 1,
+                _ => String::from_utf8_lossy(lines[line - 1].0).chars().count() + 1,
+            };
+            Finding {
+                line,
+                column,
+                message: issue.to_string(),
+            }
+        })
+        .collect()
+}
+
+/// Files have to be encoded in `charset`, only UTF-8 with or without byte order mark
+/// (`utf-8-bom`) is checked.
+pub struct Charset {
+    pub charset: String,
+}
+
+impl Rule for Charset {
+    fn id(&self) -> &'static str {
+        "charset"
+    }
+
+    fn description(&self) -> &'static str {
+        "Files must be encoded in the configured character set"
+    }
+
+    fn check(&self, text: &str) -> Vec<Finding> {
+        let message = match (self.charset.as_str(), text.starts_with(BOM)) {
+            ("utf-8", true) => "Unexpected byte order mark",
+            ("utf-8-bom", false) => "Missing byte order mark",
+            _ => return Vec::new(),
+        };
+        vec![Finding {
+            line: 1,
+            column: 1,
+            message: message.to_string(),
+        }]
+    }
+
+    fn fix(&self, text: &str) -> Option<String> {
+        match (self.charset.as_str(), text.strip_prefix(BOM)) {
+            ("utf-8", Some(rest)) => Some(rest.to_string()),
+            ("utf-8-bom", None) => Some(format!("{}{}", BOM, text)),
+            _ => None,
+        }
+    }
+}
+
+const BOM: char = '\u{feff}';
+
+/// Lines must not be longer than `max` columns, tabs reach up to the next tab stop.
+pub struct MaxLineLength {
+    pub max: usize,
+    pub tab_width: usize,
+}
+
+impl Rule for MaxLineLength {
+    fn id(&self) -> &'static str {
+        "max-line-length"
+    }
+
+    fn description(&self) -> &'static str {
+        "Lines must not exceed the configured length"
+    }
+
+    fn check_line(&self, line: &str) -> Option<(usize, String)> {
+        let mut columns = 0;
+        for (index, c) in line.chars().enumerate() {
+            columns = match c {
+                '\t' => columns + self.tab_width - columns % self.tab_width,
+                _ => columns + 1,
+            };
+            if columns > self.max {
+                return Some((index + 1, format!("Line longer than {} columns", self.max)));
+            }
+        }
+        None
+    }
+}
+
+/// All rules, configured with their defaults.
+pub fn all() -> Vec<Box<dyn Rule>> {
+    vec![
+        Box::new(TrailingWhitespace),
+        Box::new(Tabs { width: 4 }),
+        Box::new(MixedIndentation { indentation: None }),
+        Box::new(LineEndings { ending: None }),
+        Box::new(FinalNewline),
+    ]
+}
+
+/// A [Finding] of a rule within a file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    pub path: String,
+    pub rule: &'static str,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+/// The outcome of running the rules over a set of files.
+#[derive(Debug, Default, Serialize)]
+pub struct Report {
+    pub files: usize,
+    /// Files which have been changed by fixes.
+    pub fixed: Vec<String>,
+    /// What is left after fixing.
+    pub diagnostics: Vec<Diagnostic>,
+    /// Files which couldn't be processed and why.
+    pub errors: Vec<(String, String)>,
+}
+
+impl Report {
+    /// Like with `--check`: 0 if all files are fine, 1 if there are diagnostics and 2 on errors.
+    pub fn exit_code(&self) -> i32 {
+        match (self.errors.is_empty(), self.diagnostics.is_empty()) {
+            (false, _) => 2,
+            (true, false) => 1,
+            (true, true) => 0,
+        }
+    }
+
+    pub fn write<W: Write + ?Sized>(
+        &self,
+        format: Format,
+        rules: &[Box<dyn Rule>],
+        writer: &mut W,
+    ) -> std::io::Result<()> {
+        match format {
+            Format::Human => self.write_human(writer),
+            Format::Json => writeln!(writer, "{}", serde_json::to_string_pretty(self)?),
+            Format::Sarif => writeln!(
+                writer,
+                "{}",
+                serde_json::to_string_pretty(&self.sarif(rules))?
+            ),
+            Format::Github => self.write_github(writer),
+        }
+    }
+
+    fn write_human<W: Write + ?Sized>(&self, writer: &mut W) -> std::io::Result<()> {
+        for path in &self.fixed {
+            writeln!(writer, "{}: fixed", path)?;
+        }
+        for d in &self.diagnostics {
+            writeln!(
+                writer,
+                "{}:{}:{}: {} [{}]",
+                d.path, d.line, d.column, d.message, d.rule
+            )?;
+        }
+        for (path, error) in &self.errors {
+            writeln!(writer, "{}: {}", path, error)?;
+        }
+        writeln!(
+            writer,
+            "{} issue(s), {} file(s) checked, {} fixed, {} error(s)",
+            self.diagnostics.len(),
+            self.files,
+            self.fixed.len(),
+            self.errors.len()
+        )
+    }
+
+    /// See [workflow commands](https://docs.github.com/en/actions/reference/workflow-commands-for-github-actions).
+    fn write_github<W: Write + ?Sized>(&self, writer: &mut W) -> std::io::Result<()> {
+        for d in &self.diagnostics {
+            writeln!(
+                writer,
+                "::error file={},line={},col={},title={}::{}",
+                escape_property(&d.path),
+                d.line,
+                d.column,
+                escape_property(d.rule),
+                escape_data(&d.message)
+            )?;
+        }
+        for (path, error) in &self.errors {
+            writeln!(
+                writer,
+                "::error file={}::{}",
+                escape_property(path),
+                escape_data(error)
+            )?;
+        }
+        Ok(())
+    }
+
+    /// A [SARIF](https://docs.oasis-open.org/sarif/sarif/v2.1.0/sarif-v2.1.0.html) log.
+    fn sarif(&self, rules: &[Box<dyn Rule>]) -> serde_json::Value {
+        let rules: Vec<serde_json::Value> = rules
+            .iter()
+            .map(|rule| {
+                json!({
+                    "id": rule.id(),
+                    "shortDescription": { "text": rule.description() },
+                })
+            })
+            .collect();
+        let results: Vec<serde_json::Value> = self
+            .diagnostics
+            .iter()
+            .map(|d| {
+                json!({
+                    "ruleId": d.rule,
+                    "level": "error",
+                    "message": { "text": d.message },
+                    "locations": [{
+                        "physicalLocation": {
+                            "artifactLocation": { "uri": d.path },
+                            "region": { "startLine": d.line, "startColumn": d.column },
+                        }
+                    }],
+                })
+            })
+            .collect();
+        json!({
+            "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+            "version": "2.1.0",
+            "runs": [{
+                "tool": {
+                    "driver": {
+                        "name": "bricks",
+                        "version": env!("CARGO_PKG_VERSION"),
+                        "rules": rules,
+                    }
+                },
+                "results": results,
+            }],
+        })
+    }
+}
+
+fn escape_data(text: &str) -> String {
+    text.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn escape_property(text: &str) -> String {
+    escape_data(text).replace(':', "%3A").replace(',', "%2C")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Human,
+    Json,
+    Sarif,
+    /// Annotations of GitHub Actions.
+    Github,
+}
+
+impl FromStr for Format {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(Format::Human),
+            "json" => Ok(Format::Json),
+            "sarif" => Ok(Format::Sarif),
+            "github" => Ok(Format::Github),
+            format => Err(anyhow!(
+                "Unknown format \"{}\", expected human, json, sarif or github",
+                format
+            )),
+        }
+    }
+}
+
+/// Applies a set of rules to files.
+pub struct Runner {
+    rules: Vec<Box<dyn Rule>>,
+    editorconfig: Option<Resolver>,
+}
+
+impl Runner {
+    pub fn new(rules: Vec<Box<dyn Rule>>) -> Self {
+        Runner {
+            rules,
+            editorconfig: None,
+        }
+    }
+
+    /// Only the rules whose ids are listed, unknown ids are an error.
+    pub fn select(ids: &[String]) -> Result<Self, Error> {
+        let mut rules = all();
+        if let Some(unknown) = ids.iter().find(|id| !rules.iter().any(|r| r.id() == *id)) {
+            return Err(anyhow!("Unknown rule \"{}\"", unknown));
+        }
+        rules.retain(|rule| ids.iter().any(|id| id == rule.id()));
+        Ok(Runner::new(rules))
+    }
+
+    /// Run the rules enforcing the EditorConfig properties of every file (see
+    /// [crate::editorconfig::rules]) instead of the given ones.
+    pub fn editorconfig(&mut self, resolver: Resolver) -> &mut Self {
+        self.editorconfig = Some(resolver);
+        self
+    }
+
+    pub fn rules(&self) -> &[Box<dyn Rule>] {
+        &self.rules
+    }
+
+    /// Diagnostics of all rules, ordered by their location.
+    pub fn check(&self, path: &str, text: &str) -> Vec<Diagnostic> {
+        check(&self.rules, path, text)
+    }
+
+    /// Applies the fixes of all rules one after another.
+    pub fn fix(&self, text: &str) -> String {
+        fix(&self.rules, text)
+    }
+
+    /// Checks `paths` in parallel, with `fix` they are fixed in place and only what's left is
+    /// reported. The report keeps the order of `paths`.
+    ///
+    /// Files are decoded in their detected encoding (see [encoding::detect]), fixed files keep
+    /// it.
+    pub fn run<P: AsRef<Path>>(&self, paths: &[P], fix: bool) -> Report {
+        let mut report = Report::default();
+        let paths: Vec<PathBuf> = paths.iter().map(|p| p.as_ref().to_path_buf()).collect();
+        let results = walk::parallel(&paths, |path| self.run_file(path, fix));
+        for (path, result) in paths.iter().zip(results) {
+            let name = path.display().to_string();
+            report.files += 1;
+            match result {
+                Ok((fixed, diagnostics)) => {
+                    if fixed {
+                        report.fixed.push(name);
+                    }
+                    report.diagnostics.extend(diagnostics);
+                }
+                Err(error) => report.errors.push((name, error.to_string())),
+            }
+        }
+        report
+    }
+
+    fn run_file(&self, path: &Path, fix: bool) -> Result<(bool, Vec<Diagnostic>), Error> {
+        let name = path.display().to_string();
+        let configured;
+        let rules = match &self.editorconfig {
+            Some(resolver) => {
+                configured = crate::editorconfig::rules(&resolver.properties(path)?);
+                &configured
+            }
+            None => &self.rules,
+        };
+        let bytes = std::fs::read(path)?;
+        let encoding = encoding::detect(&bytes).encoding;
+        let text = encoding.decode(&bytes)?;
+        if !fix {
+            return Ok((false, check(rules, &name, &text)));
+        }
+        let fixed = self::fix(rules, &text);
+        if fixed != text {
+            let preserve = Preserve {
+                permissions: true,
+                times: false,
+            };
+            let encoded = encoding.encode(&fixed)?;
+            inplace::apply(path, preserve, |_, writer| {
+                writer.write_all(&encoded)?;
+                Ok(encoded.len())
+            })?;
+        }
+        Ok((fixed != text, check(rules, &name, &fixed)))
+    }
+}
+
+fn check(rules: &[Box<dyn Rule>], path: &str, text: &str) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> = rules
+        .iter()
+        .flat_map(|rule| {
+            rule.check(text).into_iter().map(move |finding| Diagnostic {
+                path: path.to_string(),
+                rule: rule.id(),
+                line: finding.line,
+                column: finding.column,
+                message: finding.message,
+            })
+        })
+        .collect();
+    diagnostics.sort_by_key(|d| (d.line, d.column));
+    diagnostics
+}
+
+fn fix(rules: &[Box<dyn Rule>], text: &str) -> String {
+    rules.iter().fold(text.to_string(), |text, rule| {
+        rule.fix(&text).unwrap_or(text)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn runner() -> Runner {
+        Runner::new(all())
+    }
+
+    #[test]
+    fn check_lines_and_files() {
+        let text = "fn main() {\n    let a = 1; \n\tlet b\t= 2;\n}\n";
+        let diagnostics = runner().check("main.rs", text);
+        let found: Vec<(&str, usize, usize)> = diagnostics
+            .iter()
+            .map(|d| (d.rule, d.line, d.column))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("trailing-whitespace", 2, 15),
+                ("tabs", 3, 1),
+                ("mixed-indentation", 3, 1),
+            ]
+        );
+        assert_eq!(
+            diagnostics[2].message,
+            "Mixed indentation, expected 4 spaces"
+        );
+    }
+
+    #[test]
+    fn fix_applies_all_rules() {
+        let runner = runner();
+        let fixed = runner.fix("a \n\tb\t|\n    c\n");
+        assert_eq!(fixed, "a\n    b   |\n    c\n");
+        assert!(runner.check("f", &fixed).is_empty());
+    }
+
+    #[test]
+    fn line_endings_and_final_newlines() {
+        let text = "a\r\nbc\nd\r\n\r\n";
+        let runner = Runner::new(vec![
+            Box::new(LineEndings { ending: None }),
+            Box::new(FinalNewline),
+        ]);
+        let found: Vec<(&str, usize, usize, String)> = runner
+            .check("f", text)
+            .into_iter()
+            .map(|d| (d.rule, d.line, d.column, d.message))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    "line-endings",
+                    2,
+                    3,
+                    "Unexpected line ending LF, expected CRLF".to_string()
+                ),
+                ("final-newline", 4, 1, "Extra final newlines".to_string()),
+            ]
+        );
+        assert_eq!(runner.fix(text), "a\r\nbc\r\nd\r\n");
+        assert_eq!(
+            FinalNewline.check("a\nb")[0].message,
+            "Missing final newline"
+        );
+        assert_eq!(FinalNewline.fix("a\nb\r\n\n").unwrap(), "a\nb\n");
+    }
+
+    #[test]
+    fn charset_and_max_line_length() {
+        let utf8 = Charset {
+            charset: "utf-8".to_string(),
+        };
+        let bom = Charset {
+            charset: "utf-8-bom".to_string(),
+        };
+        assert_eq!(
+            utf8.check("\u{feff}a")[0].message,
+            "Unexpected byte order mark"
+        );
+        assert_eq!(utf8.fix("\u{feff}a").unwrap(), "a");
+        assert!(bom.check("\u{feff}a").is_empty());
+        assert_eq!(bom.fix("a").unwrap(), "\u{feff}a");
+        let rule = MaxLineLength {
+            max: 8,
+            tab_width: 4,
+        };
+        assert_eq!(rule.check_line("\tabcd"), None);
+        assert_eq!(
+            rule.check_line("\tabcde"),
+            Some((6, "Line longer than 8 columns".to_string()))
+        );
+    }
+
+    #[test]
+    fn select_rules() {
+        let runner = Runner::select(&["tabs".to_string()]).unwrap();
+        assert_eq!(runner.rules().len(), 1);
+        assert!(runner.check("f", "a \n").is_empty());
+        assert!(Runner::select(&["unknown".to_string()]).is_err());
+    }
+
+    fn report() -> Report {
+        Report {
+            files: 2,
+            fixed: vec![],
+            diagnostics: runner().check("src/a,b.rs", "x \n"),
+            errors: vec![("c.bin".to_string(), "Not UTF-8 encoded".to_string())],
+        }
+    }
+
+    fn written(format: Format) -> String {
+        let mut output = Vec::new();
+        report()
+            .write(format, runner().rules(), &mut output)
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn exit_codes() {
+        let mut report = report();
+        assert_eq!(report.exit_code(), 2);
+        report.errors.clear();
+        assert_eq!(report.exit_code(), 1);
+        report.diagnostics.clear();
+        assert_eq!(report.exit_code(), 0);
+    }
+
+    #[test]
+    fn human_format() {
+        assert_eq!(
+            written(Format::Human),
+            "src/a,b.rs:1:2: Trailing whitespace [trailing-whitespace]\n\
+             c.bin: Not UTF-8 encoded\n\
+             1 issue(s), 2 file(s) checked, 0 fixed, 1 error(s)\n"
+        );
+    }
+
+    #[test]
+    fn github_format() {
+        assert_eq!(
+            written(Format::Github),
+            "::error file=src/a%2Cb.rs,line=1,col=2,title=trailing-whitespace::Trailing whitespace\n\
+             ::error file=c.bin::Not UTF-8 encoded\n"
+        );
+    }
+
+    #[test]
+    fn json_and_sarif_formats() {
+        let json: serde_json::Value = serde_json::from_str(&written(Format::Json)).unwrap();
+        assert_eq!(json["diagnostics"][0]["rule"], "trailing-whitespace");
+        assert_eq!(json["errors"][0][0], "c.bin");
+
+        let sarif: serde_json::Value = serde_json::from_str(&written(Format::Sarif)).unwrap();
+        let run = &sarif["runs"][0];
+        assert_eq!(run["tool"]["driver"]["rules"].as_array().unwrap().len(), 5);
+        let location = &run["results"][0]["locations"][0]["physicalLocation"];
+        assert_eq!(location["artifactLocation"]["uri"], "src/a,b.rs");
+        assert_eq!(location["region"]["startColumn"], 2);
+    }
+
+    #[test]
+    fn run_fixes_files_in_place() -> anyhow::Result<()> {
+        let directory = tempfile::tempdir()?;
+        let path = directory.path().join("file.txt");
+        std::fs::write(&path, "a \n")?;
+        let runner = runner();
+
+        let report = runner.run(&[&path], false);
+        assert_eq!((report.diagnostics.len(), report.exit_code()), (1, 1));
+        let report = runner.run(&[&path], true);
+        assert_eq!((report.fixed.len(), report.exit_code()), (1, 0));
+        assert_eq!(std::fs::read_to_string(&path)?, "a\n");
+        std::fs::write(&path, b"\xFF\xFEa\0 \0\n\0")?;
+        let report = runner.run(&[&path], true);
+        assert_eq!((report.fixed.len(), report.exit_code()), (1, 0));
+        assert_eq!(std::fs::read(&path)?, b"\xFF\xFEa\0\n\0");
+        let report = runner.run(&[directory.path().join("missing")], false);
+        assert_eq!(report.exit_code(), 2);
+        Ok(())
+    }
+}
@@ -0,0 +1,560 @@
+//! Runs commands with a deadline, the plumbing of the `timeout` and `retry` bricks.
+//!
+//! Like GNU `timeout`, a command which doesn't finish in time is sent SIGTERM and, if it is
+//! still running after a grace period, SIGKILL. Signals sent to the supervising process are
+//! forwarded to the command. Unless it runs in the foreground, the command gets a process group
+//! of its own and signals reach all processes in it.
+use anyhow::{anyhow, Error};
+use serde_json::json;
+use std::io::Write;
+use std::os::unix::process::ExitStatusExt;
+use std::process::{ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::{Child, Command};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::task::JoinHandle;
+
+/// Exit codes of GNU `timeout`, other codes are the ones of the command.
+pub mod exit_code {
+    pub const TIMED_OUT: i32 = 124;
+    /// The supervisor itself failed.
+    pub const FAILED: i32 = 125;
+    pub const CANNOT_INVOKE: i32 = 126;
+    pub const NOT_FOUND: i32 = 127;
+
+    /// The exit code for a command which couldn't be started.
+    pub fn of_error(error: &std::io::Error) -> i32 {
+        match error.kind() {
+            std::io::ErrorKind::NotFound => NOT_FOUND,
+            std::io::ErrorKind::PermissionDenied => CANNOT_INVOKE,
+            _ => FAILED,
+        }
+    }
+}
+
+/// Parses durations like `1.5s`, `2m`, `1h`, `1d` or `250ms`, plain numbers are seconds.
+pub fn parse_duration(duration: &str) -> Result<Duration, Error> {
+    let split = duration
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(duration.len());
+    let (number, unit) = duration.split_at(split);
+    let seconds = match unit {
+        "" | "s" => 1.0,
+        "ms" => 0.001,
+        "m" => 60.0,
+        "h" => 60.0 * 60.0,
+        "d" => 24.0 * 60.0 * 60.0,
+        _ => {
+            return Err(anyhow!(
+                "Invalid unit \"{}\" in duration \"{}\"",
+                unit,
+                duration
+            ))
+        }
+    };
+    number
+        .parse::<f64>()
+        .ok()
+        .and_then(|n| Duration::try_from_secs_f64(n * seconds).ok())
+        .ok_or_else(|| anyhow!("Invalid duration \"{}\"", duration))
+}
+
+/// Parses sizes in bytes like `512`, `64K`, `512M` or `2G` (binary multiples).
+pub fn parse_size(size: &str) -> Result<u64, Error> {
+    let split = size
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let shift = match unit.to_uppercase().trim_end_matches('B') {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        "T" => 40,
+        _ => return Err(anyhow!("Invalid unit \"{}\" in size \"{}\"", unit, size)),
+    };
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(1 << shift))
+        .ok_or_else(|| anyhow!("Invalid size \"{}\"", size))
+}
+
+/// Resource limits of the command, enforced by the kernel (see `setrlimit(2)`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// Bytes of address space, allocations beyond fail.
+    pub memory: Option<u64>,
+    /// CPU time, the command gets SIGXCPU when it is used up and SIGKILL a second later.
+    pub cpu: Option<Duration>,
+    /// Number of open file descriptors.
+    pub files: Option<u64>,
+}
+
+impl Limits {
+    fn is_empty(&self) -> bool {
+        *self == Limits::default()
+    }
+
+    /// Applies the limits to the current process, to be called between fork and exec.
+    fn apply(&self) -> std::io::Result<()> {
+        let limit = |resource, soft: u64, hard: u64| {
+            let mut current = libc::rlimit {
+                rlim_cur: 0,
+                rlim_max: 0,
+            };
+            // Only async-signal-safe calls, no allocations.
+            unsafe {
+                if libc::getrlimit(resource, &mut current) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                let hard = (hard as libc::rlim_t).min(current.rlim_max);
+                let limit = libc::rlimit {
+                    rlim_cur: (soft as libc::rlim_t).min(hard),
+                    rlim_max: hard,
+                };
+                match libc::setrlimit(resource, &limit) {
+                    0 => Ok(()),
+                    _ => Err(std::io::Error::last_os_error()),
+                }
+            }
+        };
+        if let Some(memory) = self.memory {
+            limit(libc::RLIMIT_AS, memory, memory)?;
+        }
+        if let Some(cpu) = self.cpu {
+            // Whole seconds only, rounded up so a limit is never zero.
+            let seconds = cpu.as_secs() + u64::from(cpu.subsec_nanos() > 0);
+            limit(libc::RLIMIT_CPU, seconds, seconds + 1)?;
+        }
+        if let Some(files) = self.files {
+            limit(libc::RLIMIT_NOFILE, files, files)?;
+        }
+        Ok(())
+    }
+}
+
+/// The resources a command used.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub wall: Duration,
+    pub user: Duration,
+    pub system: Duration,
+    /// Peak resident set size in bytes, the largest of all commands run so far.
+    pub max_rss: u64,
+}
+
+impl Usage {
+    /// Resources used by the terminated and waited for children of this process.
+    fn children() -> Self {
+        let mut usage = std::mem::MaybeUninit::<libc::rusage>::zeroed();
+        let usage = unsafe {
+            libc::getrusage(libc::RUSAGE_CHILDREN, usage.as_mut_ptr());
+            usage.assume_init()
+        };
+        let time = |time: libc::timeval| {
+            Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64)
+        };
+        // Linux reports kilobytes, macOS bytes.
+        let unit = if cfg!(target_os = "macos") { 1 } else { 1024 };
+        Usage {
+            wall: Duration::default(),
+            user: time(usage.ru_utime),
+            system: time(usage.ru_stime),
+            max_rss: usage.ru_maxrss as u64 * unit,
+        }
+    }
+
+    /// The resources used since `before`.
+    fn since(&self, before: &Usage) -> Self {
+        Usage {
+            wall: self.wall,
+            user: self.user.saturating_sub(before.user),
+            system: self.system.saturating_sub(before.system),
+            max_rss: self.max_rss,
+        }
+    }
+
+    pub fn cpu(&self) -> Duration {
+        self.user + self.system
+    }
+}
+
+/// How a command ended.
+#[derive(Debug)]
+pub struct Outcome {
+    pub status: ExitStatus,
+    /// Whether the command was signaled because it didn't finish in time.
+    pub timed_out: bool,
+    /// Whether the supervisor received a terminating signal while the command ran.
+    pub interrupted: bool,
+    /// What the command wrote, only if its output is captured.
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    pub usage: Usage,
+}
+
+impl Outcome {
+    /// The exit code of the command, 128 + the signal number if it was killed by one.
+    pub fn code(&self) -> i32 {
+        self.status
+            .code()
+            .or_else(|| self.status.signal().map(|signal| 128 + signal))
+            .unwrap_or(exit_code::FAILED)
+    }
+
+    /// The exit code to exit with: [exit_code::TIMED_OUT] if the command timed out, unless
+    /// the status of the command shall be preserved anyway.
+    pub fn exit_code(&self, preserve_status: bool) -> i32 {
+        match self.timed_out && !preserve_status {
+            true => exit_code::TIMED_OUT,
+            false => self.code(),
+        }
+    }
+}
+
+/// A command and how it is supervised.
+#[derive(Debug, Clone)]
+pub struct Supervisor {
+    program: String,
+    arguments: Vec<String>,
+    timeout: Option<Duration>,
+    kill_after: Option<Duration>,
+    foreground: bool,
+    capture: bool,
+    limits: Limits,
+}
+
+impl Supervisor {
+    pub fn new<S: AsRef<str>>(program: &str, arguments: &[S]) -> Self {
+        Supervisor {
+            program: program.to_string(),
+            arguments: arguments.iter().map(|a| a.as_ref().to_string()).collect(),
+            timeout: None,
+            kill_after: None,
+            foreground: false,
+            capture: false,
+            limits: Limits::default(),
+        }
+    }
+
+    /// Time the command has before it is sent SIGTERM, no limit if `None` or zero.
+    pub fn timeout(&mut self, timeout: Option<Duration>) -> &mut Self {
+        self.timeout = timeout.filter(|t| !t.is_zero());
+        self
+    }
+
+    /// Time the command has to exit after SIGTERM before it is killed, `None` never kills it.
+    pub fn kill_after(&mut self, kill_after: Option<Duration>) -> &mut Self {
+        self.kill_after = kill_after;
+        self
+    }
+
+    /// Keep the command in the process group of the supervisor, so it can read from the
+    /// terminal and gets its signals. Otherwise the command gets its own process group.
+    pub fn foreground(&mut self, foreground: bool) -> &mut Self {
+        self.foreground = foreground;
+        self
+    }
+
+    /// Record the output of the command (see [Outcome::stdout]), it is still passed on as it
+    /// is written.
+    pub fn capture(&mut self, capture: bool) -> &mut Self {
+        self.capture = capture;
+        self
+    }
+
+    pub fn limits(&mut self, limits: Limits) -> &mut Self {
+        self.limits = limits;
+        self
+    }
+
+    /// A machine readable summary of how the command ended and the resources it used.
+    pub fn summary(&self, outcome: &Outcome) -> serde_json::Value {
+        let seconds = |duration: Option<Duration>| duration.map(|d| d.as_secs_f64());
+        let command: Vec<&String> = std::iter::once(&self.program)
+            .chain(&self.arguments)
+            .collect();
+        json!({
+            "command": command,
+            "code": outcome.code(),
+            "signal": outcome.status.signal(),
+            "timed_out": outcome.timed_out,
+            "wall_time": outcome.usage.wall.as_secs_f64(),
+            "user_time": outcome.usage.user.as_secs_f64(),
+            "system_time": outcome.usage.system.as_secs_f64(),
+            "cpu_time": outcome.usage.cpu().as_secs_f64(),
+            "max_rss": outcome.usage.max_rss,
+            "timeout": seconds(self.timeout),
+            "limits": {
+                "memory": self.limits.memory,
+                "cpu": seconds(self.limits.cpu),
+                "files": self.limits.files,
+            },
+        })
+    }
+
+    /// Runs the command until it exits, errors if it can't be started.
+    pub async fn run(&self) -> std::io::Result<Outcome> {
+        let mut command = Command::new(&self.program);
+        command.args(&self.arguments).kill_on_drop(true);
+        if !self.foreground {
+            command.process_group(0);
+        }
+        if self.capture {
+            command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        }
+        if !self.limits.is_empty() {
+            let limits = self.limits;
+            unsafe { command.pre_exec(move || limits.apply()) };
+        }
+        let before = Usage::children();
+        let start = Instant::now();
+        let mut child = command.spawn()?;
+        let stdout = child
+            .stdout
+            .take()
+            .map(|out| tokio::spawn(tee(out, std::io::stdout)));
+        let stderr = child
+            .stderr
+            .take()
+            .map(|err| tokio::spawn(tee(err, std::io::stderr)));
+        let interrupted = Arc::new(AtomicBool::new(false));
+        let forwarding = self.forward_signals(&child, &interrupted)?;
+        let result = self.wait(&mut child).await;
+        forwarding.iter().for_each(JoinHandle::abort);
+        let (status, timed_out) = result?;
+        let usage = Usage {
+            wall: start.elapsed(),
+            ..Usage::children().since(&before)
+        };
+        Ok(Outcome {
+            status,
+            timed_out,
+            interrupted: interrupted.load(Ordering::SeqCst),
+            stdout: collect(stdout).await?,
+            stderr: collect(stderr).await?,
+            usage,
+        })
+    }
+
+    async fn wait(&self, child: &mut Child) -> std::io::Result<(ExitStatus, bool)> {
+        let timeout = match self.timeout {
+            Some(timeout) => timeout,
+            None => return Ok((child.wait().await?, false)),
+        };
+        if let Ok(status) = tokio::time::timeout(timeout, child.wait()).await {
+            return Ok((status?, false));
+        }
+        self.send(child, libc::SIGTERM);
+        let status = match self.kill_after {
+            Some(grace) => match tokio::time::timeout(grace, child.wait()).await {
+                Ok(status) => status?,
+                Err(_) => {
+                    self.send(child, libc::SIGKILL);
+                    child.wait().await?
+                }
+            },
+            None => child.wait().await?,
+        };
+        Ok((status, true))
+    }
+
+    /// Passes the signals which would terminate the supervisor on to the command and records
+    /// that one was received in `interrupted`. Terminal signals reach a command in the
+    /// foreground on their own.
+    fn forward_signals(
+        &self,
+        child: &Child,
+        interrupted: &Arc<AtomicBool>,
+    ) -> std::io::Result<Vec<JoinHandle<()>>> {
+        let kinds = [
+            (SignalKind::terminate(), true),
+            (SignalKind::hangup(), true),
+            (SignalKind::interrupt(), !self.foreground),
+            (SignalKind::quit(), !self.foreground),
+        ];
+        let target = self.target(child);
+        let mut forwarding = Vec::new();
+        for (kind, forward) in kinds.iter().copied() {
+            let mut signals = signal(kind)?;
+            let interrupted = interrupted.clone();
+            forwarding.push(tokio::spawn(async move {
+                while signals.recv().await.is_some() {
+                    interrupted.store(true, Ordering::SeqCst);
+                    if let (true, Some(target)) = (forward, target) {
+                        unsafe { libc::kill(target, kind.as_raw_value()) };
+                    }
+                }
+            }));
+        }
+        Ok(forwarding)
+    }
+
+    /// Sends `signal` to the child, or its process group, unless it has already been reaped.
+    fn send(&self, child: &Child, signal: libc::c_int) {
+        if let Some(target) = self.target(child) {
+            // The pid can't have been reused, the child is only reaped by waiting for it.
+            unsafe { libc::kill(target, signal) };
+        }
+    }
+
+    /// What to pass to `kill(2)` to reach the child, negative pids are process groups.
+    fn target(&self, child: &Child) -> Option<libc::pid_t> {
+        let pid = child.id()? as libc::pid_t;
+        Some(if self.foreground { pid } else { -pid })
+    }
+}
+
+/// Copies `reader` to `writer` as data arrives and returns everything copied.
+async fn tee<R, W, F>(mut reader: R, writer: F) -> std::io::Result<Vec<u8>>
+where
+    R: AsyncRead + Unpin,
+    W: Write,
+    F: Fn() -> W,
+{
+    let mut copied = Vec::new();
+    let mut buffer = [0u8; 8 * 1024];
+    loop {
+        let read = reader.read(&mut buffer).await?;
+        if read == 0 {
+            return Ok(copied);
+        }
+        let mut writer = writer();
+        writer.write_all(&buffer[..read])?;
+        writer.flush()?;
+        copied.extend_from_slice(&buffer[..read]);
+    }
+}
+
+async fn collect(tee: Option<JoinHandle<std::io::Result<Vec<u8>>>>) -> std::io::Result<Vec<u8>> {
+    match tee {
+        Some(tee) => tee.await?,
+        None => Ok(Vec::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(supervisor: &Supervisor) -> Outcome {
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(supervisor.run())
+            .unwrap()
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("5").ok(), Some(Duration::from_secs(5)));
+        assert_eq!(
+            parse_duration("1.5s").ok(),
+            Some(Duration::from_millis(1500))
+        );
+        assert_eq!(parse_duration("2m").ok(), Some(Duration::from_secs(120)));
+        assert_eq!(
+            parse_duration("250ms").ok(),
+            Some(Duration::from_millis(250))
+        );
+        assert_eq!(parse_duration("1d").ok(), Some(Duration::from_secs(86400)));
+        assert!(parse_duration("1w").is_err());
+        assert!(parse_duration("s").is_err());
+        assert!(parse_duration("-1").is_err());
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("512").ok(), Some(512));
+        assert_eq!(parse_size("64K").ok(), Some(64 * 1024));
+        assert_eq!(parse_size("512m").ok(), Some(512 * 1024 * 1024));
+        assert_eq!(parse_size("2GB").ok(), Some(2 * 1024 * 1024 * 1024));
+        assert!(parse_size("1.5G").is_err());
+        assert!(parse_size("1P").is_err());
+        assert!(parse_size("M").is_err());
+    }
+
+    #[test]
+    fn exit_codes_and_output_are_passed_on() {
+        let outcome = run(
+            Supervisor::new("sh", &["-c", "echo out; echo err >&2; exit 3"])
+                .timeout(Some(Duration::from_secs(10)))
+                .capture(true),
+        );
+        assert_eq!((outcome.code(), outcome.exit_code(false)), (3, 3));
+        assert_eq!(
+            (&outcome.stdout[..], &outcome.stderr[..]),
+            (&b"out\n"[..], &b"err\n"[..])
+        );
+        assert!(!outcome.timed_out);
+    }
+
+    #[test]
+    fn commands_are_terminated_in_time() {
+        let start = Instant::now();
+        let outcome =
+            run(Supervisor::new("sleep", &["10"]).timeout(Some(Duration::from_millis(100))));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(outcome.timed_out);
+        assert_eq!(outcome.exit_code(false), exit_code::TIMED_OUT);
+        assert_eq!(outcome.exit_code(true), 128 + libc::SIGTERM);
+    }
+
+    #[test]
+    fn commands_ignoring_sigterm_are_killed() {
+        let start = Instant::now();
+        let outcome = run(Supervisor::new("sh", &["-c", "trap '' TERM; sleep 10"])
+            .timeout(Some(Duration::from_millis(100)))
+            .kill_after(Some(Duration::from_millis(100))));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(outcome.exit_code(true), 128 + libc::SIGKILL);
+    }
+
+    #[test]
+    fn the_whole_process_group_is_terminated() {
+        let start = Instant::now();
+        // The output pipe stays open as long as the backgrounded sleep runs.
+        let outcome = run(Supervisor::new("sh", &["-c", "sleep 10 & wait"])
+            .timeout(Some(Duration::from_millis(100)))
+            .capture(true));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(outcome.timed_out);
+    }
+
+    #[test]
+    fn limits_are_applied() {
+        let outcome = run(
+            Supervisor::new("sh", &["-c", "ulimit -n; ulimit -t; ulimit -v"])
+                .capture(true)
+                .limits(Limits {
+                    memory: Some(256 * 1024 * 1024),
+                    cpu: Some(Duration::from_millis(1500)),
+                    files: Some(64),
+                }),
+        );
+        assert_eq!(String::from_utf8_lossy(&outcome.stdout), "64\n2\n262144\n");
+    }
+
+    #[test]
+    fn summaries_tell_how_commands_ended() {
+        let mut supervisor = Supervisor::new("sh", &["-c", "exit 2"]);
+        supervisor.limits(Limits {
+            files: Some(64),
+            ..Limits::default()
+        });
+        let outcome = run(&supervisor);
+        assert!(outcome.usage.wall > Duration::default());
+        let summary = supervisor.summary(&outcome);
+        assert_eq!(summary["command"], json!(["sh", "-c", "exit 2"]));
+        assert_eq!(
+            (&summary["code"], &summary["signal"], &summary["timed_out"]),
+            (&json!(2), &json!(null), &json!(false))
+        );
+        assert_eq!(summary["limits"]["files"], json!(64));
+        assert!(summary["max_rss"].as_u64().is_some_and(|rss| rss > 0));
+        assert!(summary["cpu_time"].is_f64());
+    }
+}
@@ -0,0 +1,118 @@
+// Generated by preidolia from the grammar "Numbers", do not edit.
+use tobytes::{ByteView, ToBytes};
+
+/// Converts a IEEE 754 half precision float.
+pub fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = (bits >> 10) & 0x1f;
+    let fraction = bits & 0x3ff;
+    sign * match (exponent, fraction) {
+        (0, _) => fraction as f32 * 2f32.powi(-24),
+        (0x1f, 0) => f32::INFINITY,
+        (0x1f, _) => f32::NAN,
+        _ => (1.0 + fraction as f32 / 1024.0) * 2f32.powi(exponent as i32 - 15),
+    }
+}
+
+/// Rounds to the nearest half precision float, ties to even.
+pub fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent == 0xff {
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+    let exponent = exponent - 127 + 15;
+    let (half, shift) = match exponent {
+        e if e >= 0x1f => return sign | 0x7c00,
+        e if e < -10 => return sign,
+        e if e <= 0 => (0, (14 - e) as u32),
+        e => ((e as u32) << 10, 13),
+    };
+    let mantissa = if exponent <= 0 { mantissa | 0x80_0000 } else { mantissa };
+    let rest = mantissa & ((1 << shift) - 1);
+    let halfway = 1 << (shift - 1);
+    let half = half + (mantissa >> shift);
+    let round = rest > halfway || (rest == halfway && half & 1 == 1);
+    sign | (half + round as u32) as u16
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Numbers {
+    pub integer_with_byte_lenght1: u8,
+    pub integer_with_byte_lenght2: u16,
+    /// At most 0xffffff, it is encoded in 3 bytes.
+    pub integer_with_byte_lenght3: u32,
+    pub integer_with_byte_lenght4: u32,
+    pub integer_with_byte_lenght8: u64,
+    pub integer_with_bit_length8: u8,
+    pub integer_with_bit_length16: u16,
+    /// At most 0xffffff, it is encoded in 3 bytes.
+    pub integer_with_bit_length24: u32,
+    pub integer_with_bit_length32: u32,
+    pub integer_with_bit_length64: u64,
+    pub floating_point_byte_length2: f32,
+    pub floating_point_byte_length4: f32,
+    pub floating_point_byte_length8: f64,
+    pub floating_point_bit_length16: f32,
+    pub floating_point_bit_length32: f32,
+    pub floating_point_bit_length64: f64,
+}
+
+impl ByteView for Numbers {
+    fn byte_at(&self, index: usize) -> Option<u8> {
+        debug_assert!(self.integer_with_byte_lenght3 <= 0xff_ffff, "integer_with_byte_lenght3: at most 0xffffff, it is encoded in 3 bytes.");
+        debug_assert!(self.integer_with_bit_length24 <= 0xff_ffff, "integer_with_bit_length24: at most 0xffffff, it is encoded in 3 bytes.");
+        self.integer_with_byte_lenght1.to_be_bytes().iter().cloned()
+            .chain(self.integer_with_byte_lenght2.to_be_bytes().iter().cloned())
+            .chain(self.integer_with_byte_lenght3.to_be_bytes()[1..].iter().cloned())
+            .chain(self.integer_with_byte_lenght4.to_be_bytes().iter().cloned())
+            .chain(self.integer_with_byte_lenght8.to_be_bytes().iter().cloned())
+            .chain(self.integer_with_bit_length8.to_be_bytes().iter().cloned())
+            .chain(self.integer_with_bit_length16.to_be_bytes().iter().cloned())
+            .chain(self.integer_with_bit_length24.to_be_bytes()[1..].iter().cloned())
+            .chain(self.integer_with_bit_length32.to_be_bytes().iter().cloned())
+            .chain(self.integer_with_bit_length64.to_be_bytes().iter().cloned())
+            .chain(f32_to_f16(self.floating_point_byte_length2).to_be_bytes().iter().cloned())
+            .chain(self.floating_point_byte_length4.to_be_bytes().iter().cloned())
+            .chain(self.floating_point_byte_length8.to_be_bytes().iter().cloned())
+            .chain(f32_to_f16(self.floating_point_bit_length16).to_be_bytes().iter().cloned())
+            .chain(self.floating_point_bit_length32.to_be_bytes().iter().cloned())
+            .chain(self.floating_point_bit_length64.to_be_bytes().iter().cloned())
+            .nth(index)
+    }
+
+    fn byte_size(&self) -> usize {
+        ToBytes::to_bytes(self).count()
+    }
+}
+
+pub mod parsers {
+    use super::{Numbers};
+    use nom::number::streaming::{be_f32, be_f64, be_u16, be_u24, be_u32, be_u64, be_u8};
+    use nom::{do_parse, map};
+
+    pub fn numbers(input: &[u8]) -> nom::IResult<&[u8], Numbers> {
+        do_parse!(
+            input,
+            integer_with_byte_lenght1: be_u8 >>
+            integer_with_byte_lenght2: be_u16 >>
+            integer_with_byte_lenght3: be_u24 >>
+            integer_with_byte_lenght4: be_u32 >>
+            integer_with_byte_lenght8: be_u64 >>
+            integer_with_bit_length8: be_u8 >>
+            integer_with_bit_length16: be_u16 >>
+            integer_with_bit_length24: be_u24 >>
+            integer_with_bit_length32: be_u32 >>
+            integer_with_bit_length64: be_u64 >>
+            floating_point_byte_length2: map!(be_u16, super::f16_to_f32) >>
+            floating_point_byte_length4: be_f32 >>
+            floating_point_byte_length8: be_f64 >>
+            floating_point_bit_length16: map!(be_u16, super::f16_to_f32) >>
+            floating_point_bit_length32: be_f32 >>
+            floating_point_bit_length64: be_f64 >>
+            (Numbers { integer_with_byte_lenght1, integer_with_byte_lenght2, integer_with_byte_lenght3, integer_with_byte_lenght4, integer_with_byte_lenght8, integer_with_bit_length8, integer_with_bit_length16, integer_with_bit_length24, integer_with_bit_length32, integer_with_bit_length64, floating_point_byte_length2, floating_point_byte_length4, floating_point_byte_length8, floating_point_bit_length16, floating_point_bit_length32, floating_point_bit_length64 })
+        )
+    }
+}
@@ -0,0 +1,53 @@
+// Generated by preidolia from the grammar "Strings", do not edit.
+use tobytes::{ByteView, ToBytes};
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Strings {
+    /// At most 10 bytes, shorter values are padded with zeros.
+    pub fixed_length_string: String,
+    pub zero_terminated: String,
+    pub delimiter_terminated: String,
+    /// At most 255 bytes, the length is encoded in 1 byte.
+    pub length_prefixed: String,
+}
+
+impl ByteView for Strings {
+    fn byte_at(&self, index: usize) -> Option<u8> {
+        debug_assert!(self.fixed_length_string.len() <= 10, "fixed_length_string: at most 10 bytes, shorter values are padded with zeros.");
+        debug_assert!(self.length_prefixed.len() <= 255, "length_prefixed: at most 255 bytes, the length is encoded in 1 byte.");
+        self.fixed_length_string.as_bytes().iter().cloned().chain(std::iter::repeat(0u8)).take(10)
+            .chain(self.zero_terminated.as_bytes().iter().cloned().chain(std::iter::once(0u8)))
+            .chain(self.delimiter_terminated.as_bytes().iter().cloned().chain([0x0a, 0x0a].iter().cloned()))
+            .chain(std::iter::once(self.length_prefixed.len() as u8).chain(self.length_prefixed.as_bytes().iter().cloned()))
+            .nth(index)
+    }
+
+    fn byte_size(&self) -> usize {
+        ToBytes::to_bytes(self).count()
+    }
+}
+
+pub mod parsers {
+    use super::{Strings};
+    use nom::number::streaming::{be_u8};
+    use nom::{do_parse, map_res, tag, take, take_until};
+
+    fn unpadded(bytes: &[u8]) -> Result<&str, std::str::Utf8Error> {
+        let length = bytes.iter().rposition(|b| *b != 0).map_or(0, |last| last + 1);
+        std::str::from_utf8(&bytes[..length])
+    }
+
+    pub fn strings(input: &[u8]) -> nom::IResult<&[u8], Strings> {
+        do_parse!(
+            input,
+            fixed_length_string: map_res!(take!(10), unpadded) >>
+            zero_terminated: map_res!(take_until!("\0"), std::str::from_utf8) >>
+            tag!("\0") >>
+            delimiter_terminated: map_res!(take_until!(&[0x0a, 0x0a][..]), std::str::from_utf8) >>
+            tag!(&[0x0a, 0x0a][..]) >>
+            length_prefixed_length: be_u8 >>
+            length_prefixed: map_res!(take!(length_prefixed_length), std::str::from_utf8) >>
+            (Strings { fixed_length_string: String::from(fixed_length_string), zero_terminated: String::from(zero_terminated), delimiter_terminated: String::from(delimiter_terminated), length_prefixed: String::from(length_prefixed) })
+        )
+    }
+}
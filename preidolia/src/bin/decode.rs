@@ -1,24 +1,51 @@
+use anyhow::Result;
 use human_panic::setup_panic;
+use preidolia::decoder::{Decoder, Element};
+use preidolia::parsers::{ParseFn, Parser, ParsingIterator};
+use std::io::{BufWriter, Write};
 use structopt::StructOpt;
 
 mod cli {
-    use bricks::cli::Input;
+    use bricks::cli::{Input, Output};
     use std::path::PathBuf;
 
     #[derive(structopt::StructOpt, Debug)]
     #[structopt(about = "Decode binary data based on a grammar")]
     #[structopt(setting = structopt::clap::AppSettings::ColoredHelp)]
     pub struct Decode {
-        #[structopt(name = "grammar", help = "Grammar file to process", parse(from_os_str))]
+        #[structopt(
+            name = "grammar",
+            help = "Grammar file to process, Kaitai Struct specs (.ksy) are supported as well",
+            parse(from_os_str)
+        )]
         pub grammar: PathBuf,
 
-        #[structopt(name = "input", default_value = "stdin")]
+        #[structopt(name = "input", default_value = "-")]
         #[structopt(help = "input data to be decoded")]
         pub input: Input,
+
+        #[structopt(name = "output", default_value = "-")]
+        #[structopt(
+            help = "Output sink where the decoded structures (jsonl) shall be written to, every element carries its offset and length"
+        )]
+        pub output: Output,
     }
 }
 
-fn main() {
+fn main() -> Result<()> {
     setup_panic!();
-    let _args = cli::Decode::from_args();
+    let args = cli::Decode::from_args();
+    let ufwb = preidolia::load_grammar(&args.grammar)?;
+    let decoder = Decoder::new(&ufwb)?;
+    let decode: &ParseFn<Element> = &|input| decoder.decode(input);
+
+    let mut output = BufWriter::new(args.output);
+    for record in ParsingIterator::new(Parser::new(decode), args.input).spanned() {
+        let record = record?;
+        let mut element = record.value;
+        element.shift(record.offset);
+        writeln!(&mut output, "{}", serde_json::to_string(&element)?)?;
+    }
+    output.flush()?;
+    Ok(())
 }
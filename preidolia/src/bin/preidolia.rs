@@ -0,0 +1,190 @@
+use anyhow::{anyhow, Result};
+use human_panic::setup_panic;
+use preidolia::generator::Generator;
+use preidolia::synalize::grammar::Ufwb;
+use preidolia::synalize::{lint, xml};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use structopt::StructOpt;
+
+mod cli {
+    use anyhow::{anyhow, Error};
+    use std::path::PathBuf;
+    use std::str::FromStr;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum Format {
+        Xml,
+        Json,
+        Yaml,
+        Ksy,
+    }
+
+    impl FromStr for Format {
+        type Err = Error;
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "xml" | "grammar" => Ok(Format::Xml),
+                "json" => Ok(Format::Json),
+                "yaml" | "yml" => Ok(Format::Yaml),
+                "ksy" => Ok(Format::Ksy),
+                other => Err(anyhow!("Unknown format {}", other)),
+            }
+        }
+    }
+
+    #[derive(structopt::StructOpt, Debug)]
+    #[structopt(about = "Tooling for SynalizeIt/Hexinator grammars")]
+    #[structopt(setting = structopt::clap::AppSettings::ColoredHelp)]
+    pub enum Preidolia {
+        #[structopt(about = "Check a grammar for common mistakes")]
+        Lint {
+            #[structopt(
+                name = "grammar-file",
+                help = "Grammar file to check",
+                parse(from_os_str)
+            )]
+            grammar: PathBuf,
+        },
+        #[structopt(
+            about = "Convert a grammar between xml, json and yaml, Kaitai Struct specs (ksy) can be converted into grammars"
+        )]
+        Convert {
+            #[structopt(
+                name = "grammar-file",
+                help = "Grammar file to convert, the format is derived from the file extension",
+                parse(from_os_str)
+            )]
+            grammar: PathBuf,
+
+            #[structopt(short = "f", long = "from", possible_values = &["xml", "json", "yaml", "ksy"])]
+            #[structopt(help = "format of the grammar file, overrides the file extension")]
+            from: Option<Format>,
+
+            #[structopt(short = "t", long = "to", default_value = "xml", possible_values = &["xml", "json", "yaml"])]
+            #[structopt(help = "format the grammar shall be converted to")]
+            to: Format,
+        },
+        #[structopt(
+            about = "Generate valid and malformed samples of a grammar, e.g. to fuzz parsers"
+        )]
+        Generate {
+            #[structopt(
+                name = "grammar-file",
+                help = "Grammar file the samples are generated for, Kaitai Struct specs (.ksy) are supported as well",
+                parse(from_os_str)
+            )]
+            grammar: PathBuf,
+
+            #[structopt(short = "o", long = "output", parse(from_os_str))]
+            #[structopt(help = "directory the samples are written to, one file per sample")]
+            output: PathBuf,
+
+            #[structopt(short = "n", long = "count", default_value = "100")]
+            #[structopt(help = "amount of samples to generate")]
+            count: usize,
+
+            #[structopt(short = "m", long = "malformed", default_value = "50")]
+            #[structopt(help = "percentage of the samples which shall be malformed")]
+            malformed: usize,
+
+            #[structopt(short = "s", long = "seed")]
+            #[structopt(help = "seed to reproduce the samples of a previous run")]
+            seed: Option<u64>,
+        },
+    }
+}
+
+fn load(grammar: &Path, format: cli::Format) -> Result<Ufwb> {
+    let content = std::fs::read_to_string(grammar)?;
+    Ok(match format {
+        cli::Format::Xml => quick_xml::de::from_str(&content)?,
+        cli::Format::Json => serde_json::from_str(&content)?,
+        cli::Format::Yaml => serde_yaml::from_str(&content)?,
+        cli::Format::Ksy => preidolia::kaitai::from_str(&content)?,
+    })
+}
+
+fn lint(grammar: &Path) -> Result<()> {
+    let xml = std::fs::read_to_string(grammar)?;
+    let reports = lint::lint(&xml)?;
+    for report in &reports {
+        println!("{}:{}", grammar.display(), report);
+    }
+    match reports.len() {
+        0 => std::process::exit(0),
+        _ => std::process::exit(1),
+    }
+}
+
+fn convert(grammar: &Path, from: Option<cli::Format>, to: cli::Format) -> Result<()> {
+    let from = match from {
+        Some(format) => format,
+        None => grammar
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .ok_or_else(|| anyhow!("Can't detect the format of {}", grammar.display()))?
+            .parse()?,
+    };
+    let ufwb = load(grammar, from)?;
+    match to {
+        cli::Format::Xml => print!("{}", xml::to_string(&ufwb)?),
+        cli::Format::Json => println!("{}", serde_json::to_string_pretty(&ufwb)?),
+        cli::Format::Yaml => print!("{}", serde_yaml::to_string(&ufwb)?),
+        cli::Format::Ksy => {
+            return Err(anyhow!(
+                "Grammars can't be converted into Kaitai Struct specs"
+            ))
+        }
+    }
+    Ok(())
+}
+
+fn generate(
+    grammar: &Path,
+    output: &Path,
+    count: usize,
+    malformed: usize,
+    seed: Option<u64>,
+) -> Result<()> {
+    let ufwb = preidolia::load_grammar(grammar)?;
+    let seed = match seed {
+        Some(seed) => seed,
+        None => SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos() as u64,
+    };
+    eprintln!("Generating {} samples with seed {}", count, seed);
+    let mut generator = Generator::new(&ufwb, seed)?;
+    std::fs::create_dir_all(output)?;
+    let valid = count - count * malformed.min(100) / 100;
+    for index in 0..count {
+        let sample = if index < valid {
+            generator.valid()?
+        } else {
+            generator.malformed()?
+        };
+        let kind = match sample.flaw {
+            Some(flaw) => flaw.to_string(),
+            None => "valid".to_string(),
+        };
+        std::fs::write(
+            output.join(format!("{:05}-{}.bin", index, kind)),
+            sample.data,
+        )?;
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    setup_panic!();
+    match cli::Preidolia::from_args() {
+        cli::Preidolia::Lint { grammar } => lint(&grammar),
+        cli::Preidolia::Convert { grammar, from, to } => convert(&grammar, from, to),
+        cli::Preidolia::Generate {
+            grammar,
+            output,
+            count,
+            malformed,
+            seed,
+        } => generate(&grammar, &output, count, malformed, seed),
+    }
+}
@@ -0,0 +1,1004 @@
+//! Generates rust code from [Synalyze It](https://www.synalysis.net)/[Hexinator](https://hexinator.com) grammars.
+//!
+//! Every structure of a grammar is turned into a struct, a `ByteView` implementation
+//! and a nom parser (within the `parsers` module), in the same fashion as the hand written
+//! codecs of `protocols::tftp` or `formats::adobe::ase`.
+//! The generated code therefore requires the `nom` and `tobytes` crates.
+//!
+//! # Example
+//! ```ignore
+//! // build.rs
+//! fn main() {
+//!     preidolia::codegen::build("resources/grammars/Numbers.grammar").unwrap();
+//! }
+//!
+//! // lib.rs
+//! pub mod numbers {
+//!     include!(concat!(env!("OUT_DIR"), "/numbers.rs"));
+//! }
+//! ```
+use crate::synalize::grammar::{
+    Endianess, Length, Number, NumberType, Signedness, StringType, StructureElement, Ufwb, Unit,
+};
+use anyhow::{anyhow, bail, Result};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fmt::Write;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+/// Generates the code for a grammar file into `$OUT_DIR`, intended to be called from a `build.rs`.
+///
+/// The name of the generated file is derived from the grammar file name (e.g. `Numbers.grammar` -> `numbers.rs`).
+pub fn build<P: AsRef<Path>>(grammar: P) -> Result<PathBuf> {
+    let grammar = grammar.as_ref();
+    let out_dir = std::env::var_os("OUT_DIR").ok_or_else(|| anyhow!("OUT_DIR is not set"))?;
+    let stem = grammar
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .ok_or_else(|| anyhow!("Invalid grammar file name {:?}", grammar))?;
+    let output = Path::new(&out_dir).join(format!("{}.rs", field_name(stem)));
+    println!("cargo:rerun-if-changed={}", grammar.display());
+    generate_file(grammar, &output)?;
+    Ok(output)
+}
+
+/// Generates the code for the grammar file `grammar` and writes it to `output`.
+pub fn generate_file<P: AsRef<Path>, Q: AsRef<Path>>(grammar: P, output: Q) -> Result<()> {
+    let reader = BufReader::new(std::fs::File::open(grammar)?);
+    let ufwb: Ufwb = quick_xml::de::from_reader(reader)?;
+    std::fs::write(output, generate(&ufwb)?)?;
+    Ok(())
+}
+
+/// Generates the code for all structures of a grammar.
+pub fn generate(ufwb: &Ufwb) -> Result<String> {
+    let types = collect_types(ufwb)?;
+    let type_names: HashMap<usize, &str> = types.iter().map(|t| (t.id, t.name.as_str())).collect();
+
+    let mut code = String::new();
+    writeln!(
+        code,
+        "// Generated by preidolia from the grammar \"{}\", do not edit.",
+        ufwb.grammar.name
+    )?;
+    writeln!(code, "use tobytes::{{ByteView, ToBytes}};")?;
+    let half_precision = types
+        .iter()
+        .flat_map(|t| t.fields.iter())
+        .any(|field| matches!(field.kind, Kind::Float { bytes: 2, .. }));
+    if half_precision {
+        writeln!(code)?;
+        write!(code, "{}", HALF_PRECISION)?;
+    }
+    for t in &types {
+        writeln!(code)?;
+        write_struct(&mut code, t, &type_names)?;
+        writeln!(code)?;
+        write_byte_view(&mut code, t)?;
+    }
+    writeln!(code)?;
+    write_parsers(&mut code, &types, &type_names)?;
+    Ok(code)
+}
+
+struct Type {
+    id: usize,
+    name: String,
+    fields: Vec<Field>,
+}
+
+struct Field {
+    name: String,
+    kind: Kind,
+}
+
+enum Kind {
+    Integer {
+        bytes: usize,
+        signed: bool,
+        big_endian: bool,
+    },
+    Float {
+        bytes: usize,
+        big_endian: bool,
+    },
+    FixedLengthString {
+        length: usize,
+    },
+    ZeroTerminatedString,
+    DelimiterTerminatedString {
+        delimiter: Vec<u8>,
+    },
+    PrefixedLengthString,
+    Structure {
+        id: usize,
+    },
+}
+
+#[derive(Clone, Copy)]
+struct Context {
+    big_endian: bool,
+    signed: bool,
+}
+
+impl Context {
+    /// Applies the settings of a structure or number, unset ones are inherited.
+    fn inherit(self, endian: &Option<Endianess>, signed: &Option<Signedness>) -> Self {
+        Context {
+            big_endian: endian
+                .as_ref()
+                .map_or(self.big_endian, |e| *e == Endianess::Big),
+            signed: signed
+                .as_ref()
+                .map_or(self.signed, |s| *s == Signedness::Signed),
+        }
+    }
+}
+
+fn collect_types(ufwb: &Ufwb) -> Result<Vec<Type>> {
+    let root = &ufwb.grammar.structure;
+    let context = Context {
+        big_endian: root.endian == Endianess::Big,
+        signed: root.signed == Signedness::Signed,
+    };
+    let mut collector = Collector {
+        types: Vec::new(),
+        names: HashSet::new(),
+    };
+    collector.collect(root.id, &root.name, &root.items, context)?;
+    Ok(collector.types)
+}
+
+struct Collector {
+    types: Vec<Type>,
+    names: HashSet<String>,
+}
+
+impl Collector {
+    fn collect(
+        &mut self,
+        id: usize,
+        name: &str,
+        items: &Option<Vec<StructureElement>>,
+        context: Context,
+    ) -> Result<()> {
+        let index = self.types.len();
+        let name = unique(type_name(name, id), id, &mut self.names);
+        self.types.push(Type {
+            id,
+            name,
+            fields: Vec::new(),
+        });
+
+        let mut field_names = HashSet::new();
+        let mut fields = Vec::new();
+        for item in items.iter().flatten() {
+            if item.repeat() != (None, None) {
+                bail!(
+                    "\"{}\" is repeated, which is not supported by generated parsers",
+                    item.name()
+                );
+            }
+            let (id, name, kind) = match item {
+                StructureElement::Number(number) => {
+                    (number.id, &number.name, number_kind(number, context)?)
+                }
+                StructureElement::String(string) => {
+                    let kind = match string.r#type {
+                        StringType::FixedLength => Kind::FixedLengthString {
+                            length: constant(
+                                string.length.as_ref().ok_or_else(|| {
+                                    anyhow!("Fixed length string \"{}\" has no length", string.name)
+                                })?,
+                                &string.name,
+                            )?,
+                        },
+                        StringType::ZeroTerminated => Kind::ZeroTerminatedString,
+                        StringType::DelimiterTerminated => Kind::DelimiterTerminatedString {
+                            delimiter: string
+                                .delimiter
+                                .as_deref()
+                                .and_then(decode_hex)
+                                .ok_or_else(|| {
+                                    anyhow!("String \"{}\" has no valid delimiter", string.name)
+                                })?,
+                        },
+                        StringType::PrefixedLength => Kind::PrefixedLengthString,
+                    };
+                    (string.id, &string.name, kind)
+                }
+                StructureElement::Structure(structure) => {
+                    let context = context.inherit(&structure.endian, &structure.signed);
+                    self.collect(structure.id, &structure.name, &structure.items, context)?;
+                    let kind = Kind::Structure { id: structure.id };
+                    (structure.id, &structure.name, kind)
+                }
+                StructureElement::StructRef(structref) => {
+                    let id = structref.structure_id().ok_or_else(|| {
+                        anyhow!("Invalid structure reference \"{}\"", structref.structure)
+                    })?;
+                    (structref.id, &structref.name, Kind::Structure { id })
+                }
+            };
+            fields.push(Field {
+                name: unique(field_name(name), id, &mut field_names),
+                kind,
+            });
+        }
+        self.types[index].fields = fields;
+        Ok(())
+    }
+}
+
+fn number_kind(number: &Number, context: Context) -> Result<Kind> {
+    let context = context.inherit(&number.endian, &number.signed);
+    let length = constant(&number.length, &number.name)?;
+    let bytes = match number.unit {
+        Some(Unit::Bit) => match (length / 8, length % 8) {
+            (bytes, 0) => bytes,
+            _ => {
+                return Err(anyhow!(
+                    "Bit length {} of \"{}\" is not supported",
+                    number.length,
+                    number.name
+                ))
+            }
+        },
+        Some(Unit::Byte) | None => length,
+    };
+    match number.r#type {
+        NumberType::Integer if matches!(bytes, 1 | 2 | 3 | 4 | 8) => Ok(Kind::Integer {
+            bytes,
+            signed: context.signed,
+            big_endian: context.big_endian,
+        }),
+        NumberType::Float if matches!(bytes, 2 | 4 | 8) => Ok(Kind::Float {
+            bytes,
+            big_endian: context.big_endian,
+        }),
+        _ => Err(anyhow!(
+            "Length {} of \"{}\" is not supported",
+            number.length,
+            number.name
+        )),
+    }
+}
+
+/// Lengths of generated parsers can't depend on decoded fields.
+fn constant(length: &Length, name: &str) -> Result<usize> {
+    length
+        .expression()
+        .ok()
+        .and_then(|expression| expression.constant())
+        .and_then(|length| usize::try_from(length).ok())
+        .ok_or_else(|| {
+            anyhow!(
+                "Length \"{}\" of \"{}\" is not a constant, which is not supported by generated parsers",
+                length,
+                name
+            )
+        })
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.is_empty() {
+        return None;
+    }
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| match pair {
+            [high, low] => u8::from_str_radix(std::str::from_utf8(&[*high, *low]).ok()?, 16).ok(),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Splits a grammar name into words, e.g. `IntegerWithByteLength1` -> `Integer With Byte Length1`.
+fn words(name: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    for c in name.chars() {
+        if !c.is_ascii_alphanumeric() {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            continue;
+        }
+        if c.is_ascii_uppercase()
+            && matches!(word.chars().last(), Some(l) if !l.is_ascii_uppercase())
+        {
+            words.push(std::mem::take(&mut word));
+        }
+        word.push(c);
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+fn type_name(name: &str, id: usize) -> String {
+    let name: String = words(name)
+        .iter()
+        .map(|word| {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|first| first.to_ascii_uppercase().to_string() + chars.as_str())
+                .unwrap_or_default()
+        })
+        .collect();
+    match name.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() => name,
+        _ => format!("Structure{}{}", id, name),
+    }
+}
+
+fn field_name(name: &str) -> String {
+    let name = words(name)
+        .iter()
+        .map(|word| word.to_ascii_lowercase())
+        .collect::<Vec<String>>()
+        .join("_");
+    match name.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() => match name.as_str() {
+            "as" | "break" | "const" | "continue" | "crate" | "else" | "enum" | "extern"
+            | "false" | "fn" | "for" | "if" | "impl" | "in" | "let" | "loop" | "match" | "mod"
+            | "move" | "mut" | "pub" | "ref" | "return" | "static" | "struct" | "trait"
+            | "true" | "type" | "unsafe" | "use" | "where" | "while" | "async" | "await"
+            | "dyn" => format!("r#{}", name),
+            _ => name,
+        },
+        _ => format!("field_{}", name).trim_end_matches('_').to_string(),
+    }
+}
+
+/// Makes sure a name is only used once by appending the element id if necessary.
+fn unique(name: String, id: usize, names: &mut HashSet<String>) -> String {
+    let name = if names.contains(&name) {
+        format!("{}_{}", name, id)
+    } else {
+        name
+    };
+    names.insert(name.clone());
+    name
+}
+
+fn rust_type(kind: &Kind, type_names: &HashMap<usize, &str>) -> Result<String> {
+    Ok(match kind {
+        Kind::Integer { bytes, signed, .. } => {
+            let bits = if *bytes == 3 { 32 } else { bytes * 8 };
+            format!("{}{}", if *signed { 'i' } else { 'u' }, bits)
+        }
+        // half precision floats are represented by f32, which holds all of their values
+        Kind::Float { bytes: 2, .. } => String::from("f32"),
+        Kind::Float { bytes, .. } => format!("f{}", bytes * 8),
+        Kind::FixedLengthString { .. }
+        | Kind::ZeroTerminatedString
+        | Kind::DelimiterTerminatedString { .. }
+        | Kind::PrefixedLengthString => String::from("String"),
+        Kind::Structure { id } => String::from(
+            *type_names
+                .get(id)
+                .ok_or_else(|| anyhow!("Unknown structure id:{}", id))?,
+        ),
+    })
+}
+
+fn write_struct(code: &mut String, t: &Type, type_names: &HashMap<usize, &str>) -> Result<()> {
+    writeln!(code, "#[derive(Debug, PartialEq, Clone)]")?;
+    if t.fields.is_empty() {
+        writeln!(code, "pub struct {} {{}}", t.name)?;
+        return Ok(());
+    }
+    writeln!(code, "pub struct {} {{", t.name)?;
+    for field in &t.fields {
+        if let Some((_, limit)) = limit(field) {
+            writeln!(code, "    /// {}", limit)?;
+        }
+        writeln!(
+            code,
+            "    pub {}: {},",
+            field.name,
+            rust_type(&field.kind, type_names)?
+        )?;
+    }
+    writeln!(code, "}}")?;
+    Ok(())
+}
+
+fn byte_literals(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:#04x}", b))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// The values a field can be encoded with, as a condition on `self` and a description, the
+/// generated `byte_at` checks them in debug builds.
+fn limit(field: &Field) -> Option<(String, String)> {
+    let name = &field.name;
+    match &field.kind {
+        Kind::Integer {
+            bytes: 3,
+            signed: false,
+            ..
+        } => Some((
+            format!("self.{} <= 0xff_ffff", name),
+            String::from("At most 0xffffff, it is encoded in 3 bytes."),
+        )),
+        Kind::Integer {
+            bytes: 3,
+            signed: true,
+            ..
+        } => Some((
+            format!("(-0x80_0000..=0x7f_ffff).contains(&self.{})", name),
+            String::from("From -0x800000 to 0x7fffff, it is encoded in 3 bytes."),
+        )),
+        Kind::FixedLengthString { length } => Some((
+            format!("self.{}.len() <= {}", name, length),
+            format!(
+                "At most {} bytes, shorter values are padded with zeros.",
+                length
+            ),
+        )),
+        Kind::PrefixedLengthString => Some((
+            format!("self.{}.len() <= 255", name),
+            String::from("At most 255 bytes, the length is encoded in 1 byte."),
+        )),
+        _ => None,
+    }
+}
+
+fn to_bytes_expression(field: &Field) -> String {
+    let name = &field.name;
+    let number = |bytes: usize, big_endian: bool| match (bytes, big_endian) {
+        (3, true) => format!("self.{}.to_be_bytes()[1..].iter().cloned()", name),
+        (3, false) => format!("self.{}.to_le_bytes()[..3].iter().cloned()", name),
+        (_, true) => format!("self.{}.to_be_bytes().iter().cloned()", name),
+        (_, false) => format!("self.{}.to_le_bytes().iter().cloned()", name),
+    };
+    match &field.kind {
+        Kind::Integer {
+            bytes, big_endian, ..
+        } => number(*bytes, *big_endian),
+        Kind::Float {
+            bytes: 2,
+            big_endian: true,
+        } => format!("f32_to_f16(self.{}).to_be_bytes().iter().cloned()", name),
+        Kind::Float {
+            bytes: 2,
+            big_endian: false,
+        } => format!("f32_to_f16(self.{}).to_le_bytes().iter().cloned()", name),
+        Kind::Float { bytes, big_endian } => number(*bytes, *big_endian),
+        Kind::FixedLengthString { length } => format!(
+            "self.{}.as_bytes().iter().cloned().chain(std::iter::repeat(0u8)).take({})",
+            name, length
+        ),
+        Kind::ZeroTerminatedString => format!(
+            "self.{}.as_bytes().iter().cloned().chain(std::iter::once(0u8))",
+            name
+        ),
+        Kind::DelimiterTerminatedString { delimiter } => format!(
+            "self.{}.as_bytes().iter().cloned().chain([{}].iter().cloned())",
+            name,
+            byte_literals(delimiter)
+        ),
+        Kind::PrefixedLengthString => format!(
+            "std::iter::once(self.{0}.len() as u8).chain(self.{0}.as_bytes().iter().cloned())",
+            name
+        ),
+        Kind::Structure { .. } => format!("self.{}.to_bytes()", name),
+    }
+}
+
+fn write_byte_view(code: &mut String, t: &Type) -> Result<()> {
+    writeln!(code, "impl ByteView for {} {{", t.name)?;
+    if t.fields.is_empty() {
+        writeln!(
+            code,
+            "    fn byte_at(&self, _index: usize) -> Option<u8> {{"
+        )?;
+        writeln!(code, "        None")?;
+    } else {
+        writeln!(code, "    fn byte_at(&self, index: usize) -> Option<u8> {{")?;
+        for field in &t.fields {
+            if let Some((condition, limit)) = limit(field) {
+                writeln!(
+                    code,
+                    "        debug_assert!({}, \"{}: {}{}\");",
+                    condition,
+                    field.name,
+                    limit[..1].to_lowercase(),
+                    &limit[1..]
+                )?;
+            }
+        }
+        for (i, field) in t.fields.iter().enumerate() {
+            match i {
+                0 => writeln!(code, "        {}", to_bytes_expression(field))?,
+                _ => writeln!(code, "            .chain({})", to_bytes_expression(field))?,
+            }
+        }
+        writeln!(code, "            .nth(index)")?;
+    }
+    writeln!(code, "    }}")?;
+    writeln!(code)?;
+    writeln!(code, "    fn byte_size(&self) -> usize {{")?;
+    writeln!(code, "        ToBytes::to_bytes(self).count()")?;
+    writeln!(code, "    }}")?;
+    writeln!(code, "}}")?;
+    Ok(())
+}
+
+fn number_parser(kind: &Kind) -> Option<String> {
+    let endian = |big_endian: bool| if big_endian { "be" } else { "le" };
+    match kind {
+        Kind::Integer {
+            bytes,
+            signed,
+            big_endian,
+        } => Some(format!(
+            "{}_{}{}",
+            endian(*big_endian),
+            if *signed { 'i' } else { 'u' },
+            bytes * 8
+        )),
+        Kind::Float {
+            bytes: 2,
+            big_endian,
+        } => Some(format!("{}_u16", endian(*big_endian))),
+        Kind::Float { bytes, big_endian } => {
+            Some(format!("{}_f{}", endian(*big_endian), bytes * 8))
+        }
+        Kind::PrefixedLengthString => Some(String::from("be_u8")),
+        _ => None,
+    }
+}
+
+fn write_parsers(
+    code: &mut String,
+    types: &[Type],
+    type_names: &HashMap<usize, &str>,
+) -> Result<()> {
+    let mut number_parsers = BTreeSet::new();
+    let mut macros = BTreeSet::new();
+    macros.insert("do_parse");
+    for field in types.iter().flat_map(|t| t.fields.iter()) {
+        if let Some(parser) = number_parser(&field.kind) {
+            number_parsers.insert(parser);
+        }
+        match field.kind {
+            Kind::FixedLengthString { .. } | Kind::PrefixedLengthString => {
+                macros.extend(&["map_res", "take"])
+            }
+            Kind::ZeroTerminatedString | Kind::DelimiterTerminatedString { .. } => {
+                macros.extend(&["map_res", "tag", "take_until"])
+            }
+            Kind::Structure { .. } => macros.extend(&["call"]),
+            Kind::Float { bytes: 2, .. } => macros.extend(&["map"]),
+            Kind::Integer { .. } | Kind::Float { .. } => {}
+        }
+    }
+    let fixed_length = types
+        .iter()
+        .flat_map(|t| t.fields.iter())
+        .any(|field| matches!(field.kind, Kind::FixedLengthString { .. }));
+
+    writeln!(code, "pub mod parsers {{")?;
+    let names: Vec<&str> = types.iter().map(|t| t.name.as_str()).collect();
+    writeln!(code, "    use super::{{{}}};", names.join(", "))?;
+    if !number_parsers.is_empty() {
+        let parsers: Vec<String> = number_parsers.into_iter().collect();
+        writeln!(
+            code,
+            "    use nom::number::streaming::{{{}}};",
+            parsers.join(", ")
+        )?;
+    }
+    let macros: Vec<&str> = macros.into_iter().collect();
+    writeln!(code, "    use nom::{{{}}};", macros.join(", "))?;
+    if fixed_length {
+        writeln!(code)?;
+        write!(code, "{}", UNPADDED)?;
+    }
+
+    for t in types {
+        writeln!(code)?;
+        writeln!(
+            code,
+            "    pub fn {}(input: &[u8]) -> nom::IResult<&[u8], {}> {{",
+            parser_name(&t.name),
+            t.name
+        )?;
+        writeln!(code, "        do_parse!(")?;
+        writeln!(code, "            input,")?;
+        for field in &t.fields {
+            let name = &field.name;
+            match &field.kind {
+                Kind::Float { bytes: 2, .. } => writeln!(
+                    code,
+                    "            {}: map!({}, super::f16_to_f32) >>",
+                    name,
+                    number_parser(&field.kind).unwrap_or_default()
+                )?,
+                Kind::Integer { .. } | Kind::Float { .. } => writeln!(
+                    code,
+                    "            {}: {} >>",
+                    name,
+                    number_parser(&field.kind).unwrap_or_default()
+                )?,
+                Kind::FixedLengthString { length } => writeln!(
+                    code,
+                    "            {}: map_res!(take!({}), unpadded) >>",
+                    name, length
+                )?,
+                Kind::ZeroTerminatedString => {
+                    writeln!(
+                        code,
+                        "            {}: map_res!(take_until!(\"\\0\"), std::str::from_utf8) >>",
+                        name
+                    )?;
+                    writeln!(code, "            tag!(\"\\0\") >>")?;
+                }
+                Kind::DelimiterTerminatedString { delimiter } => {
+                    let delimiter = format!("&[{}][..]", byte_literals(delimiter));
+                    writeln!(
+                        code,
+                        "            {}: map_res!(take_until!({}), std::str::from_utf8) >>",
+                        name, delimiter
+                    )?;
+                    writeln!(code, "            tag!({}) >>", delimiter)?;
+                }
+                Kind::PrefixedLengthString => {
+                    let length = format!("{}_length", name.trim_start_matches("r#"));
+                    writeln!(code, "            {}: be_u8 >>", length)?;
+                    writeln!(
+                        code,
+                        "            {}: map_res!(take!({}), std::str::from_utf8) >>",
+                        name, length
+                    )?;
+                }
+                Kind::Structure { id } => writeln!(
+                    code,
+                    "            {}: call!(self::{}) >>",
+                    name,
+                    parser_name(type_names.get(id).cloned().unwrap_or_default())
+                )?,
+            }
+        }
+        let values: Vec<String> = t
+            .fields
+            .iter()
+            .map(|field| match field.kind {
+                Kind::Integer { .. } | Kind::Float { .. } | Kind::Structure { .. } => {
+                    field.name.clone()
+                }
+                _ => format!("{0}: String::from({0})", field.name),
+            })
+            .collect();
+        match values.len() {
+            0 => writeln!(code, "            ({} {{}})", t.name)?,
+            _ => writeln!(code, "            ({} {{ {} }})", t.name, values.join(", "))?,
+        }
+        writeln!(code, "        )")?;
+        writeln!(code, "    }}")?;
+    }
+    writeln!(code, "}}")?;
+    Ok(())
+}
+
+/// Conversions of half precision floats, which are not supported by rust.
+const HALF_PRECISION: &str = r#"/// Converts a IEEE 754 half precision float.
+pub fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = (bits >> 10) & 0x1f;
+    let fraction = bits & 0x3ff;
+    sign * match (exponent, fraction) {
+        (0, _) => fraction as f32 * 2f32.powi(-24),
+        (0x1f, 0) => f32::INFINITY,
+        (0x1f, _) => f32::NAN,
+        _ => (1.0 + fraction as f32 / 1024.0) * 2f32.powi(exponent as i32 - 15),
+    }
+}
+
+/// Rounds to the nearest half precision float, ties to even.
+pub fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent == 0xff {
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+    let exponent = exponent - 127 + 15;
+    let (half, shift) = match exponent {
+        e if e >= 0x1f => return sign | 0x7c00,
+        e if e < -10 => return sign,
+        e if e <= 0 => (0, (14 - e) as u32),
+        e => ((e as u32) << 10, 13),
+    };
+    let mantissa = if exponent <= 0 { mantissa | 0x80_0000 } else { mantissa };
+    let rest = mantissa & ((1 << shift) - 1);
+    let halfway = 1 << (shift - 1);
+    let half = half + (mantissa >> shift);
+    let round = rest > halfway || (rest == halfway && half & 1 == 1);
+    sign | (half + round as u32) as u16
+}
+"#;
+
+/// Fixed length strings are padded with zeros.
+const UNPADDED: &str = r#"    fn unpadded(bytes: &[u8]) -> Result<&str, std::str::Utf8Error> {
+        let length = bytes.iter().rposition(|b| *b != 0).map_or(0, |last| last + 1);
+        std::str::from_utf8(&bytes[..length])
+    }
+"#;
+
+fn parser_name(type_name: &str) -> String {
+    field_name(type_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quick_xml::de::from_str;
+    use tobytes::ToBytes;
+
+    mod numbers {
+        include!("../resources/generated/numbers.rs");
+    }
+
+    mod strings {
+        include!("../resources/generated/strings.rs");
+    }
+
+    fn ufwb(items: &str) -> Ufwb {
+        from_str(&crate::synalize::grammar::fixture(
+            "Codegen", "Packet", items,
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn type_and_field_names() {
+        assert_eq!(type_name("Numbers", 1), "Numbers");
+        assert_eq!(type_name("<new structure>", 2), "NewStructure");
+        assert_eq!(type_name("42", 3), "Structure342");
+        assert_eq!(
+            field_name("IntegerWithByteLenght1"),
+            "integer_with_byte_lenght1"
+        );
+        assert_eq!(field_name("<new number>"), "new_number");
+        assert_eq!(field_name("type"), "r#type");
+        assert_eq!(field_name("1st"), "field_1st");
+    }
+
+    #[test]
+    fn generate_structure() -> Result<()> {
+        let ufwb = ufwb(
+            r#"            <number name="Opcode" id="2" type="integer" length="2"/>
+            <string name="FileName" id="3" type="zero-terminated"/>
+            <structure name="Version" id="4" endian="little">
+                <number name="Major" id="5" type="integer" length="1"/>
+                <number name="Minor" id="6" type="float" length="32" lengthunit="bit"/>
+            </structure>"#,
+        );
+        let expected = r#"// Generated by preidolia from the grammar "Codegen", do not edit.
+use tobytes::{ByteView, ToBytes};
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Packet {
+    pub opcode: u16,
+    pub file_name: String,
+    pub version: Version,
+}
+
+impl ByteView for Packet {
+    fn byte_at(&self, index: usize) -> Option<u8> {
+        self.opcode.to_be_bytes().iter().cloned()
+            .chain(self.file_name.as_bytes().iter().cloned().chain(std::iter::once(0u8)))
+            .chain(self.version.to_bytes())
+            .nth(index)
+    }
+
+    fn byte_size(&self) -> usize {
+        ToBytes::to_bytes(self).count()
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Version {
+    pub major: u8,
+    pub minor: f32,
+}
+
+impl ByteView for Version {
+    fn byte_at(&self, index: usize) -> Option<u8> {
+        self.major.to_le_bytes().iter().cloned()
+            .chain(self.minor.to_le_bytes().iter().cloned())
+            .nth(index)
+    }
+
+    fn byte_size(&self) -> usize {
+        ToBytes::to_bytes(self).count()
+    }
+}
+
+pub mod parsers {
+    use super::{Packet, Version};
+    use nom::number::streaming::{be_u16, le_f32, le_u8};
+    use nom::{call, do_parse, map_res, tag, take_until};
+
+    pub fn packet(input: &[u8]) -> nom::IResult<&[u8], Packet> {
+        do_parse!(
+            input,
+            opcode: be_u16 >>
+            file_name: map_res!(take_until!("\0"), std::str::from_utf8) >>
+            tag!("\0") >>
+            version: call!(self::version) >>
+            (Packet { opcode, file_name: String::from(file_name), version })
+        )
+    }
+
+    pub fn version(input: &[u8]) -> nom::IResult<&[u8], Version> {
+        do_parse!(
+            input,
+            major: le_u8 >>
+            minor: le_f32 >>
+            (Version { major, minor })
+        )
+    }
+}
+"#;
+        assert_eq!(generate(&ufwb)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn generate_strings() -> Result<()> {
+        let ufwb = ufwb(
+            r#"            <string name="Fixed" id="2" type="fixed-length" length="4"/>
+            <string name="Delimited" id="3" type="delimiter-terminated" delimiter="0A0D"/>
+            <string name="Prefixed" id="4" type="pascal"/>"#,
+        );
+        let code = generate(&ufwb)?;
+        assert!(code.contains(
+            "self.fixed.as_bytes().iter().cloned().chain(std::iter::repeat(0u8)).take(4)"
+        ));
+        assert!(code.contains("fixed: map_res!(take!(4), unpadded) >>"));
+        assert!(code.contains(
+            "delimited: map_res!(take_until!(&[0x0a, 0x0d][..]), std::str::from_utf8) >>"
+        ));
+        assert!(code.contains("prefixed_length: be_u8 >>"));
+        assert!(code.contains("prefixed: map_res!(take!(prefixed_length), std::str::from_utf8) >>"));
+        Ok(())
+    }
+
+    #[test]
+    fn unsupported_number_length() {
+        let ufwb = ufwb(
+            r#"            <number name="Odd" id="2" type="integer" length="5" lengthunit="bit"/>"#,
+        );
+        assert!(generate(&ufwb).is_err());
+    }
+
+    #[test]
+    fn generated_code_is_up_to_date() -> Result<()> {
+        let generated = |name: &str| -> Result<String> {
+            let xml = std::fs::read_to_string(format!("resources/grammars/{}.grammar", name))?;
+            generate(&from_str(&xml)?)
+        };
+        assert_eq!(
+            generated("Numbers")?,
+            include_str!("../resources/generated/numbers.rs")
+        );
+        assert_eq!(
+            generated("Strings")?,
+            include_str!("../resources/generated/strings.rs")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn generated_code_round_trips() {
+        let numbers = numbers::Numbers {
+            integer_with_byte_lenght1: 1,
+            integer_with_byte_lenght2: 0x0203,
+            integer_with_byte_lenght3: 0x04_0506,
+            integer_with_byte_lenght4: 0x0708_090a,
+            integer_with_byte_lenght8: u64::MAX,
+            integer_with_bit_length8: 2,
+            integer_with_bit_length16: 0xfffe,
+            integer_with_bit_length24: 0xff_fffd,
+            integer_with_bit_length32: 3,
+            integer_with_bit_length64: 4,
+            floating_point_byte_length2: -1.5,
+            floating_point_byte_length4: 0.1,
+            floating_point_byte_length8: 0.2,
+            floating_point_bit_length16: 65504.0,
+            floating_point_bit_length32: f32::MIN_POSITIVE,
+            floating_point_bit_length64: f64::MAX,
+        };
+        let bytes: Vec<u8> = numbers.to_bytes().collect();
+        assert_eq!(
+            bytes.len(),
+            1 + 2 + 3 + 4 + 8 + 1 + 2 + 3 + 4 + 8 + 2 + 4 + 8 + 2 + 4 + 8
+        );
+        assert_eq!(&bytes[36..38], &[0xbe, 0x00]);
+        assert_eq!(numbers::parsers::numbers(&bytes), Ok((&[][..], numbers)));
+
+        let strings = strings::Strings {
+            fixed_length_string: String::from("short"),
+            zero_terminated: String::from("zero"),
+            delimiter_terminated: String::from("delimited"),
+            length_prefixed: String::from("pascal"),
+        };
+        let bytes: Vec<u8> = strings.to_bytes().collect();
+        assert_eq!(&bytes[..10], b"short\x00\x00\x00\x00\x00");
+        assert_eq!(strings::parsers::strings(&bytes), Ok((&[][..], strings)));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn values_exceeding_their_encoding_are_refused() {
+        let encode = |strings: strings::Strings| {
+            std::panic::catch_unwind(|| strings.to_bytes().collect::<Vec<u8>>()).is_err()
+        };
+        let strings = strings::Strings {
+            fixed_length_string: String::from("0123456789"),
+            zero_terminated: String::new(),
+            delimiter_terminated: String::new(),
+            length_prefixed: "a".repeat(255),
+        };
+        let bytes: Vec<u8> = strings.to_bytes().collect();
+        assert_eq!(
+            strings::parsers::strings(&bytes),
+            Ok((&[][..], strings.clone()))
+        );
+        // longer values would be truncated or wrap around
+        assert!(encode(strings::Strings {
+            fixed_length_string: String::from("0123456789a"),
+            ..strings.clone()
+        }));
+        assert!(encode(strings::Strings {
+            length_prefixed: "a".repeat(256),
+            ..strings
+        }));
+        let numbers = numbers::Numbers {
+            integer_with_byte_lenght1: 0,
+            integer_with_byte_lenght2: 0,
+            integer_with_byte_lenght3: 0x100_0000,
+            integer_with_byte_lenght4: 0,
+            integer_with_byte_lenght8: 0,
+            integer_with_bit_length8: 0,
+            integer_with_bit_length16: 0,
+            integer_with_bit_length24: 0,
+            integer_with_bit_length32: 0,
+            integer_with_bit_length64: 0,
+            floating_point_byte_length2: 0.0,
+            floating_point_byte_length4: 0.0,
+            floating_point_byte_length8: 0.0,
+            floating_point_bit_length16: 0.0,
+            floating_point_bit_length32: 0.0,
+            floating_point_bit_length64: 0.0,
+        };
+        assert!(std::panic::catch_unwind(|| numbers.to_bytes().count()).is_err());
+    }
+
+    #[test]
+    fn half_precision_floats_are_rounded() {
+        use numbers::{f16_to_f32, f32_to_f16};
+        for bits in (0..=u16::MAX).filter(|bits| bits & 0x7c00 != 0x7c00 || bits & 0x3ff == 0) {
+            assert_eq!(f32_to_f16(f16_to_f32(bits)), bits);
+        }
+        assert!(f16_to_f32(f32_to_f16(f32::NAN)).is_nan());
+        // halfway between 1.0 and the next half precision float, ties to the even 1.0
+        assert_eq!(f32_to_f16(1.0 + 2f32.powi(-11)), 0x3c00);
+        assert_eq!(f32_to_f16(1.0 + 3.0 * 2f32.powi(-11)), 0x3c02);
+        assert_eq!(f32_to_f16(65520.0), 0x7c00);
+        assert_eq!(f32_to_f16(2f32.powi(-25)), 0);
+        assert_eq!(f32_to_f16(1.5 * 2f32.powi(-25)), 1);
+    }
+}
@@ -0,0 +1,779 @@
+//! Decodes binary data based on a [Synalyze It](https://www.synalysis.net)/[Hexinator](https://hexinator.com) grammar.
+//!
+//! The decoder walks the grammar starting with its start structure and produces a tree of
+//! [Element]s, every element knows where it is located within the decoded input.
+use crate::synalize::grammar::{
+    self, Endianess, Length, NumberType, Signedness, StringType, StructureElement, Ufwb, Unit,
+};
+use crate::synalize::lint::{self, Issue, Positions};
+use anyhow::{anyhow, bail, Result};
+use nom::error::{Error, ErrorKind};
+use nom::{Err, IResult, Needed};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+/// Structures referencing each other are not followed deeper than this.
+pub(crate) const MAX_DEPTH: usize = 64;
+
+/// A decoded grammar element and the bytes it was decoded from.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct Element {
+    pub name: String,
+    /// Offset of the first byte of the element.
+    pub offset: u64,
+    /// Number of bytes the element occupies.
+    pub length: usize,
+    pub value: Value,
+}
+
+impl Element {
+    /// Moves the element and all of its children by `offset` bytes.
+    ///
+    /// Offsets are relative to the decoded input, shifting them by the offset of the
+    /// record (see [crate::parsers::ParsingIterator::spanned]) makes them absolute.
+    pub fn shift(&mut self, offset: u64) {
+        self.offset += offset;
+        if let Value::Structure(elements) = &mut self.value {
+            for element in elements {
+                element.shift(offset);
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+#[serde(untagged)]
+pub enum Value {
+    Unsigned(u64),
+    Signed(i64),
+    Float(f64),
+    String(String),
+    Structure(Vec<Element>),
+}
+
+/// Settings a structure passes on to its elements and nested structures.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Context<'g> {
+    pub(crate) big_endian: bool,
+    pub(crate) signed: bool,
+    pub(crate) encoding: &'g str,
+}
+
+impl<'g> Context<'g> {
+    pub(crate) fn inherit(
+        self,
+        endian: Option<&Endianess>,
+        signed: Option<&Signedness>,
+        encoding: Option<&'g str>,
+    ) -> Self {
+        Context {
+            big_endian: endian.map_or(self.big_endian, |e| *e == Endianess::Big),
+            signed: signed.map_or(self.signed, |s| *s == Signedness::Signed),
+            encoding: encoding.unwrap_or(self.encoding),
+        }
+    }
+}
+
+/// A structure together with the settings it uses.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Node<'g> {
+    pub(crate) name: &'g str,
+    pub(crate) context: Context<'g>,
+    pub(crate) items: &'g Option<Vec<StructureElement>>,
+}
+
+/// Structures by id and the id of the start structure of a grammar.
+pub(crate) struct Structures<'g> {
+    pub(crate) start: usize,
+    pub(crate) nodes: HashMap<usize, Node<'g>>,
+}
+
+impl<'g> Structures<'g> {
+    /// Indexes the structures of `ufwb`, grammars with issues (see [lint]) are rejected.
+    pub(crate) fn new(ufwb: &'g Ufwb) -> Result<Self> {
+        let issue = lint::check(ufwb, &Positions::default())
+            .into_iter()
+            .find(|report| !matches!(report.issue, Issue::FloatWithBitLength { .. }));
+        if let Some(report) = issue {
+            bail!("Invalid grammar \"{}\", {}", ufwb.grammar.name, report);
+        }
+
+        let root = &ufwb.grammar.structure;
+        let context = Context {
+            big_endian: root.endian == Endianess::Big,
+            signed: root.signed == Signedness::Signed,
+            encoding: &root.encoding,
+        };
+        let mut nodes = HashMap::new();
+        nodes.insert(
+            root.id,
+            Node {
+                name: &root.name,
+                context,
+                items: &root.items,
+            },
+        );
+        collect(&root.items, context, &mut nodes)?;
+        let start = ufwb
+            .grammar
+            .start_id()
+            .ok_or_else(|| anyhow!("Invalid start {}", ufwb.grammar.start))?;
+        Ok(Structures { start, nodes })
+    }
+
+    pub(crate) fn start(&self) -> Node<'g> {
+        self.nodes[&self.start]
+    }
+
+    /// The linter made sure all ids used by structures and references are known.
+    pub(crate) fn get(&self, id: usize) -> Node<'g> {
+        self.nodes[&id]
+    }
+}
+
+/// The minimum and maximum (if limited) number of repetitions of an item.
+type Repetitions = (usize, Option<usize>);
+
+pub struct Decoder<'g> {
+    structures: Structures<'g>,
+}
+
+impl<'g> Decoder<'g> {
+    /// Prepares the decoding of `ufwb`, grammars with issues (see [lint]) are rejected.
+    pub fn new(ufwb: &'g Ufwb) -> Result<Self> {
+        Ok(Decoder {
+            structures: Structures::new(ufwb)?,
+        })
+    }
+
+    /// Decodes one instance of the start structure, offsets are relative to the start of `input`.
+    ///
+    /// Like the streaming nom parsers, `Incomplete` is returned if `input` ends within the structure.
+    /// This includes repetitions without limit which reach the end of `input`, as more of them
+    /// could follow.
+    pub fn decode<'i>(&self, input: &'i [u8]) -> IResult<&'i [u8], Element> {
+        let node = self.structures.start();
+        let element = self.structure(input, 0, node.name, node, None, 0)?;
+        Ok((&input[element.length..], element))
+    }
+
+    fn structure<'i>(
+        &self,
+        input: &'i [u8],
+        offset: usize,
+        name: &str,
+        node: Node<'g>,
+        parent: Option<&Scope>,
+        depth: usize,
+    ) -> Result<Element, Err<Error<&'i [u8]>>> {
+        if depth > MAX_DEPTH {
+            return Err(failure(input, offset, ErrorKind::TooLarge));
+        }
+        let mut elements = Vec::new();
+        let mut position = offset;
+        for item in node.items.iter().flatten() {
+            let (min, max) = {
+                let scope = Scope {
+                    elements: &elements,
+                    parent,
+                };
+                self.repetitions(item, &scope, input, position)?
+            };
+            let mut count = 0;
+            while !matches!(max, Some(max) if count >= max) {
+                let scope = Scope {
+                    elements: &elements,
+                    parent,
+                };
+                let element = match self.item(item, node, input, position, &scope, depth) {
+                    Ok(element) => element,
+                    // optional repetitions end with the first one which doesn't match
+                    Err(Err::Error(_)) | Err(Err::Failure(_)) if count >= min => break,
+                    Err(e) => return Err(e),
+                };
+                position += element.length;
+                elements.push(element);
+                count += 1;
+                // empty elements would be repeated forever
+                if max.is_none() && elements.last().map_or(0, |e| e.length) == 0 {
+                    break;
+                }
+            }
+        }
+        Ok(element(
+            name,
+            offset,
+            position - offset,
+            Value::Structure(elements),
+        ))
+    }
+
+    /// The bounds of the repetitions of `item`, the upper one is `None` if there is no limit.
+    ///
+    /// Like in Synalyze It, an item is repeated at least `repeatmin` (default 1) and at most
+    /// `repeatmax` (default 1) times.
+    fn repetitions<'i>(
+        &self,
+        item: &StructureElement,
+        scope: &Scope,
+        input: &'i [u8],
+        offset: usize,
+    ) -> Result<Repetitions, Err<Error<&'i [u8]>>> {
+        let (repeatmin, repeatmax) = item.repeat();
+        let min = match repeatmin {
+            Some(min) => scope.evaluate(min, input, offset)?,
+            None => 1,
+        };
+        let max = match repeatmax {
+            Some(max) if max.is_unlimited() => return Ok((min, None)),
+            Some(max) => scope.evaluate(max, input, offset)?,
+            None => 1,
+        };
+        // a lone repeatmin is an exact count, a lone repeatmax of 0 leaves the item out
+        match repeatmin {
+            Some(_) => Ok((min, Some(max.max(min)))),
+            None => Ok((min.min(max), Some(max))),
+        }
+    }
+
+    fn item<'i>(
+        &self,
+        item: &StructureElement,
+        node: Node<'g>,
+        input: &'i [u8],
+        position: usize,
+        scope: &Scope,
+        depth: usize,
+    ) -> Result<Element, Err<Error<&'i [u8]>>> {
+        Ok(match item {
+            StructureElement::Number(number) => {
+                let length = scope.evaluate(&number.length, input, position)?;
+                let length = match number.unit {
+                    Some(Unit::Bit) if length % 8 != 0 => {
+                        return Err(failure(input, position, ErrorKind::Verify))
+                    }
+                    Some(Unit::Bit) => length / 8,
+                    _ => length,
+                };
+                let valid = match number.r#type {
+                    NumberType::Integer => (1..=8).contains(&length),
+                    NumberType::Float => [2, 4, 8].contains(&length),
+                };
+                if !valid {
+                    return Err(failure(input, position, ErrorKind::Verify));
+                }
+                let bytes = take(input, position, length)?;
+                let context =
+                    node.context
+                        .inherit(number.endian.as_ref(), number.signed.as_ref(), None);
+                let value = match number.r#type {
+                    NumberType::Integer => integer(bytes, context),
+                    NumberType::Float => float(bytes, context),
+                };
+                element(&number.name, position, length, value)
+            }
+            StructureElement::String(string) => {
+                self.string(input, position, string, node.context, scope)?
+            }
+            StructureElement::Structure(structure) => {
+                let nested = self.structures.get(structure.id);
+                self.structure(
+                    input,
+                    position,
+                    &structure.name,
+                    nested,
+                    Some(scope),
+                    depth + 1,
+                )?
+            }
+            StructureElement::StructRef(structref) => {
+                let referenced = self.structures.get(structref.structure_id().unwrap_or(0));
+                self.structure(
+                    input,
+                    position,
+                    &structref.name,
+                    referenced,
+                    Some(scope),
+                    depth + 1,
+                )?
+            }
+        })
+    }
+
+    fn string<'i>(
+        &self,
+        input: &'i [u8],
+        offset: usize,
+        string: &grammar::String,
+        context: Context,
+        scope: &Scope,
+    ) -> Result<Element, Err<Error<&'i [u8]>>> {
+        let (text, length) = match string.r#type {
+            StringType::FixedLength => {
+                let length = match &string.length {
+                    Some(length) => scope.evaluate(length, input, offset)?,
+                    None => 0,
+                };
+                (take(input, offset, length)?, length)
+            }
+            StringType::ZeroTerminated => {
+                let text = terminated(input, offset, &[0])?;
+                (text, text.len() + 1)
+            }
+            StringType::DelimiterTerminated => {
+                let delimiter = hex(string.delimiter.as_deref().unwrap_or_default());
+                let text = terminated(input, offset, &delimiter)?;
+                (text, text.len() + delimiter.len())
+            }
+            StringType::PrefixedLength => {
+                let prefix = take(input, offset, 1)?[0] as usize;
+                (take(input, offset + 1, prefix)?, prefix + 1)
+            }
+        };
+        Ok(element(
+            &string.name,
+            offset,
+            length,
+            Value::String(text_of(text, context.encoding)),
+        ))
+    }
+}
+
+/// The elements decoded so far, expressions can reference them.
+///
+/// Lookups start with the latest element of the innermost structure and continue with the
+/// enclosing structures.
+struct Scope<'s> {
+    elements: &'s [Element],
+    parent: Option<&'s Scope<'s>>,
+}
+
+impl Scope<'_> {
+    fn find(&self, name: &str) -> Option<&Element> {
+        let found = match name {
+            "prev" => self.elements.last(),
+            _ => self.elements.iter().rev().find(|e| e.name == name),
+        };
+        found.or_else(|| self.parent?.find(name))
+    }
+
+    fn resolve(&self, path: &[String]) -> Option<i64> {
+        let (first, rest) = path.split_first()?;
+        let mut element = self.find(first)?;
+        for name in rest {
+            element = match &element.value {
+                Value::Structure(elements) => elements.iter().rev().find(|e| &e.name == name)?,
+                _ => return None,
+            };
+        }
+        match element.value {
+            Value::Unsigned(value) => i64::try_from(value).ok(),
+            Value::Signed(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Evaluates a length or repeat count, unknown references or negative results are failures.
+    fn evaluate<'i>(
+        &self,
+        length: &Length,
+        input: &'i [u8],
+        offset: usize,
+    ) -> Result<usize, Err<Error<&'i [u8]>>> {
+        if let Length::Fixed(length) = length {
+            return Ok(*length);
+        }
+        length
+            .expression()
+            .and_then(|expression| expression.evaluate(&|path| self.resolve(path)))
+            .ok()
+            .and_then(|value| usize::try_from(value).ok())
+            .ok_or_else(|| failure(input, offset, ErrorKind::Verify))
+    }
+}
+
+/// Registers all structures defined within `items`, including the ones nested deeper.
+fn collect<'g>(
+    items: &'g Option<Vec<StructureElement>>,
+    context: Context<'g>,
+    structures: &mut HashMap<usize, Node<'g>>,
+) -> Result<()> {
+    for item in items.iter().flatten() {
+        match item {
+            StructureElement::Structure(structure) => {
+                let context = context.inherit(
+                    structure.endian.as_ref(),
+                    structure.signed.as_ref(),
+                    structure.encoding.as_deref(),
+                );
+                let node = Node {
+                    name: &structure.name,
+                    context,
+                    items: &structure.items,
+                };
+                structures.insert(structure.id, node);
+                collect(&structure.items, context, structures)?;
+            }
+            StructureElement::Number(number)
+                if number.unit == Some(Unit::Bit)
+                    && matches!(number.length.fixed(), Some(bits) if !bits.is_multiple_of(8)) =>
+            {
+                bail!(
+                    "\"{}\" is a bit field of {} bits, only whole bytes can be decoded",
+                    number.name,
+                    number.length
+                )
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn element(name: &str, offset: usize, length: usize, value: Value) -> Element {
+    Element {
+        name: name.to_string(),
+        offset: offset as u64,
+        length,
+        value,
+    }
+}
+
+fn failure(input: &[u8], offset: usize, kind: ErrorKind) -> Err<Error<&[u8]>> {
+    Err::Failure(Error::new(input.get(offset..).unwrap_or_default(), kind))
+}
+
+fn take(input: &[u8], offset: usize, length: usize) -> Result<&[u8], Err<Error<&[u8]>>> {
+    match input.get(offset..offset + length) {
+        Some(bytes) => Ok(bytes),
+        None => Err(Err::Incomplete(Needed::new(offset + length - input.len()))),
+    }
+}
+
+/// Bytes starting at `offset` up to (excluding) the `delimiter`.
+fn terminated<'i>(
+    input: &'i [u8],
+    offset: usize,
+    delimiter: &[u8],
+) -> Result<&'i [u8], Err<Error<&'i [u8]>>> {
+    let data = input.get(offset..).unwrap_or_default();
+    match data.windows(delimiter.len()).position(|w| w == delimiter) {
+        Some(position) => Ok(&data[..position]),
+        None => Err(Err::Incomplete(Needed::Unknown)),
+    }
+}
+
+fn integer(bytes: &[u8], context: Context) -> Value {
+    let fold = |value: u64, byte: &u8| value << 8 | *byte as u64;
+    let raw = if context.big_endian {
+        bytes.iter().fold(0, fold)
+    } else {
+        bytes.iter().rev().fold(0, fold)
+    };
+    match context.signed {
+        true => {
+            let unused = 64 - 8 * bytes.len() as u32;
+            Value::Signed((raw << unused) as i64 >> unused)
+        }
+        false => Value::Unsigned(raw),
+    }
+}
+
+fn float(bytes: &[u8], context: Context) -> Value {
+    let bits = match integer(
+        bytes,
+        Context {
+            signed: false,
+            ..context
+        },
+    ) {
+        Value::Unsigned(bits) => bits,
+        _ => unreachable!("unsigned integers are decoded as Value::Unsigned"),
+    };
+    Value::Float(match bytes.len() {
+        2 => half(bits as u16),
+        4 => f32::from_bits(bits as u32) as f64,
+        _ => f64::from_bits(bits),
+    })
+}
+
+/// Converts a IEEE 754 half precision float.
+fn half(bits: u16) -> f64 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = (bits >> 10) & 0x1f;
+    let fraction = bits & 0x3ff;
+    sign * match (exponent, fraction) {
+        (0, _) => fraction as f64 * 2f64.powi(-24),
+        (0x1f, 0) => f64::INFINITY,
+        (0x1f, _) => f64::NAN,
+        _ => (1.0 + fraction as f64 / 1024.0) * 2f64.powi(exponent as i32 - 15),
+    }
+}
+
+/// Decodes a hex encoded delimiter, the linter made sure it is valid.
+pub(crate) fn hex(delimiter: &str) -> Vec<u8> {
+    delimiter
+        .as_bytes()
+        .chunks(2)
+        .filter_map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+/// Decodes text in the encoding of the structure, encodings other than latin-1 are treated as UTF-8.
+fn text_of(bytes: &[u8], encoding: &str) -> String {
+    if encoding.starts_with("ISO_8859-1") || encoding.eq_ignore_ascii_case("latin1") {
+        bytes.iter().map(|b| *b as char).collect()
+    } else {
+        String::from_utf8_lossy(bytes).into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quick_xml::de::from_str;
+
+    fn grammar(items: &str) -> Ufwb {
+        from_str(&crate::synalize::grammar::fixture("Test", "Root", items)).unwrap()
+    }
+
+    fn children(element: Element) -> Vec<(String, u64, usize, Value)> {
+        match element.value {
+            Value::Structure(elements) => elements
+                .into_iter()
+                .map(|e| (e.name, e.offset, e.length, e.value))
+                .collect(),
+            value => panic!("{:?} is not a structure", value),
+        }
+    }
+
+    #[test]
+    fn decode_numbers() {
+        let ufwb = grammar(
+            r#"<number name="a" id="2" type="integer" length="2"/>
+               <number name="b" id="3" type="integer" length="24" lengthunit="bit"/>
+               <structure name="s" id="4" endian="little" signed="yes">
+                   <number name="c" id="5" type="integer" length="2"/>
+                   <number name="d" id="6" type="float" length="4"/>
+               </structure>
+               <number name="e" id="7" type="float" length="2"/>"#,
+        );
+        let decoder = Decoder::new(&ufwb).unwrap();
+        let input = [
+            0x01, 0x02, 0x00, 0x00, 0x03, 0xfe, 0xff, 0x00, 0x00, 0xc0, 0x3f, 0x3c, 0x00, 0xff,
+        ];
+        let (rest, root) = decoder.decode(&input).unwrap();
+        assert_eq!(rest, &[0xff]);
+        assert_eq!((root.offset, root.length), (0, 13));
+        let mut elements = children(root);
+        let nested = elements.remove(2);
+        assert_eq!(
+            elements,
+            vec![
+                ("a".to_string(), 0, 2, Value::Unsigned(0x0102)),
+                ("b".to_string(), 2, 3, Value::Unsigned(3)),
+                ("e".to_string(), 11, 2, Value::Float(1.0)),
+            ]
+        );
+        assert_eq!(nested.0, "s");
+        assert_eq!((nested.1, nested.2), (5, 6));
+        match nested.3 {
+            Value::Structure(elements) => {
+                assert_eq!(elements[0].value, Value::Signed(-2));
+                assert_eq!((elements[1].offset, elements[1].length), (7, 4));
+                assert_eq!(elements[1].value, Value::Float(1.5));
+            }
+            value => panic!("{:?} is not a structure", value),
+        }
+    }
+
+    #[test]
+    fn decode_strings() {
+        let ufwb = grammar(
+            r#"<string name="fixed" id="2" type="fixed-length" length="3"/>
+               <string name="zero" id="3" type="zero-terminated"/>
+               <string name="delimited" id="4" type="delimiter-terminated" delimiter="0D0A"/>
+               <string name="pascal" id="5" type="pascal"/>"#,
+        );
+        let decoder = Decoder::new(&ufwb).unwrap();
+        let (rest, root) = decoder.decode(b"abcde\0fg\r\n\x02hi").unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(
+            children(root),
+            vec![
+                ("fixed".to_string(), 0, 3, Value::String("abc".to_string())),
+                ("zero".to_string(), 3, 3, Value::String("de".to_string())),
+                (
+                    "delimited".to_string(),
+                    6,
+                    4,
+                    Value::String("fg".to_string())
+                ),
+                ("pascal".to_string(), 10, 3, Value::String("hi".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_structref() {
+        let ufwb = grammar(
+            r#"<structure name="Pair" id="2">
+                   <number name="x" id="3" type="integer" length="1"/>
+               </structure>
+               <structref name="Second" id="4" structure="id:2"/>"#,
+        );
+        let decoder = Decoder::new(&ufwb).unwrap();
+        let (_, root) = decoder.decode(&[1, 2]).unwrap();
+        let elements = children(root);
+        assert_eq!(elements[1].0, "Second");
+        assert_eq!((elements[1].1, elements[1].2), (1, 1));
+    }
+
+    #[test]
+    fn decode_expressions() {
+        let ufwb = grammar(
+            r#"<structure name="Header" id="2">
+                   <number name="size" id="3" type="integer" length="1"/>
+                   <number name="flags" id="4" type="integer" length="1"/>
+               </structure>
+               <string name="name" id="5" type="fixed-length" length="Header.size * 2"/>
+               <number name="count" id="6" type="integer" length="1"/>
+               <number name="value" id="7" type="integer" length="count" repeatmax="count"/>
+               <structure name="Extension" id="8" repeatmax="Header.flags &amp; 0x01">
+                   <number name="size" id="9" type="integer" length="Header.size"/>
+                   <string name="text" id="10" type="fixed-length" length="prev"/>
+               </structure>"#,
+        );
+        let decoder = Decoder::new(&ufwb).unwrap();
+        let input = [1, 1, b'a', b'b', 2, 0, 1, 0, 2, 2, b'x', b'y', 9];
+        let (rest, root) = decoder.decode(&input).unwrap();
+        assert_eq!(rest, [9]);
+        let elements = children(root);
+        let summary: Vec<(&str, u64, usize)> = elements
+            .iter()
+            .map(|(name, offset, length, _)| (name.as_str(), *offset, *length))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("Header", 0, 2),
+                ("name", 2, 2),
+                ("count", 4, 1),
+                ("value", 5, 2),
+                ("value", 7, 2),
+                ("Extension", 9, 3),
+            ]
+        );
+        assert_eq!(elements[3].3, Value::Unsigned(1));
+        assert_eq!(elements[4].3, Value::Unsigned(2));
+
+        // the extension is left out if the flag isn't set
+        let (rest, root) = decoder.decode(&[1, 0, b'a', b'b', 0, 9]).unwrap();
+        assert_eq!(rest, [9]);
+        assert_eq!(children(root).len(), 3);
+    }
+
+    #[test]
+    fn repetitions_are_bounded() {
+        // entries end with an empty one, whose text has a negative length
+        let ufwb = |repeatmin: &str, repeatmax: &str| {
+            grammar(&format!(
+                r#"<structure name="Entry" id="2" repeatmin="{}" repeatmax="{}">
+                       <number name="length" id="3" type="integer" length="1"/>
+                       <string name="text" id="4" type="fixed-length" length="length - 1"/>
+                   </structure>
+                   <number name="end" id="5" type="integer" length="1"/>"#,
+                repeatmin, repeatmax
+            ))
+        };
+        let names = |input: &[u8], ufwb: &Ufwb| -> Vec<String> {
+            let (_, root) = Decoder::new(ufwb).unwrap().decode(input).unwrap();
+            children(root).into_iter().map(|(name, ..)| name).collect()
+        };
+        let input = [3, b'a', b'b', 2, b'c', 0, 9];
+        for repeatmax in &["-1", "unlimited", "5"] {
+            assert_eq!(
+                names(&input, &ufwb("1", repeatmax)),
+                vec!["Entry", "Entry", "end"]
+            );
+        }
+        assert_eq!(names(&input, &ufwb("0", "1")), vec!["Entry", "end"]);
+        assert_eq!(names(&[0, 9], &ufwb("0", "-1")), vec!["end"]);
+        // fewer repetitions than repeatmin
+        let at_least_three = ufwb("3", "-1");
+        let decoder = Decoder::new(&at_least_three).unwrap();
+        assert!(matches!(decoder.decode(&input), Err(Err::Failure(_))));
+        // more repetitions could follow
+        assert!(matches!(
+            decoder.decode(&[2, b'a', 2]),
+            Err(Err::Incomplete(_))
+        ));
+    }
+
+    #[test]
+    fn invalid_evaluations_fail() {
+        let ufwb = grammar(
+            r#"<number name="a" id="2" type="integer" length="1"/>
+               <string name="b" id="3" type="fixed-length" length="a - 2"/>
+               <number name="c" id="4" type="integer" length="unknown"/>"#,
+        );
+        let decoder = Decoder::new(&ufwb).unwrap();
+        assert!(matches!(
+            decoder.decode(&[1, 0]),
+            Err(Err::Failure(Error {
+                code: ErrorKind::Verify,
+                ..
+            }))
+        ));
+        assert!(matches!(
+            decoder.decode(&[2, 0]),
+            Err(Err::Failure(Error { input: [0], .. }))
+        ));
+    }
+
+    #[test]
+    fn incomplete_input() {
+        let ufwb = grammar(
+            r#"<number name="a" id="2" type="integer" length="4"/>
+               <string name="b" id="3" type="zero-terminated"/>"#,
+        );
+        let decoder = Decoder::new(&ufwb).unwrap();
+        assert_eq!(
+            decoder.decode(&[0, 0]),
+            Err(Err::Incomplete(Needed::new(2)))
+        );
+        assert_eq!(
+            decoder.decode(&[0, 0, 0, 0, b'a']),
+            Err(Err::Incomplete(Needed::Unknown))
+        );
+    }
+
+    #[test]
+    fn recursive_structures_are_limited() {
+        let ufwb = grammar(r#"<structref name="Self" id="2" structure="id:1"/>"#);
+        let decoder = Decoder::new(&ufwb).unwrap();
+        assert!(matches!(decoder.decode(&[]), Err(Err::Failure(_))));
+    }
+
+    #[test]
+    fn invalid_grammars_are_rejected() {
+        let ufwb = grammar(r#"<structref name="Missing" id="2" structure="id:7"/>"#);
+        assert!(Decoder::new(&ufwb).is_err());
+        let ufwb =
+            grammar(r#"<number name="Flag" id="2" type="integer" length="1" lengthunit="bit"/>"#);
+        assert!(Decoder::new(&ufwb).is_err());
+    }
+
+    #[test]
+    fn shifted_element_as_json() {
+        let ufwb = grammar(r#"<number name="a" id="2" type="integer" length="1"/>"#);
+        let decoder = Decoder::new(&ufwb).unwrap();
+        let (_, mut root) = decoder.decode(&[7]).unwrap();
+        root.shift(10);
+        assert_eq!(
+            serde_json::to_string(&root).unwrap(),
+            r#"{"name":"Root","offset":10,"length":1,"value":[{"name":"a","offset":10,"length":1,"value":7}]}"#
+        );
+    }
+}
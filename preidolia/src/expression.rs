@@ -0,0 +1,459 @@
+//! Expressions used by grammars for lengths and repetitions, e.g. `Header.Count * 4`.
+//!
+//! Expressions consist of integers (decimal or `0x` hex), references to already decoded
+//! fields and the operators of C with their usual precedence. Comparisons and logical
+//! operators (also available as `and`, `or` and `not`) evaluate to `1` or `0`, which makes a
+//! condition usable as repeat count. A reference is a dotted path (e.g. `prev.size`) whose
+//! first segment names a field of the current or an enclosing structure (`prev` is the
+//! previous field) and whose further segments name fields of nested structures.
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Integer(i64),
+    Reference(Vec<String>),
+    Unary(UnaryOperator, Box<Expression>),
+    Binary(BinaryOperator, Box<Expression>, Box<Expression>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOperator {
+    Negate,
+    Not,
+    Complement,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOperator {
+    Multiply,
+    Divide,
+    Remainder,
+    Add,
+    Subtract,
+    ShiftLeft,
+    ShiftRight,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Equal,
+    NotEqual,
+    BitAnd,
+    BitXor,
+    BitOr,
+    And,
+    Or,
+}
+
+impl BinaryOperator {
+    /// Operators with a higher precedence bind stronger.
+    fn precedence(self) -> u8 {
+        match self {
+            BinaryOperator::Or => 1,
+            BinaryOperator::And => 2,
+            BinaryOperator::BitOr => 3,
+            BinaryOperator::BitXor => 4,
+            BinaryOperator::BitAnd => 5,
+            BinaryOperator::Equal | BinaryOperator::NotEqual => 6,
+            BinaryOperator::Less
+            | BinaryOperator::LessOrEqual
+            | BinaryOperator::Greater
+            | BinaryOperator::GreaterOrEqual => 7,
+            BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight => 8,
+            BinaryOperator::Add | BinaryOperator::Subtract => 9,
+            BinaryOperator::Multiply | BinaryOperator::Divide | BinaryOperator::Remainder => 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    Syntax { position: usize, message: String },
+    UnknownReference(String),
+    DivisionByZero,
+    Overflow,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Syntax { position, message } => {
+                write!(f, "{} at position {}", message, position)
+            }
+            Error::UnknownReference(path) => write!(f, "\"{}\" is not a decoded number", path),
+            Error::DivisionByZero => write!(f, "division by zero"),
+            Error::Overflow => write!(f, "overflow"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl FromStr for Expression {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            index: 0,
+            end: s.len(),
+            depth: 0,
+        };
+        let expression = parser.expression(0)?;
+        match parser.tokens.get(parser.index) {
+            None => Ok(expression),
+            Some((position, token)) => Err(Error::Syntax {
+                position: *position,
+                message: format!("unexpected {:?}", token),
+            }),
+        }
+    }
+}
+
+impl Expression {
+    /// Evaluates the expression, `resolve` provides the values of the referenced fields.
+    pub fn evaluate(&self, resolve: &dyn Fn(&[String]) -> Option<i64>) -> Result<i64, Error> {
+        match self {
+            Expression::Integer(value) => Ok(*value),
+            Expression::Reference(path) => {
+                resolve(path).ok_or_else(|| Error::UnknownReference(path.join(".")))
+            }
+            Expression::Unary(operator, operand) => {
+                let value = operand.evaluate(resolve)?;
+                match operator {
+                    UnaryOperator::Negate => value.checked_neg().ok_or(Error::Overflow),
+                    UnaryOperator::Not => Ok((value == 0) as i64),
+                    UnaryOperator::Complement => Ok(!value),
+                }
+            }
+            Expression::Binary(BinaryOperator::And, left, right) => {
+                Ok((left.evaluate(resolve)? != 0 && right.evaluate(resolve)? != 0) as i64)
+            }
+            Expression::Binary(BinaryOperator::Or, left, right) => {
+                Ok((left.evaluate(resolve)? != 0 || right.evaluate(resolve)? != 0) as i64)
+            }
+            Expression::Binary(operator, left, right) => {
+                let left = left.evaluate(resolve)?;
+                let right = right.evaluate(resolve)?;
+                binary(*operator, left, right)
+            }
+        }
+    }
+
+    /// The paths of all fields referenced by the expression.
+    pub fn references(&self) -> Vec<&[String]> {
+        match self {
+            Expression::Integer(_) => Vec::new(),
+            Expression::Reference(path) => vec![path],
+            Expression::Unary(_, operand) => operand.references(),
+            Expression::Binary(_, left, right) => {
+                let mut references = left.references();
+                references.extend(right.references());
+                references
+            }
+        }
+    }
+
+    /// Value of an expression which doesn't reference any field.
+    pub fn constant(&self) -> Option<i64> {
+        self.evaluate(&|_| None).ok()
+    }
+}
+
+fn binary(operator: BinaryOperator, left: i64, right: i64) -> Result<i64, Error> {
+    let shift = || u32::try_from(right).ok().filter(|shift| *shift < 64);
+    let value = match operator {
+        BinaryOperator::Multiply => left.checked_mul(right),
+        BinaryOperator::Divide | BinaryOperator::Remainder if right == 0 => {
+            return Err(Error::DivisionByZero)
+        }
+        BinaryOperator::Divide => left.checked_div(right),
+        BinaryOperator::Remainder => left.checked_rem(right),
+        BinaryOperator::Add => left.checked_add(right),
+        BinaryOperator::Subtract => left.checked_sub(right),
+        BinaryOperator::ShiftLeft => shift().map(|shift| left << shift),
+        BinaryOperator::ShiftRight => shift().map(|shift| left >> shift),
+        BinaryOperator::Less => Some((left < right) as i64),
+        BinaryOperator::LessOrEqual => Some((left <= right) as i64),
+        BinaryOperator::Greater => Some((left > right) as i64),
+        BinaryOperator::GreaterOrEqual => Some((left >= right) as i64),
+        BinaryOperator::Equal => Some((left == right) as i64),
+        BinaryOperator::NotEqual => Some((left != right) as i64),
+        BinaryOperator::BitAnd => Some(left & right),
+        BinaryOperator::BitXor => Some(left ^ right),
+        BinaryOperator::BitOr => Some(left | right),
+        BinaryOperator::And => Some((left != 0 && right != 0) as i64),
+        BinaryOperator::Or => Some((left != 0 || right != 0) as i64),
+    };
+    value.ok_or(Error::Overflow)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Integer(i64),
+    Path(Vec<String>),
+    Operator(&'static str),
+}
+
+/// Operators ordered such that longer ones are matched first.
+const OPERATORS: [&str; 22] = [
+    "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "*", "/", "%", "+", "-", "<", ">", "&", "^",
+    "|", "!", "~", "(", ")",
+];
+
+fn tokenize(text: &str) -> Result<Vec<(usize, Token)>, Error> {
+    let mut tokens = Vec::new();
+    let mut position = 0;
+    while position < text.len() {
+        let rest = &text[position..];
+        let first = rest.chars().next().unwrap_or_default();
+        if first.is_whitespace() {
+            position += first.len_utf8();
+            continue;
+        }
+        let length = if first.is_ascii_digit() {
+            let length = rest
+                .find(|c: char| !c.is_ascii_alphanumeric())
+                .unwrap_or(rest.len());
+            let literal = &rest[..length];
+            let value = match literal.strip_prefix("0x") {
+                Some(hex) => i64::from_str_radix(hex, 16),
+                None => literal.parse(),
+            };
+            let value = value.map_err(|_| Error::Syntax {
+                position,
+                message: format!("invalid number \"{}\"", literal),
+            })?;
+            tokens.push((position, Token::Integer(value)));
+            length
+        } else if first.is_alphabetic() || first == '_' {
+            let length = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+                .unwrap_or(rest.len());
+            let token = match &rest[..length] {
+                "and" => Token::Operator("&&"),
+                "or" => Token::Operator("||"),
+                "not" => Token::Operator("!"),
+                path => Token::Path(path.split('.').map(str::to_string).collect()),
+            };
+            tokens.push((position, token));
+            length
+        } else {
+            let operator = OPERATORS
+                .iter()
+                .find(|operator| rest.starts_with(*operator))
+                .ok_or_else(|| Error::Syntax {
+                    position,
+                    message: format!("unexpected character '{}'", first),
+                })?;
+            tokens.push((position, Token::Operator(operator)));
+            operator.len()
+        };
+        position += length;
+    }
+    Ok(tokens)
+}
+
+/// How deeply parentheses and unary operators may be nested, deeper expressions would overflow
+/// the stack while they are parsed or evaluated.
+const MAX_NESTING: usize = 64;
+
+/// Precedence climbing parser.
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    index: usize,
+    end: usize,
+    /// Operands being parsed, each one nests deeper.
+    depth: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Result<(usize, Token), Error> {
+        let token = self.tokens.get(self.index).cloned().ok_or(Error::Syntax {
+            position: self.end,
+            message: "unexpected end".to_string(),
+        })?;
+        self.index += 1;
+        Ok(token)
+    }
+
+    fn expression(&mut self, min_precedence: u8) -> Result<Expression, Error> {
+        let mut left = self.operand()?;
+        while let Some((_, Token::Operator(operator))) = self.tokens.get(self.index) {
+            let operator = match binary_operator(operator) {
+                Some(operator) if operator.precedence() > min_precedence => operator,
+                _ => break,
+            };
+            self.index += 1;
+            let right = self.expression(operator.precedence())?;
+            left = Expression::Binary(operator, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn operand(&mut self) -> Result<Expression, Error> {
+        if self.depth == MAX_NESTING {
+            return Err(Error::Syntax {
+                position: self.tokens.get(self.index).map_or(self.end, |(p, _)| *p),
+                message: format!("nested deeper than {} levels", MAX_NESTING),
+            });
+        }
+        self.depth += 1;
+        let operand = self.nested_operand();
+        self.depth -= 1;
+        operand
+    }
+
+    fn nested_operand(&mut self) -> Result<Expression, Error> {
+        let (position, token) = self.next()?;
+        let unary = |operator, parser: &mut Parser| {
+            Ok(Expression::Unary(operator, Box::new(parser.operand()?)))
+        };
+        match token {
+            Token::Integer(value) => Ok(Expression::Integer(value)),
+            Token::Path(path) => Ok(Expression::Reference(path)),
+            Token::Operator("-") => unary(UnaryOperator::Negate, self),
+            Token::Operator("!") => unary(UnaryOperator::Not, self),
+            Token::Operator("~") => unary(UnaryOperator::Complement, self),
+            Token::Operator("(") => {
+                let expression = self.expression(0)?;
+                match self.next()? {
+                    (_, Token::Operator(")")) => Ok(expression),
+                    (position, token) => Err(Error::Syntax {
+                        position,
+                        message: format!("expected ')' instead of {:?}", token),
+                    }),
+                }
+            }
+            token => Err(Error::Syntax {
+                position,
+                message: format!("unexpected {:?}", token),
+            }),
+        }
+    }
+}
+
+fn binary_operator(operator: &str) -> Option<BinaryOperator> {
+    Some(match operator {
+        "*" => BinaryOperator::Multiply,
+        "/" => BinaryOperator::Divide,
+        "%" => BinaryOperator::Remainder,
+        "+" => BinaryOperator::Add,
+        "-" => BinaryOperator::Subtract,
+        "<<" => BinaryOperator::ShiftLeft,
+        ">>" => BinaryOperator::ShiftRight,
+        "<" => BinaryOperator::Less,
+        "<=" => BinaryOperator::LessOrEqual,
+        ">" => BinaryOperator::Greater,
+        ">=" => BinaryOperator::GreaterOrEqual,
+        "==" => BinaryOperator::Equal,
+        "!=" => BinaryOperator::NotEqual,
+        "&" => BinaryOperator::BitAnd,
+        "^" => BinaryOperator::BitXor,
+        "|" => BinaryOperator::BitOr,
+        "&&" => BinaryOperator::And,
+        "||" => BinaryOperator::Or,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(text: &str) -> Result<i64, Error> {
+        let expression: Expression = text.parse()?;
+        expression.evaluate(&|path| match path.join(".").as_str() {
+            "prev.size" => Some(3),
+            "Header.Count" => Some(10),
+            "flag" => Some(1),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(evaluate("1 + 2 * 3"), Ok(7));
+        assert_eq!(evaluate("(1 + 2) * 3"), Ok(9));
+        assert_eq!(evaluate("10 - 4 - 3"), Ok(3));
+        assert_eq!(evaluate("17 / 5 + 17 % 5"), Ok(5));
+        assert_eq!(evaluate("-0x10 + 1"), Ok(-15));
+    }
+
+    #[test]
+    fn bitwise() {
+        assert_eq!(evaluate("1 << 4 | 0x3"), Ok(0x13));
+        assert_eq!(evaluate("0xff & ~0xf0 ^ 1"), Ok(0x0e));
+        assert_eq!(evaluate("256 >> 4"), Ok(16));
+    }
+
+    #[test]
+    fn comparisons_and_logic() {
+        assert_eq!(evaluate("1 < 2"), Ok(1));
+        assert_eq!(evaluate("2 <= 1"), Ok(0));
+        assert_eq!(evaluate("1 + 1 == 2 && 3 != 3"), Ok(0));
+        assert_eq!(evaluate("not flag or 4 >= 4"), Ok(1));
+        assert_eq!(evaluate("!0"), Ok(1));
+    }
+
+    #[test]
+    fn references() {
+        assert_eq!(evaluate("prev.size * 4"), Ok(12));
+        assert_eq!(evaluate("Header.Count - prev.size"), Ok(7));
+        assert_eq!(
+            evaluate("missing + 1"),
+            Err(Error::UnknownReference("missing".to_string()))
+        );
+        let expression: Expression = "a.b + (c << a.b)".parse().unwrap();
+        let paths: Vec<String> = expression
+            .references()
+            .iter()
+            .map(|path| path.join("."))
+            .collect();
+        assert_eq!(paths, ["a.b", "c", "a.b"]);
+    }
+
+    #[test]
+    fn evaluation_errors() {
+        assert_eq!(evaluate("1 / (flag - 1)"), Err(Error::DivisionByZero));
+        assert_eq!(evaluate("0x7fffffffffffffff + 1"), Err(Error::Overflow));
+        assert_eq!(evaluate("1 << 64"), Err(Error::Overflow));
+    }
+
+    #[test]
+    fn syntax_errors() {
+        for text in &["", "1 +", "(1", "1 2", "1 $ 2", "0xzz", ")"] {
+            assert!(
+                matches!(text.parse::<Expression>(), Err(Error::Syntax { .. })),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn nesting_is_limited() {
+        let parentheses = |depth: usize| {
+            format!("{}1{}", "(".repeat(depth), ")".repeat(depth)).parse::<Expression>()
+        };
+        assert_eq!(parentheses(MAX_NESTING - 1), Ok(Expression::Integer(1)));
+        assert!(matches!(
+            parentheses(MAX_NESTING),
+            Err(Error::Syntax { .. })
+        ));
+        assert!(matches!(parentheses(100_000), Err(Error::Syntax { .. })));
+        let negations = format!("{}1", "-".repeat(100_000));
+        assert!(matches!(
+            negations.parse::<Expression>(),
+            Err(Error::Syntax { .. })
+        ));
+    }
+
+    #[test]
+    fn constant() {
+        assert_eq!("4 * 4".parse::<Expression>().unwrap().constant(), Some(16));
+        assert_eq!("flag * 4".parse::<Expression>().unwrap().constant(), None);
+    }
+}
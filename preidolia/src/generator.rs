@@ -0,0 +1,491 @@
+//! Generates valid and deliberately malformed samples of a grammar, e.g. as corpus for fuzzing parsers.
+//!
+//! Valid samples vary the lengths of variable length strings and only use the fixed values
+//! (enumeration) of numbers which have some. Malformed samples are valid samples which
+//! have been broken in one place, see [Flaw].
+use crate::decoder::{hex, Context, Node, Structures, MAX_DEPTH};
+use crate::synalize::grammar::{
+    self, Length, Number, NumberType, StringType, StructureElement, Ufwb, Unit,
+};
+use anyhow::{bail, Result};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::fmt;
+
+/// Upper limit for the length of generated variable length strings.
+const MAX_TEXT_LENGTH: usize = 32;
+/// Upper limit for the repetitions beyond `repeatmin` of items which are repeated without limit.
+const MAX_REPETITIONS: usize = 8;
+
+/// How a malformed sample has been broken.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Flaw {
+    /// The sample ends before the start structure is complete.
+    Truncated,
+    /// A number with fixed values holds a value which isn't one of them.
+    EnumOutOfRange,
+    /// The terminator of a zero or delimiter terminated string has been replaced.
+    WrongTerminator,
+    /// The length prefix of a pascal string claims more bytes than there are.
+    LengthMismatch,
+}
+
+impl fmt::Display for Flaw {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Flaw::Truncated => "truncated",
+            Flaw::EnumOutOfRange => "enum-out-of-range",
+            Flaw::WrongTerminator => "wrong-terminator",
+            Flaw::LengthMismatch => "length-mismatch",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    pub data: Vec<u8>,
+    /// `None` for valid samples.
+    pub flaw: Option<Flaw>,
+}
+
+/// Places within a generated sample which can be broken.
+enum Site {
+    Enum {
+        offset: usize,
+        length: usize,
+        big_endian: bool,
+        values: HashSet<u64>,
+    },
+    Terminator {
+        offset: usize,
+        delimiter: Vec<u8>,
+    },
+    Prefix {
+        offset: usize,
+    },
+}
+
+pub struct Generator<'g> {
+    structures: Structures<'g>,
+    rng: StdRng,
+}
+
+impl<'g> Generator<'g> {
+    /// Creates a generator for `ufwb`, the same `seed` yields the same samples.
+    pub fn new(ufwb: &'g Ufwb, seed: u64) -> Result<Self> {
+        Ok(Generator {
+            structures: Structures::new(ufwb)?,
+            rng: StdRng::seed_from_u64(seed),
+        })
+    }
+
+    /// Generates a sample of the start structure which can be decoded.
+    pub fn valid(&mut self) -> Result<Sample> {
+        let (data, _) = self.generate()?;
+        Ok(Sample { data, flaw: None })
+    }
+
+    /// Generates a sample which has been broken in one randomly chosen way.
+    pub fn malformed(&mut self) -> Result<Sample> {
+        let (mut data, sites) = self.generate()?;
+        let mut candidates: Vec<Option<&Site>> = sites.iter().map(Some).collect();
+        if !data.is_empty() {
+            // truncation
+            candidates.push(None);
+        }
+        while !candidates.is_empty() {
+            let candidate = candidates.swap_remove(self.rng.gen_range(0..candidates.len()));
+            if let Some(flaw) = self.break_sample(&mut data, candidate) {
+                return Ok(Sample {
+                    data,
+                    flaw: Some(flaw),
+                });
+            }
+        }
+        bail!("The samples of the grammar have nothing which could be broken")
+    }
+
+    fn generate(&mut self) -> Result<(Vec<u8>, Vec<Site>)> {
+        let mut data = Vec::new();
+        let mut sites = Vec::new();
+        let start = self.structures.start();
+        self.structure(start, 0, &mut data, &mut sites)?;
+        Ok((data, sites))
+    }
+
+    /// Breaks `data` at `site` (truncates it for `None`), `None` is returned if that isn't possible.
+    fn break_sample(&mut self, data: &mut Vec<u8>, site: Option<&Site>) -> Option<Flaw> {
+        match site {
+            None => {
+                data.truncate(self.rng.gen_range(0..data.len()));
+                Some(Flaw::Truncated)
+            }
+            Some(Site::Enum {
+                offset,
+                length,
+                big_endian,
+                values,
+            }) => {
+                let value = self.out_of_range(*length, values)?;
+                data.splice(
+                    *offset..*offset + *length,
+                    encode(value, *length, *big_endian),
+                );
+                Some(Flaw::EnumOutOfRange)
+            }
+            Some(Site::Terminator { offset, delimiter }) => {
+                for index in 0..delimiter.len() {
+                    data[offset + index] = self.character(delimiter);
+                }
+                Some(Flaw::WrongTerminator)
+            }
+            Some(Site::Prefix { offset }) => {
+                let length = data[*offset];
+                if length == u8::MAX {
+                    return None;
+                }
+                data[*offset] = self.rng.gen_range(length + 1..=u8::MAX);
+                Some(Flaw::LengthMismatch)
+            }
+        }
+    }
+
+    /// Picks a value of `length` bytes which isn't contained in `values`.
+    fn out_of_range(&mut self, length: usize, values: &HashSet<u64>) -> Option<u64> {
+        let mask = mask(length);
+        let random = (0..16).map(|_| self.rng.gen::<u64>() & mask);
+        let max = values.iter().max().copied().unwrap_or(0);
+        let following = (1..=256).map(|step| max.wrapping_add(step) & mask);
+        let mut candidates = random.collect::<Vec<u64>>().into_iter().chain(following);
+        candidates.find(|value| !values.contains(value))
+    }
+
+    fn structure(
+        &mut self,
+        node: Node<'g>,
+        depth: usize,
+        data: &mut Vec<u8>,
+        sites: &mut Vec<Site>,
+    ) -> Result<()> {
+        if depth > MAX_DEPTH {
+            bail!(
+                "\"{}\" contains itself, its samples would never end",
+                node.name
+            );
+        }
+        for item in node.items.iter().flatten() {
+            for _ in 0..self.repetitions(item)? {
+                match item {
+                    StructureElement::Number(number) => {
+                        self.number(number, node.context, data, sites)?
+                    }
+                    StructureElement::String(string) => self.string(string, data, sites)?,
+                    StructureElement::Structure(structure) => {
+                        let nested = self.structures.get(structure.id);
+                        self.structure(nested, depth + 1, data, sites)?
+                    }
+                    StructureElement::StructRef(structref) => {
+                        let referenced = self.structures.get(structref.structure_id().unwrap_or(0));
+                        self.structure(referenced, depth + 1, data, sites)?
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// How often `item` is repeated, between its bounds like in the
+    /// [decoder](crate::decoder::Decoder).
+    fn repetitions(&mut self, item: &StructureElement) -> Result<usize> {
+        let (repeatmin, repeatmax) = item.repeat();
+        let min = match repeatmin {
+            Some(min) => constant(min, item.name())?,
+            None => 1,
+        };
+        let max = match repeatmax {
+            Some(max) if max.is_unlimited() => min + MAX_REPETITIONS,
+            Some(max) => constant(max, item.name())?,
+            None => 1,
+        };
+        let (min, max) = match repeatmin {
+            Some(_) => (min, max.max(min)),
+            None => (min.min(max), max),
+        };
+        if min == max {
+            Ok(min)
+        } else {
+            Ok(self.rng.gen_range(min..=max))
+        }
+    }
+
+    fn number(
+        &mut self,
+        number: &Number,
+        context: Context,
+        data: &mut Vec<u8>,
+        sites: &mut Vec<Site>,
+    ) -> Result<()> {
+        let context = context.inherit(number.endian.as_ref(), number.signed.as_ref(), None);
+        let length = constant(&number.length, &number.name)?;
+        let length = match number.unit {
+            Some(Unit::Bit) => length / 8,
+            _ => length,
+        };
+        let values: HashSet<u64> = number
+            .fixedvalues
+            .iter()
+            .flat_map(|fixedvalues| fixedvalues.values.iter())
+            .filter_map(|value| value.number())
+            .map(|value| value as u64 & mask(length))
+            .collect();
+        let value = match number.r#type {
+            NumberType::Integer if !values.is_empty() => {
+                let mut sorted: Vec<u64> = values.iter().copied().collect();
+                sorted.sort_unstable();
+                sites.push(Site::Enum {
+                    offset: data.len(),
+                    length,
+                    big_endian: context.big_endian,
+                    values,
+                });
+                sorted[self.rng.gen_range(0..sorted.len())]
+            }
+            NumberType::Float if length == 4 => self.rng.gen_range(-1e6..1e6f32).to_bits() as u64,
+            NumberType::Float if length == 8 => self.rng.gen_range(-1e6..1e6f64).to_bits(),
+            _ => self.rng.gen::<u64>() & mask(length),
+        };
+        data.extend(encode(value, length, context.big_endian));
+        Ok(())
+    }
+
+    fn string(
+        &mut self,
+        string: &grammar::String,
+        data: &mut Vec<u8>,
+        sites: &mut Vec<Site>,
+    ) -> Result<()> {
+        let delimiter = match string.r#type {
+            StringType::ZeroTerminated => vec![0],
+            StringType::DelimiterTerminated => hex(string.delimiter.as_deref().unwrap_or_default()),
+            StringType::FixedLength | StringType::PrefixedLength => Vec::new(),
+        };
+        let length = match string.r#type {
+            StringType::FixedLength => match &string.length {
+                Some(length) => constant(length, &string.name)?,
+                None => 0,
+            },
+            _ => self.rng.gen_range(0..=MAX_TEXT_LENGTH),
+        };
+        if string.r#type == StringType::PrefixedLength {
+            sites.push(Site::Prefix { offset: data.len() });
+            data.push(length as u8);
+        }
+        for _ in 0..length {
+            let character = self.character(&delimiter);
+            data.push(character);
+        }
+        if !delimiter.is_empty() {
+            sites.push(Site::Terminator {
+                offset: data.len(),
+                delimiter: delimiter.clone(),
+            });
+            data.extend(delimiter);
+        }
+        Ok(())
+    }
+
+    /// A printable ascii character which isn't part of `delimiter`.
+    fn character(&mut self, delimiter: &[u8]) -> u8 {
+        loop {
+            let character = self.rng.gen_range(b' '..=b'~');
+            if !delimiter.contains(&character) {
+                return character;
+            }
+        }
+    }
+}
+
+/// Lengths and repeat counts referencing other fields aren't supported (yet).
+fn constant(length: &Length, name: &str) -> Result<usize> {
+    let value = length.expression().ok().and_then(|e| e.constant());
+    match value.and_then(|value| usize::try_from(value).ok()) {
+        Some(value) => Ok(value),
+        None => bail!(
+            "\"{}\" uses \"{}\", only constant lengths and repetitions can be generated",
+            name,
+            length
+        ),
+    }
+}
+
+fn mask(length: usize) -> u64 {
+    match length {
+        0 => 0,
+        1..=7 => (1 << (8 * length)) - 1,
+        _ => u64::MAX,
+    }
+}
+
+/// The lowest `length` bytes of `value` in the given byte order.
+fn encode(value: u64, length: usize, big_endian: bool) -> Vec<u8> {
+    let mut bytes = value.to_le_bytes()[..length.min(8)].to_vec();
+    if big_endian {
+        bytes.reverse();
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::{Decoder, Element, Value};
+    use nom::Err;
+    use quick_xml::de::from_str;
+
+    const OPCODES: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<ufwb version="1.17">
+    <grammar name="Opcodes" start="id:1" author="Nicola Coretti" complete="yes">
+        <description>test</description>
+        <structure name="Packet" id="1" encoding="UTF-8" endian="big" signed="no">
+            <number name="Opcode" id="2" type="integer" length="2">
+                <fixedvalues>
+                    <fixedvalue name="read" value="1"/>
+                    <fixedvalue name="write" value="2"/>
+                </fixedvalues>
+            </number>
+            <string name="Name" id="3" type="zero-terminated"/>
+            <string name="Line" id="4" type="delimiter-terminated" delimiter="0D0A"/>
+            <string name="Pascal" id="5" type="pascal"/>
+        </structure>
+    </grammar>
+</ufwb>"#;
+
+    fn decode_all(decoder: &Decoder, data: &[u8]) -> Element {
+        match decoder.decode(data) {
+            Ok((rest, element)) => {
+                assert!(rest.is_empty(), "{:?} is left", rest);
+                element
+            }
+            Err(e) => panic!("{:?} can't be decoded: {:?}", data, e),
+        }
+    }
+
+    fn opcode(element: &Element) -> Value {
+        match &element.value {
+            Value::Structure(elements) => elements[0].value.clone(),
+            value => panic!("{:?} is not a structure", value),
+        }
+    }
+
+    #[test]
+    fn valid_samples_can_be_decoded() -> Result<()> {
+        for (xml, variable_length) in &[
+            (include_str!("../resources/grammars/Numbers.grammar"), false),
+            (include_str!("../resources/grammars/Strings.grammar"), true),
+            (
+                include_str!("../resources/grammars/TestGrammar.grammar"),
+                true,
+            ),
+            (OPCODES, true),
+        ] {
+            let ufwb: Ufwb = from_str(xml)?;
+            let decoder = Decoder::new(&ufwb)?;
+            let mut generator = Generator::new(&ufwb, 7)?;
+            let mut lengths = HashSet::new();
+            for _ in 0..32 {
+                let sample = generator.valid()?;
+                assert_eq!(sample.flaw, None);
+                decode_all(&decoder, &sample.data);
+                lengths.insert(sample.data.len());
+            }
+            assert_eq!(lengths.len() > 1, *variable_length, "{}", ufwb.grammar.name);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn valid_samples_use_fixed_values() -> Result<()> {
+        let ufwb: Ufwb = from_str(OPCODES)?;
+        let decoder = Decoder::new(&ufwb)?;
+        let mut generator = Generator::new(&ufwb, 1)?;
+        for _ in 0..16 {
+            let element = decode_all(&decoder, &generator.valid()?.data);
+            assert!(matches!(
+                opcode(&element),
+                Value::Unsigned(1) | Value::Unsigned(2)
+            ));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn malformed_samples() -> Result<()> {
+        let ufwb: Ufwb = from_str(OPCODES)?;
+        let decoder = Decoder::new(&ufwb)?;
+        let mut generator = Generator::new(&ufwb, 3)?;
+        let mut flaws = HashSet::new();
+        for _ in 0..64 {
+            let sample = generator.malformed()?;
+            let flaw = sample.flaw.unwrap();
+            flaws.insert(flaw.to_string());
+            let decoded = decoder.decode(&sample.data);
+            match flaw {
+                Flaw::Truncated => assert!(matches!(decoded, Err(Err::Incomplete(_)))),
+                Flaw::EnumOutOfRange => {
+                    let (_, element) = decoded.unwrap();
+                    assert!(!matches!(
+                        opcode(&element),
+                        Value::Unsigned(1) | Value::Unsigned(2)
+                    ))
+                }
+                Flaw::WrongTerminator | Flaw::LengthMismatch => {
+                    assert!(!matches!(decoded, Ok((rest, _)) if rest.is_empty()))
+                }
+            }
+        }
+        assert_eq!(flaws.len(), 4, "{:?}", flaws);
+        Ok(())
+    }
+
+    #[test]
+    fn repetitions_are_within_their_bounds() -> Result<()> {
+        for (repeatmin, repeatmax, min, max) in &[
+            ("0", "-1", 0, MAX_REPETITIONS),
+            ("2", "unlimited", 2, 2 + MAX_REPETITIONS),
+            ("1", "3", 1, 3),
+        ] {
+            let xml = crate::synalize::grammar::fixture(
+                "Repetitions",
+                "Items",
+                &format!(
+                    r#"<number name="Item" id="2" type="integer" length="1" repeatmin="{}" repeatmax="{}"/>"#,
+                    repeatmin, repeatmax
+                ),
+            );
+            let ufwb: Ufwb = from_str(&xml)?;
+            let mut generator = Generator::new(&ufwb, 11)?;
+            let mut counts = HashSet::new();
+            for _ in 0..64 {
+                counts.insert(generator.valid()?.data.len());
+            }
+            let expected: HashSet<usize> = (*min..=*max).collect();
+            assert_eq!(counts, expected, "{} {}", repeatmin, repeatmax);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn samples_depend_on_the_seed() -> Result<()> {
+        let ufwb: Ufwb = from_str(OPCODES)?;
+        let samples = |seed| -> Result<Vec<Sample>> {
+            let mut generator = Generator::new(&ufwb, seed)?;
+            (0..8).map(|_| generator.malformed()).collect()
+        };
+        assert_eq!(samples(5)?, samples(5)?);
+        assert_ne!(samples(5)?, samples(6)?);
+        Ok(())
+    }
+}
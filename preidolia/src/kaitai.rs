@@ -0,0 +1,618 @@
+//! Loads [Kaitai Struct](https://kaitai.io) specs (`.ksy`) into the grammar model of [crate::synalize].
+//!
+//! Types are placed inline where they are used first, further uses reference them.
+//! Enums become fixed values of the number using them. Instances calculated by a `value`
+//! don't occupy any bytes and are left out. Sizes, repetitions (`repeat: expr`) and conditions
+//! (`if`) become [expressions](crate::expression), a condition being a repetition of zero or
+//! one times. Everything a grammar can't express (repetitions until the end of the stream or a
+//! condition, positioned instances) is rejected.
+use crate::expression::Expression;
+use crate::synalize::grammar::{
+    Endianess, FixedValue, FixedValues, Grammar, Length, Number, NumberType, RootStructure,
+    Signedness, String, StringType, StructRef, Structure, StructureElement, Ufwb, Unit,
+};
+use anyhow::{anyhow, bail, Result};
+use std::collections::HashMap;
+
+/// Structures to deserialize a `.ksy` file, only the parts which can be mapped onto a grammar are kept.
+pub mod spec {
+    use serde::Deserialize;
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    pub struct Ksy {
+        pub meta: Meta,
+        #[serde(flatten)]
+        pub root: Type,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    pub struct Meta {
+        pub id: String,
+        pub title: Option<String>,
+        pub endian: Option<Endian>,
+        pub encoding: Option<String>,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq, Clone, Copy)]
+    #[serde(rename_all = "lowercase")]
+    pub enum Endian {
+        Le,
+        Be,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq, Default)]
+    pub struct Type {
+        pub doc: Option<String>,
+        #[serde(default)]
+        pub seq: Vec<Attribute>,
+        #[serde(default)]
+        pub types: BTreeMap<String, Type>,
+        #[serde(default)]
+        pub enums: BTreeMap<String, BTreeMap<i64, EnumValue>>,
+        #[serde(default)]
+        pub instances: BTreeMap<String, Instance>,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(untagged)]
+    pub enum EnumValue {
+        Name(String),
+        Detailed { id: String },
+    }
+
+    impl EnumValue {
+        pub fn name(&self) -> &str {
+            match self {
+                EnumValue::Name(name) | EnumValue::Detailed { id: name } => name,
+            }
+        }
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    pub struct Attribute {
+        pub id: Option<String>,
+        #[serde(rename = "type")]
+        pub r#type: Option<String>,
+        pub size: Option<Size>,
+        pub terminator: Option<u8>,
+        pub contents: Option<Contents>,
+        #[serde(rename = "enum")]
+        pub r#enum: Option<String>,
+        pub repeat: Option<String>,
+        #[serde(rename = "repeat-expr")]
+        pub repeat_expr: Option<Size>,
+        #[serde(rename = "if")]
+        pub condition: Option<String>,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(untagged)]
+    pub enum Size {
+        Fixed(usize),
+        Expression(String),
+    }
+
+    /// Magic bytes, either given as text or as list of bytes and texts.
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(untagged)]
+    pub enum Contents {
+        Text(String),
+        Parts(Vec<Part>),
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(untagged)]
+    pub enum Part {
+        Byte(u8),
+        Text(String),
+    }
+
+    impl Contents {
+        pub fn len(&self) -> usize {
+            match self {
+                Contents::Text(text) => text.len(),
+                Contents::Parts(parts) => parts
+                    .iter()
+                    .map(|part| match part {
+                        Part::Byte(_) => 1,
+                        Part::Text(text) => text.len(),
+                    })
+                    .sum(),
+            }
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.len() == 0
+        }
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    pub struct Instance {
+        pub pos: Option<serde_yaml::Value>,
+        pub value: Option<serde_yaml::Value>,
+    }
+}
+
+use spec::{Attribute, Endian, Ksy, Size, Type};
+
+/// Loads a `.ksy` spec and converts it into a grammar.
+pub fn from_str(ksy: &str) -> Result<Ufwb> {
+    let ksy: Ksy = serde_yaml::from_str(ksy)?;
+    to_grammar(&ksy)
+}
+
+/// Converts a Kaitai Struct spec into a grammar which starts with the top level `seq`.
+pub fn to_grammar(ksy: &Ksy) -> Result<Ufwb> {
+    let mut converter = Converter {
+        types: HashMap::new(),
+        enums: HashMap::new(),
+        structures: HashMap::new(),
+        next_id: 1,
+    };
+    converter.register(&ksy.root)?;
+    let id = converter.id();
+    let items = converter.items(&ksy.root)?;
+    let endian = match ksy.meta.endian {
+        Some(Endian::Le) => Endianess::Little,
+        Some(Endian::Be) | None => Endianess::Big,
+    };
+    Ok(Ufwb {
+        version: "1.17".to_string(),
+        grammar: Grammar {
+            name: ksy
+                .meta
+                .title
+                .clone()
+                .unwrap_or_else(|| ksy.meta.id.clone()),
+            start: format!("id:{}", id),
+            author: std::string::String::new(),
+            email: None,
+            complete: "yes".to_string(),
+            structure: RootStructure {
+                name: ksy.meta.id.clone(),
+                id,
+                encoding: ksy
+                    .meta
+                    .encoding
+                    .clone()
+                    .unwrap_or_else(|| "UTF-8".to_string()),
+                endian,
+                signed: Signedness::Unsigned,
+                items: Some(items),
+            },
+            description: ksy.root.doc.clone().unwrap_or_default(),
+        },
+    })
+}
+
+type Enum = std::collections::BTreeMap<i64, spec::EnumValue>;
+
+struct Converter<'k> {
+    types: HashMap<&'k str, &'k Type>,
+    enums: HashMap<&'k str, &'k Enum>,
+    /// Id of the structure a type has been placed in first.
+    structures: HashMap<&'k str, usize>,
+    next_id: usize,
+}
+
+impl<'k> Converter<'k> {
+    /// Registers the types and enums declared by `ty` and its nested types.
+    ///
+    /// Names are not scoped, they have to be unique within the whole spec.
+    fn register(&mut self, ty: &'k Type) -> Result<()> {
+        for (name, nested) in &ty.types {
+            if self.types.insert(name, nested).is_some() {
+                bail!("Type \"{}\" is declared more than once", name);
+            }
+            self.register(nested)?;
+        }
+        for (name, values) in &ty.enums {
+            if self.enums.insert(name, values).is_some() {
+                bail!("Enum \"{}\" is declared more than once", name);
+            }
+        }
+        Ok(())
+    }
+
+    fn id(&mut self) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    fn items(&mut self, ty: &'k Type) -> Result<Vec<StructureElement>> {
+        for (name, instance) in &ty.instances {
+            if instance.pos.is_some() {
+                bail!(
+                    "Instance \"{}\" is located by position, which is not supported",
+                    name
+                );
+            }
+        }
+        ty.seq
+            .iter()
+            .enumerate()
+            .map(|(index, attribute)| self.attribute(index, attribute))
+            .collect()
+    }
+
+    fn attribute(&mut self, index: usize, attribute: &'k Attribute) -> Result<StructureElement> {
+        let name = attribute
+            .id
+            .clone()
+            .unwrap_or_else(|| format!("unnamed{}", index));
+        let count = match (attribute.repeat.as_deref(), &attribute.repeat_expr) {
+            (None, _) => None,
+            (Some("expr"), Some(count)) => Some(length(&name, count)?),
+            (Some(repeat), _) => bail!(
+                "\"{}\" is repeated \"{}\", only \"expr\" with a \"repeat-expr\" is supported",
+                name,
+                repeat
+            ),
+        };
+        // kaitai repeats exactly, grammars between repeatmin and repeatmax times
+        let repeat = match (&attribute.condition, count) {
+            (None, count) => count,
+            (Some(condition), count) => {
+                let condition = expression(&name, condition)?;
+                Some(Length::Expression(match count {
+                    Some(count) => format!("({}) * ({})", condition, count),
+                    None => condition.to_string(),
+                }))
+            }
+        };
+        let size = match &attribute.size {
+            Some(size) => Some(length(&name, size)?),
+            None => None,
+        };
+        let id = self.id();
+        let ty = match attribute.r#type.as_deref() {
+            None | Some("str") | Some("strz") => {
+                let mut string = string(name, id, attribute, size)?;
+                string.repeatmin = repeat.clone();
+                string.repeatmax = repeat;
+                return Ok(StructureElement::String(string));
+            }
+            // types may be given as path (e.g. `header::entry`)
+            Some(ty) => ty.rsplit("::").next().unwrap_or(ty),
+        };
+        // a size limits the substream a type is parsed from, grammars can't express that
+        let keys = [
+            ("size", attribute.size.is_some()),
+            ("terminator", attribute.terminator.is_some()),
+            ("contents", attribute.contents.is_some()),
+        ];
+        if let Some((key, _)) = keys.iter().find(|(_, given)| *given) {
+            bail!(
+                "\"{}\" has a {} but the type \"{}\", only strings support it",
+                name,
+                key,
+                ty
+            );
+        }
+        if let Some(mut number) = number(&name, id, ty) {
+            if let Some(path) = &attribute.r#enum {
+                let enumeration = path.rsplit("::").next().unwrap_or(path);
+                let values = self
+                    .enums
+                    .get(enumeration)
+                    .ok_or_else(|| anyhow!("\"{}\" uses the unknown enum \"{}\"", name, path))?;
+                number.fixedvalues = Some(FixedValues {
+                    values: values
+                        .iter()
+                        .map(|(value, enum_value)| FixedValue {
+                            name: enum_value.name().to_string(),
+                            value: value.to_string(),
+                        })
+                        .collect(),
+                });
+            }
+            number.repeatmin = repeat.clone();
+            number.repeatmax = repeat;
+            return Ok(StructureElement::Number(number));
+        }
+        if let Some(structure) = self.structures.get(ty) {
+            return Ok(StructureElement::StructRef(StructRef {
+                name,
+                id,
+                structure: format!("id:{}", structure),
+                repeatmin: repeat.clone(),
+                repeatmax: repeat,
+            }));
+        }
+        let declaration = *self
+            .types
+            .get(ty)
+            .ok_or_else(|| anyhow!("\"{}\" has the unknown type \"{}\"", name, ty))?;
+        self.structures.insert(ty, id);
+        Ok(StructureElement::Structure(Structure {
+            name,
+            id,
+            encoding: None,
+            endian: None,
+            signed: None,
+            items: Some(self.items(declaration)?),
+            repeatmin: repeat.clone(),
+            repeatmax: repeat,
+        }))
+    }
+}
+
+/// Sizes and repetitions are either numbers or expressions, the latter are checked upfront.
+fn length(name: &str, size: &Size) -> Result<Length> {
+    Ok(match size {
+        Size::Fixed(size) => Length::Fixed(*size),
+        Size::Expression(text) => Length::Expression(expression(name, text)?.to_string()),
+    })
+}
+
+/// Checks that an expression can be evaluated by the decoder.
+///
+/// Special names like `_io`, `_parent` or `_root` have no counterpart in grammars.
+fn expression<'t>(name: &str, text: &'t str) -> Result<&'t str> {
+    let unsupported = |reason: std::string::String| {
+        anyhow!(
+            "\"{}\" uses the expression \"{}\" which is not supported, {}",
+            name,
+            text,
+            reason
+        )
+    };
+    let expression: Expression = text
+        .parse()
+        .map_err(|error: crate::expression::Error| unsupported(error.to_string()))?;
+    if let Some(path) = expression
+        .references()
+        .into_iter()
+        .find(|path| path.iter().any(|segment| segment.starts_with('_')))
+    {
+        return Err(unsupported(format!("\"{}\" is unknown", path.join("."))));
+    }
+    Ok(text)
+}
+
+/// Maps the builtin types `str`, `strz` and byte arrays (no type) onto strings.
+fn string(
+    name: std::string::String,
+    id: usize,
+    attribute: &Attribute,
+    size: Option<Length>,
+) -> Result<String> {
+    let zero_terminated = attribute.r#type.as_deref() == Some("strz");
+    let (r#type, length, delimiter) = match (size, &attribute.contents, attribute.terminator) {
+        (Some(size), _, _) => (StringType::FixedLength, Some(size), None),
+        (None, Some(contents), _) => (StringType::FixedLength, Some(contents.len().into()), None),
+        (None, None, Some(0)) => (StringType::ZeroTerminated, None, None),
+        (None, None, Some(terminator)) => (
+            StringType::DelimiterTerminated,
+            None,
+            Some(format!("{:02X}", terminator)),
+        ),
+        (None, None, None) if zero_terminated => (StringType::ZeroTerminated, None, None),
+        (None, None, None) => bail!("\"{}\" has neither a size nor a terminator", name),
+    };
+    Ok(String {
+        name,
+        id,
+        length,
+        r#type,
+        delimiter,
+        repeatmin: None,
+        repeatmax: None,
+    })
+}
+
+/// Maps the builtin number types (e.g. `u4le`, `s2`, `f8be`, `b3`), other types yield `None`.
+fn number(name: &str, id: usize, ty: &str) -> Option<Number> {
+    let (ty, endian) = match (ty.strip_suffix("le"), ty.strip_suffix("be")) {
+        (Some(ty), _) => (ty, Some(Endianess::Little)),
+        (_, Some(ty)) => (ty, Some(Endianess::Big)),
+        _ => (ty, None),
+    };
+    let length: usize = ty.get(1..)?.parse().ok()?;
+    let (r#type, signed, unit) = match (ty.get(..1)?, length) {
+        ("u", 1) | ("u", 2) | ("u", 4) | ("u", 8) => (NumberType::Integer, None, None),
+        ("s", 1) | ("s", 2) | ("s", 4) | ("s", 8) => {
+            (NumberType::Integer, Some(Signedness::Signed), None)
+        }
+        ("f", 4) | ("f", 8) => (NumberType::Float, None, None),
+        ("b", 1..=64) if endian.is_none() => (NumberType::Integer, None, Some(Unit::Bit)),
+        _ => return None,
+    };
+    Some(Number {
+        name: name.to_string(),
+        id,
+        r#type,
+        length: Length::Fixed(length),
+        unit,
+        endian,
+        signed,
+        fixedvalues: None,
+        repeatmin: None,
+        repeatmax: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synalize::xml;
+
+    #[test]
+    fn convert_spec() -> Result<()> {
+        let ufwb = from_str(
+            r#"
+meta:
+  id: packet
+  title: Packet
+  endian: le
+  encoding: ASCII
+doc: A made up packet format
+seq:
+  - id: magic
+    contents: [0x50, 0x4b, "!"]
+  - id: kind
+    type: u1
+    enum: kind
+  - id: length
+    type: s4be
+  - id: header
+    type: header
+  - id: trailer
+    type: header
+  - id: name
+    type: strz
+  - id: line
+    type: str
+    terminator: 10
+types:
+  header:
+    seq:
+      - id: flags
+        type: b8
+      - id: ratio
+        type: f4
+enums:
+  kind:
+    1: request
+    2:
+      id: response
+      doc: answer to a request
+instances:
+  total:
+    value: length + 4
+"#,
+        )?;
+        assert_eq!(
+            xml::to_string(&ufwb)?,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<ufwb version="1.17">
+    <grammar name="Packet" start="id:1" author="" complete="yes">
+        <description>A made up packet format</description>
+        <structure name="packet" id="1" encoding="ASCII" endian="little" signed="no">
+            <string name="magic" id="2" type="fixed-length" length="3"/>
+            <number name="kind" id="3" type="integer" length="1">
+                <fixedvalues>
+                    <fixedvalue name="request" value="1"/>
+                    <fixedvalue name="response" value="2"/>
+                </fixedvalues>
+            </number>
+            <number name="length" id="4" type="integer" length="4" endian="big" signed="yes"/>
+            <structure name="header" id="5">
+                <number name="flags" id="6" type="integer" length="8" lengthunit="bit"/>
+                <number name="ratio" id="7" type="float" length="4"/>
+            </structure>
+            <structref name="trailer" id="8" structure="id:5"/>
+            <string name="name" id="9" type="zero-terminated"/>
+            <string name="line" id="10" type="delimiter-terminated" delimiter="0A"/>
+        </structure>
+    </grammar>
+</ufwb>
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn recursive_types_are_referenced() -> Result<()> {
+        let ufwb = from_str(
+            r#"
+meta:
+  id: tree
+seq:
+  - id: root
+    type: node
+types:
+  node:
+    seq:
+      - id: value
+        type: u1
+      - id: child
+        type: node
+"#,
+        )?;
+        let items = ufwb.grammar.structure.items.unwrap();
+        let node = match &items[0] {
+            StructureElement::Structure(node) => node,
+            other => panic!("{:?} is not a structure", other),
+        };
+        let child = &node.items.as_ref().unwrap()[1];
+        assert!(
+            matches!(child, StructureElement::StructRef(r) if r.structure_id() == Some(node.id))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn unsupported_features_are_rejected() {
+        let spec =
+            |attribute: &str| format!("meta:\n  id: test\nseq:\n  - id: field\n{}\n", attribute);
+        for attribute in &[
+            "    type: u1\n    repeat: eos",
+            "    type: u1\n    repeat: expr",
+            "    type: u1\n    if: \"length > 0 ? 1 : 0\"",
+            "    size: _io.size - 4",
+            "    size: lengths[0]",
+            "    type: unknown",
+            "    type: u1\n    enum: unknown",
+            "    type: u4\n    size: 2",
+            "    type: header\n    size: 8",
+            "    type: u2\n    terminator: 0",
+            "    type: u1\n    contents: [1]",
+        ] {
+            let error = from_str(&spec(attribute)).unwrap_err();
+            assert!(
+                error.to_string().contains("\"field\""),
+                "{}: {}",
+                attribute,
+                error
+            );
+        }
+        let positioned = spec("    type: u1\ninstances:\n  at:\n    pos: 4\n    type: u1");
+        let error = from_str(&positioned).unwrap_err();
+        assert!(error.to_string().contains("\"at\""), "{}", error);
+    }
+
+    #[test]
+    fn sizes_repetitions_and_conditions_become_expressions() -> Result<()> {
+        let ufwb = from_str(
+            r#"
+meta:
+  id: records
+seq:
+  - id: count
+    type: u1
+  - id: lengths
+    type: u2
+    repeat: expr
+    repeat-expr: count
+  - id: name
+    size: count - 1
+    if: count > 0
+  - id: extra
+    type: u1
+    repeat: expr
+    repeat-expr: 2
+    if: count == 3
+"#,
+        )?;
+        assert_eq!(
+            xml::to_string(&ufwb)?,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<ufwb version="1.17">
+    <grammar name="records" start="id:1" author="" complete="yes">
+        <description></description>
+        <structure name="records" id="1" encoding="UTF-8" endian="big" signed="no">
+            <number name="count" id="2" type="integer" length="1"/>
+            <number name="lengths" id="3" repeatmin="count" repeatmax="count" type="integer" length="2"/>
+            <string name="name" id="4" repeatmin="count &gt; 0" repeatmax="count &gt; 0" type="fixed-length" length="count - 1"/>
+            <number name="extra" id="5" repeatmin="(count == 3) * (2)" repeatmax="(count == 3) * (2)" type="integer" length="1"/>
+        </structure>
+    </grammar>
+</ufwb>
+"#
+        );
+        Ok(())
+    }
+}
@@ -1,2 +1,24 @@
-pub mod synalize;
+pub mod codegen;
+pub mod decoder;
+pub mod expression;
+pub mod generator;
+pub mod kaitai;
 pub mod parsers;
+pub mod synalize;
+
+use std::path::Path;
+
+/// Loads a grammar, the format is derived from the extension of `path`.
+///
+/// Kaitai Struct specs (`.ksy`) are converted, json and yaml are read as exported by
+/// `preidolia convert`, everything else as Synalyze It xml.
+pub fn load_grammar(path: &Path) -> anyhow::Result<synalize::grammar::Ufwb> {
+    let content = std::fs::read_to_string(path)?;
+    let extension = path.extension().and_then(|extension| extension.to_str());
+    Ok(match extension {
+        Some("ksy") => kaitai::from_str(&content)?,
+        Some("json") => serde_json::from_str(&content)?,
+        Some("yaml") | Some("yml") => serde_yaml::from_str(&content)?,
+        _ => quick_xml::de::from_str(&content)?,
+    })
+}
@@ -1,57 +1,381 @@
-use anyhow::{anyhow, Result};
+use anyhow::Result;
+use futures::Stream;
 use nom::*;
+use serde::Serialize;
 use std::io::Read;
+use tokio::io::{AsyncRead, AsyncReadExt};
 
-fn parse_from_read<I: Read, O: Sized>(
-    parser: &dyn Fn(&[u8]) -> nom::IResult<&[u8], O>,
-    buffer: &mut Vec<u8>,
-    input: &mut I,
-) -> Result<O> {
-    loop {
-        match (parser)(&buffer) {
-            Ok((_, value)) => return Ok(value),
-            Err(e) => match e {
-                Err::Incomplete(Needed::Size(size)) => {
-                    let mut buf: Vec<u8> = vec![0; size.get()];
-                    match input.read_exact(&mut buf) {
-                        Ok(_) => {
-                            buffer.append(&mut buf);
+/// Amount of bytes which is requested from the input at once, if not specified otherwise.
+pub const DEFAULT_CHUNK_SIZE: usize = 4096;
+
+/// Signature of the (streaming) nom parsers driven by a [Parser].
+pub type ParseFn<'a, O> = dyn Fn(&[u8]) -> nom::IResult<&[u8], O> + 'a;
+
+/// Keeps the bytes which have been read from an input but not yet consumed by a parser.
+#[derive(Debug)]
+pub struct Buffer {
+    data: Vec<u8>,
+    start: usize,
+    chunk_size: usize,
+    consumed: u64,
+}
+
+impl Buffer {
+    pub fn new(chunk_size: usize) -> Self {
+        Buffer {
+            data: Vec::new(),
+            start: 0,
+            chunk_size: chunk_size.max(1),
+            consumed: 0,
+        }
+    }
+
+    /// Bytes which have not been consumed yet.
+    pub fn data(&self) -> &[u8] {
+        &self.data[self.start..]
+    }
+
+    /// Absolute offset (within the input) of the first byte which has not been consumed yet.
+    pub fn offset(&self) -> u64 {
+        self.consumed
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len() - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Marks `count` bytes as consumed, the memory of consumed bytes is released once
+    /// they take up more than a chunk.
+    pub fn consume(&mut self, count: usize) {
+        let count = count.min(self.len());
+        self.consumed += count as u64;
+        self.start += count;
+        if self.start == self.data.len() {
+            self.data.clear();
+            self.start = 0;
+        } else if self.start >= self.chunk_size {
+            self.data.drain(..self.start);
+            self.start = 0;
+        }
+    }
+
+    /// Reads at least `needed` bytes from `input`, returns false if the input ended before.
+    pub fn fill<R: Read>(&mut self, input: &mut R, needed: usize) -> std::io::Result<bool> {
+        let target = self.data.len() + needed;
+        while self.data.len() < target {
+            let offset = self.reserve();
+            let result = input.read(&mut self.data[offset..]);
+            match self.commit(offset, result) {
+                Ok(0) => return Ok(false),
+                Ok(_) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(true)
+    }
+
+    /// Reads at least `needed` bytes from `input`, returns false if the input ended before.
+    pub async fn fill_async<R: AsyncRead + Unpin>(
+        &mut self,
+        input: &mut R,
+        needed: usize,
+    ) -> std::io::Result<bool> {
+        let target = self.data.len() + needed;
+        while self.data.len() < target {
+            let offset = self.reserve();
+            let result = input.read(&mut self.data[offset..]).await;
+            match self.commit(offset, result) {
+                Ok(0) => return Ok(false),
+                Ok(_) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(true)
+    }
+
+    /// Makes room for the next read of a chunk and returns where it shall start. Large
+    /// amounts of needed bytes are read chunk by chunk, so memory only grows with the input.
+    fn reserve(&mut self) -> usize {
+        let offset = self.data.len();
+        self.data.resize(offset + self.chunk_size, 0);
+        offset
+    }
+
+    /// Drops the part of the reserved space which has not been filled by a read.
+    fn commit(&mut self, offset: usize, result: std::io::Result<usize>) -> std::io::Result<usize> {
+        let read = *result.as_ref().unwrap_or(&0);
+        self.data.truncate(offset + read);
+        result
+    }
+}
+
+impl Default for Buffer {
+    fn default() -> Self {
+        Buffer::new(DEFAULT_CHUNK_SIZE)
+    }
+}
+
+/// Determines the length of a frame from its first bytes, `None` is returned if more bytes are needed.
+pub type FrameLength = dyn Fn(&[u8]) -> Option<usize>;
+
+/// Defines how parsing continues after a record could not be parsed.
+#[derive(Default)]
+pub enum Recovery<'a> {
+    /// Report the error and stop parsing.
+    #[default]
+    Stop,
+    /// Skip a single byte and retry.
+    SkipByte,
+    /// Skip bytes until the sync pattern (e.g. a magic) is found.
+    Resync(Vec<u8>),
+    /// Skip the whole frame which could not be parsed.
+    SkipFrame(&'a FrameLength),
+}
+
+/// Error for a record which could not be parsed.
+#[derive(Debug, PartialEq)]
+pub struct ParseError {
+    /// Absolute offset of the record within the input.
+    pub offset: u64,
+    pub message: String,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "Failed to parse record at offset {}, {}",
+            self.offset, self.message
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// A parsed record together with its position in the input.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct Spanned<T> {
+    /// Absolute offset of the first byte of the record.
+    pub offset: u64,
+    /// Number of bytes the record occupies.
+    pub length: usize,
+    pub value: T,
+}
+
+enum Progress<O> {
+    Parsed(Spanned<O>),
+    Needs(usize),
+}
+
+fn parse_buffered<O>(parser: &ParseFn<O>, buffer: &mut Buffer) -> Result<Progress<O>> {
+    let offset = buffer.offset();
+    let error = |e: nom::error::Error<&[u8]>, data: &[u8]| ParseError {
+        offset,
+        message: format!(
+            "{:?} at offset {}",
+            e.code,
+            offset + (data.len() - e.input.len()) as u64
+        ),
+    };
+    match (parser)(buffer.data()) {
+        // a record without bytes would be parsed over and over again
+        Ok(_) if buffer.is_empty() => Ok(Progress::Needs(1)),
+        Ok((rest, _)) if rest.len() == buffer.len() => Err(ParseError {
+            offset,
+            message: String::from("the parser did not consume any bytes"),
+        }
+        .into()),
+        Ok((rest, value)) => {
+            let consumed = buffer.len() - rest.len();
+            buffer.consume(consumed);
+            Ok(Progress::Parsed(Spanned {
+                offset,
+                length: consumed,
+                value,
+            }))
+        }
+        Err(Err::Incomplete(Needed::Size(size))) => Ok(Progress::Needs(size.get())),
+        Err(Err::Incomplete(Needed::Unknown)) => Ok(Progress::Needs(1)),
+        Err(Err::Error(e)) => Err(error(e, buffer.data()).into()),
+        Err(Err::Failure(e)) => Err(error(e, buffer.data()).into()),
+    }
+}
+
+enum State {
+    Parsing,
+    Resyncing,
+    MeasuringFrame,
+    Skipping(usize),
+    Stopped,
+}
+
+pub struct Parser<'a, O: Sized> {
+    parser: &'a ParseFn<'a, O>,
+    buffer: Buffer,
+    recovery: Recovery<'a>,
+    state: State,
+}
+
+impl<'a, O: Sized> Parser<'a, O> {
+    pub fn new(parser: &'a ParseFn<'a, O>) -> Self {
+        Parser {
+            parser,
+            buffer: Buffer::default(),
+            recovery: Recovery::default(),
+            state: State::Parsing,
+        }
+    }
+
+    /// Requests (at least) `chunk_size` bytes whenever more input is needed.
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.buffer = Buffer::new(chunk_size);
+        self
+    }
+
+    /// Sets how parsing continues after a record could not be parsed.
+    pub fn with_recovery(mut self, recovery: Recovery<'a>) -> Self {
+        self.recovery = recovery;
+        self
+    }
+
+    fn parse<I: Read>(&mut self, input: &mut I) -> Result<Spanned<O>> {
+        loop {
+            match self.step()? {
+                Progress::Parsed(value) => return Ok(value),
+                Progress::Needs(size) => {
+                    if !self.buffer.fill(input, size)? {
+                        return Err(self.end_of_input());
+                    }
+                }
+            }
+        }
+    }
+
+    async fn parse_async<I: AsyncRead + Unpin>(&mut self, input: &mut I) -> Result<Spanned<O>> {
+        loop {
+            match self.step()? {
+                Progress::Parsed(value) => return Ok(value),
+                Progress::Needs(size) => {
+                    if !self.buffer.fill_async(input, size).await? {
+                        return Err(self.end_of_input());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Parses or skips (while recovering) the buffered input until a record is parsed or more input is needed.
+    fn step(&mut self) -> Result<Progress<O>> {
+        loop {
+            match self.state {
+                State::Parsing => {
+                    let result = parse_buffered(self.parser, &mut self.buffer);
+                    if result.is_err() {
+                        self.start_recovery();
+                    }
+                    return result;
+                }
+                State::Resyncing => {
+                    let pattern = match &self.recovery {
+                        Recovery::Resync(pattern) if !pattern.is_empty() => pattern,
+                        _ => {
+                            self.state = State::Parsing;
+                            continue;
                         }
-                        Err(e) => return Err(anyhow::Error::new(e)),
                     };
-                }
-                Err::Incomplete(Needed::Unknown) => {
-                    let mut buf: Vec<u8> = vec![0; 1];
-                    match input.read_exact(&mut buf) {
-                        Ok(_) => {
-                            buffer.append(&mut buf);
+                    let data = self.buffer.data();
+                    match data.windows(pattern.len()).position(|w| w == &pattern[..]) {
+                        Some(position) => {
+                            self.buffer.consume(position);
+                            self.state = State::Parsing;
+                        }
+                        None => {
+                            // keep the bytes which could be the start of the pattern
+                            let skip = data.len().saturating_sub(pattern.len() - 1);
+                            self.buffer.consume(skip);
+                            return Ok(Progress::Needs(1));
                         }
-                        Err(e) => return Err(anyhow::Error::new(e)),
+                    }
+                }
+                State::MeasuringFrame => {
+                    let length = match &self.recovery {
+                        Recovery::SkipFrame(length) => (length)(self.buffer.data()),
+                        _ => Some(0),
                     };
+                    match length {
+                        Some(length) => self.state = State::Skipping(length.max(1)),
+                        None => return Ok(Progress::Needs(1)),
+                    }
                 }
-                Err::Error(e) => {
-                    return Err(anyhow!("Failed to parse, {:?}", e));
+                State::Skipping(remaining) => {
+                    let skip = remaining.min(self.buffer.len());
+                    self.buffer.consume(skip);
+                    if skip < remaining {
+                        self.state = State::Skipping(remaining - skip);
+                        return Ok(Progress::Needs(1));
+                    }
+                    self.state = State::Parsing;
                 }
-                Err::Failure(e) => {
-                    return Err(anyhow!("Failed to parse, {:?}", e));
+                State::Stopped => {
+                    return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into())
                 }
-            },
+            }
         }
     }
-}
 
-pub struct Parser<'a, O: Sized> {
-    parser: &'a dyn Fn(&[u8]) -> nom::IResult<&[u8], O>,
-}
+    fn start_recovery(&mut self) {
+        self.state = match self.recovery {
+            Recovery::Stop => State::Stopped,
+            Recovery::SkipByte => State::Skipping(1),
+            Recovery::Resync(_) => {
+                // skip the first byte, otherwise the pattern would be found where parsing failed
+                self.buffer.consume(1);
+                State::Resyncing
+            }
+            Recovery::SkipFrame(_) => State::MeasuringFrame,
+        };
+    }
 
-impl<'a, O: Sized> Parser<'a, O> {
-    pub fn new(parser: &'a dyn Fn(&[u8]) -> nom::IResult<&[u8], O>) -> Self {
-        Parser { parser }
+    /// Creates the error for an input which ended, a partially read record is dropped.
+    fn end_of_input(&mut self) -> anyhow::Error {
+        let offset = self.buffer.offset();
+        let left = self.buffer.len();
+        self.buffer.consume(left);
+        match (&self.state, left) {
+            (State::Parsing, 0) | (State::Stopped, _) => {
+                anyhow::Error::new(std::io::Error::from(std::io::ErrorKind::UnexpectedEof))
+            }
+            (State::Parsing, _) => ParseError {
+                offset,
+                message: format!("input ended within the record, {} bytes are left", left),
+            }
+            .into(),
+            // the record which caused the recovery has already been reported
+            _ => {
+                self.state = State::Stopped;
+                anyhow::Error::new(std::io::Error::from(std::io::ErrorKind::UnexpectedEof))
+            }
+        }
     }
+}
 
-    fn parse<I: Read>(&mut self, input: &mut I) -> Result<O> {
-        let mut buf: Vec<u8> = Vec::new();
-        parse_from_read(self.parser, &mut buf, input)
+/// Ends the iteration if the input ended cleanly (between two records).
+fn next_item<O>(result: Result<O>) -> Option<Result<O>> {
+    match result {
+        Ok(value) => Some(Ok(value)),
+        Err(e) => match e.root_cause().downcast_ref::<std::io::Error>() {
+            Some(io_error) => match io_error.kind() {
+                std::io::ErrorKind::UnexpectedEof => None,
+                _ => Some(Err(e)),
+            },
+            _ => Some(Err(e)),
+        },
     }
 }
 
@@ -64,29 +388,76 @@ impl<'a, R: Read, O: Sized> ParsingIterator<'a, R, O> {
     pub fn new(parser: Parser<'a, O>, input: R) -> Self {
         ParsingIterator { input, parser }
     }
+
+    /// Yields the records together with their offset and length in the input.
+    pub fn spanned(self) -> SpannedParsingIterator<'a, R, O> {
+        SpannedParsingIterator(self)
+    }
 }
 
 impl<'a, R: Read, O> Iterator for ParsingIterator<'a, R, O> {
     type Item = Result<O>;
 
     fn next(&mut self) -> Option<Result<O>> {
-        match self.parser.parse(&mut self.input) {
-            Ok(value) => Some(Ok(value)),
-            Err(e) => match e.root_cause().downcast_ref::<std::io::Error>() {
-                Some(io_error) => match io_error.kind() {
-                    std::io::ErrorKind::UnexpectedEof => None,
-                    _ => Some(Err(e)),
-                },
-                _ => Some(Err(e)),
-            }, //Some(Err(e)),
-        }
+        next_item(
+            self.parser
+                .parse(&mut self.input)
+                .map(|record| record.value),
+        )
+    }
+}
+
+/// [ParsingIterator] which yields [Spanned] records, see [ParsingIterator::spanned].
+pub struct SpannedParsingIterator<'a, R: Read, O: Sized>(ParsingIterator<'a, R, O>);
+
+impl<'a, R: Read, O> Iterator for SpannedParsingIterator<'a, R, O> {
+    type Item = Result<Spanned<O>>;
+
+    fn next(&mut self) -> Option<Result<Spanned<O>>> {
+        next_item(self.0.parser.parse(&mut self.0.input))
+    }
+}
+
+/// Asynchronous counterpart of the [ParsingIterator] for tokio based inputs (e.g. sockets).
+pub struct ParsingStream<'a, R: AsyncRead + Unpin, O: Sized> {
+    input: R,
+    parser: Parser<'a, O>,
+}
+
+impl<'a, R: AsyncRead + Unpin + 'a, O: Sized + 'a> ParsingStream<'a, R, O> {
+    pub fn new(parser: Parser<'a, O>, input: R) -> Self {
+        ParsingStream { input, parser }
+    }
+
+    /// Parses the next record, `None` is returned once the input ended.
+    pub async fn next(&mut self) -> Option<Result<O>> {
+        next_item(
+            self.parser
+                .parse_async(&mut self.input)
+                .await
+                .map(|record| record.value),
+        )
+    }
+
+    /// Parses the next record and keeps its offset and length, see [ParsingIterator::spanned].
+    pub async fn next_spanned(&mut self) -> Option<Result<Spanned<O>>> {
+        next_item(self.parser.parse_async(&mut self.input).await)
+    }
+
+    /// Converts this into a [Stream] of parsed records.
+    pub fn into_stream(self) -> impl Stream<Item = Result<O>> + 'a {
+        futures::stream::unfold(self, |mut stream| async move {
+            stream.next().await.map(|item| (item, stream))
+        })
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use futures::StreamExt;
     use nom::number::streaming::be_u32;
+    use nom::number::streaming::be_u64;
     use nom::number::streaming::be_u8;
 
     #[derive(Debug, PartialEq)]
@@ -120,6 +491,43 @@ mod tests {
         )
     }
 
+    fn parse_string(input: &[u8]) -> nom::IResult<&[u8], String> {
+        do_parse!(
+            input,
+            value: map_res!(take_until!("\0"), std::str::from_utf8)
+                >> take!(1)
+                >> (String::from(value))
+        )
+    }
+
+    /// Parses a record consisting of the magic `AB` and a one byte value.
+    fn parse_record(input: &[u8]) -> nom::IResult<&[u8], u8> {
+        do_parse!(input, tag!("AB") >> value: be_u8 >> (value))
+    }
+
+    /// Parses a frame consisting of a one byte length and a record.
+    fn parse_frame(input: &[u8]) -> nom::IResult<&[u8], u8> {
+        do_parse!(input, _length: be_u8 >> value: parse_record >> (value))
+    }
+
+    fn offsets<O>(results: Vec<Result<O>>) -> Vec<u64> {
+        results
+            .into_iter()
+            .filter_map(|r| r.err())
+            .map(|e| e.downcast::<ParseError>().unwrap().offset)
+            .collect()
+    }
+
+    /// Reader which only hands out one byte per read, like a slow socket.
+    struct Trickle(std::io::Cursor<Vec<u8>>);
+
+    impl Read for Trickle {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = buf.len().min(1);
+            Read::read(&mut self.0, &mut buf[..len])
+        }
+    }
+
     #[test]
     fn test_parse_u32() {
         let input: Vec<u8> = vec![0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02];
@@ -171,6 +579,192 @@ mod tests {
         let mut parser: Parser<Identifier> = Parser::new(&parse_identifier);
         let r = parser.parse(&mut cursor);
         assert!(r.is_ok());
-        assert_eq!(expected, r.unwrap())
+        assert_eq!(expected, r.unwrap().value)
+    }
+
+    #[test]
+    fn keeps_bytes_read_ahead_for_the_next_record() {
+        let input = b"first\0second\0third\0".to_vec();
+        for chunk_size in &[1, 3, 7, DEFAULT_CHUNK_SIZE] {
+            let parser = Parser::new(&parse_string).with_chunk_size(*chunk_size);
+            let iter = ParsingIterator::new(parser, std::io::Cursor::new(input.clone()));
+            let values: Vec<String> = iter.map(|r| r.unwrap()).collect();
+            assert_eq!(values, vec!["first", "second", "third"]);
+        }
+    }
+
+    #[test]
+    fn reads_whatever_is_available() {
+        let input = Trickle(std::io::Cursor::new(b"slow\0socket\0".to_vec()));
+        let iter = ParsingIterator::new(Parser::new(&parse_string), input);
+        let values: Vec<String> = iter.map(|r| r.unwrap()).collect();
+        assert_eq!(values, vec!["slow", "socket"]);
+    }
+
+    #[test]
+    fn truncated_record_is_an_error() {
+        let input = std::io::Cursor::new(vec![0x00, 0x00, 0x00, 0x01, 0x00, 0x00]);
+        let mut iter = ParsingIterator::new(Parser::new(&parse_u32), input);
+        assert_eq!(iter.next().unwrap().unwrap(), 1);
+        assert!(iter.next().unwrap().is_err());
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn consumed_bytes_are_released() {
+        let mut buffer = Buffer::new(4);
+        let mut input = std::io::Cursor::new(vec![0u8; 10]);
+        assert!(buffer.fill(&mut input, 6).unwrap());
+        assert_eq!(buffer.len(), 8);
+        buffer.consume(2);
+        assert_eq!(buffer.data.len(), 8);
+        buffer.consume(2);
+        assert_eq!(buffer.data.len(), 4);
+        assert_eq!(buffer.len(), 4);
+        assert!(!buffer.fill(&mut input, 8).unwrap());
+        assert_eq!(buffer.len(), 6);
+    }
+
+    #[test]
+    fn needed_bytes_are_read_chunk_by_chunk() {
+        let mut buffer = Buffer::new(4);
+        let mut input = std::io::Cursor::new(vec![0u8; 10]);
+        assert!(!buffer.fill(&mut input, usize::MAX / 2).unwrap());
+        assert_eq!(buffer.len(), 10);
+        assert!(buffer.data.capacity() < 64);
+
+        // a length field announcing far more bytes than the input has
+        fn parse_sized(input: &[u8]) -> nom::IResult<&[u8], usize> {
+            do_parse!(input, length: be_u64 >> data: take!(length) >> (data.len()))
+        }
+        let input = std::io::Cursor::new(b"\x7f\xff\xff\xff\xff\xff\xff\xf0abc".to_vec());
+        let mut iter = ParsingIterator::new(Parser::new(&parse_sized), input);
+        assert!(iter.next().unwrap().is_err());
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn parsers_must_consume_bytes() {
+        fn parse_nothing(input: &[u8]) -> nom::IResult<&[u8], ()> {
+            Ok((input, ()))
+        }
+        let input = std::io::Cursor::new(b"x".to_vec());
+        let mut iter = ParsingIterator::new(Parser::new(&parse_nothing), input);
+        let error = iter.next().unwrap().unwrap_err();
+        assert_eq!(error.downcast::<ParseError>().unwrap().offset, 0);
+        assert!(iter.next().is_none());
+
+        let input = std::io::Cursor::new(Vec::new());
+        assert!(ParsingIterator::new(Parser::new(&parse_nothing), input)
+            .next()
+            .is_none());
+    }
+
+    #[test]
+    fn stop_after_error_by_default() {
+        let input = std::io::Cursor::new(b"AB\x01XXAB\x02".to_vec());
+        let mut iter = ParsingIterator::new(Parser::new(&parse_record), input);
+        assert_eq!(iter.next().unwrap().unwrap(), 1);
+        let error = iter.next().unwrap().unwrap_err();
+        assert_eq!(
+            error.downcast::<ParseError>().unwrap(),
+            ParseError {
+                offset: 3,
+                message: String::from("Tag at offset 3"),
+            }
+        );
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn recover_by_skipping_bytes() {
+        let input = std::io::Cursor::new(b"AB\x01XXAB\x02".to_vec());
+        let parser = Parser::new(&parse_record).with_recovery(Recovery::SkipByte);
+        let results: Vec<Result<u8>> = ParsingIterator::new(parser, input).collect();
+        assert_eq!(offsets(results), vec![3, 4]);
+
+        let input = std::io::Cursor::new(b"AB\x01XXAB\x02".to_vec());
+        let parser = Parser::new(&parse_record).with_recovery(Recovery::SkipByte);
+        let values: Vec<u8> = ParsingIterator::new(parser, input)
+            .filter_map(|r| r.ok())
+            .collect();
+        assert_eq!(values, vec![1, 2]);
+    }
+
+    #[test]
+    fn recover_by_resynchronisation() {
+        let input = b"AB\x01AXB\x02XAB\x03XXXX".to_vec();
+        for chunk_size in &[1, 2, DEFAULT_CHUNK_SIZE] {
+            let parser = Parser::new(&parse_record)
+                .with_chunk_size(*chunk_size)
+                .with_recovery(Recovery::Resync(b"AB".to_vec()));
+            let results: Vec<Result<u8>> =
+                ParsingIterator::new(parser, std::io::Cursor::new(input.clone())).collect();
+            let values: Vec<u8> = results
+                .iter()
+                .filter_map(|r| r.as_ref().ok())
+                .cloned()
+                .collect();
+            assert_eq!(values, vec![1, 3]);
+            assert_eq!(offsets(results), vec![3, 11]);
+        }
+    }
+
+    #[test]
+    fn recover_by_skipping_frames() {
+        let frame_length = |data: &[u8]| data.first().map(|length| *length as usize + 1);
+        let input = b"\x03AB\x01\x05XXXXX\x03AB\x02".to_vec();
+        for chunk_size in &[1, 3, DEFAULT_CHUNK_SIZE] {
+            let parser = Parser::new(&parse_frame)
+                .with_chunk_size(*chunk_size)
+                .with_recovery(Recovery::SkipFrame(&frame_length));
+            let results: Vec<Result<u8>> =
+                ParsingIterator::new(parser, std::io::Cursor::new(input.clone())).collect();
+            let values: Vec<u8> = results
+                .iter()
+                .filter_map(|r| r.as_ref().ok())
+                .cloned()
+                .collect();
+            assert_eq!(values, vec![1, 2]);
+            assert_eq!(offsets(results), vec![4]);
+        }
+    }
+
+    #[test]
+    fn spans_are_absolute_offsets() {
+        let input = b"AB\x01XXAB\x02AB\x03".to_vec();
+        let parser = Parser::new(&parse_record)
+            .with_chunk_size(2)
+            .with_recovery(Recovery::Resync(b"AB".to_vec()));
+        let records: Vec<Spanned<u8>> = ParsingIterator::new(parser, std::io::Cursor::new(input))
+            .spanned()
+            .filter_map(|r| r.ok())
+            .collect();
+        let spans: Vec<(u64, usize, u8)> = records
+            .into_iter()
+            .map(|r| (r.offset, r.length, r.value))
+            .collect();
+        assert_eq!(spans, vec![(0, 3, 1), (5, 3, 2), (8, 3, 3)]);
+    }
+
+    #[test]
+    fn spanned_record_as_json() {
+        let record = Spanned {
+            offset: 4,
+            length: 2,
+            value: 7,
+        };
+        assert_eq!(
+            serde_json::to_string(&record).unwrap(),
+            r#"{"offset":4,"length":2,"value":7}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn parse_async_stream() {
+        let input: &[u8] = &[0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02];
+        let stream = ParsingStream::new(Parser::new(&parse_u32).with_chunk_size(3), input);
+        let values: Vec<u32> = stream.into_stream().map(|r| r.unwrap()).collect().await;
+        assert_eq!(values, vec![1, 2]);
     }
 }
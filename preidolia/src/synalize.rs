@@ -1,45 +1,74 @@
 /// Contains structures to parse [Synalyze It](https://www.synalysis.net)/[Hexinator](https://hexinator.com) grammar files.
 pub mod grammar {
-    use serde::{Deserialize, Serialize};
+    use crate::expression::{Error as ExpressionError, Expression};
+    use serde::de::{self, Deserializer, Visitor};
+    use serde::{Deserialize, Serialize, Serializer};
+    use std::fmt;
 
     #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    #[serde(deny_unknown_fields)]
     pub struct Ufwb {
-        version: std::string::String,
-        grammar: Grammar,
+        pub version: std::string::String,
+        pub grammar: Grammar,
     }
 
     #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    #[serde(deny_unknown_fields)]
     pub struct Grammar {
-        name: std::string::String,
-        start: std::string::String,
-        author: std::string::String,
-        email: Option<std::string::String>,
-        complete: std::string::String,
-        structure: RootStructure,
-        description: std::string::String,
+        pub name: std::string::String,
+        pub start: std::string::String,
+        pub author: std::string::String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub email: Option<std::string::String>,
+        pub complete: std::string::String,
+        pub structure: RootStructure,
+        pub description: std::string::String,
+    }
+
+    impl Grammar {
+        /// Id of the structure the grammar starts with, if `start` is a valid reference.
+        pub fn start_id(&self) -> Option<usize> {
+            reference(&self.start)
+        }
     }
 
     #[derive(Debug, Serialize, Deserialize, PartialEq)]
-    #[serde(rename = "structure")]
+    #[serde(rename = "structure", deny_unknown_fields)]
     pub struct RootStructure {
-        name: std::string::String,
-        id: usize,
-        encoding: std::string::String,
-        endian: Endianess,
-        signed: Signedness,
-        #[serde(rename(deserialize = "$value"))]
-        items: Option<Vec<StructureElement>>,
+        pub name: std::string::String,
+        pub id: usize,
+        pub encoding: std::string::String,
+        pub endian: Endianess,
+        pub signed: Signedness,
+        #[serde(
+            rename(deserialize = "$value"),
+            alias = "items",
+            skip_serializing_if = "Option::is_none"
+        )]
+        pub items: Option<Vec<StructureElement>>,
     }
 
     #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    #[serde(deny_unknown_fields)]
     pub struct Structure {
-        name: std::string::String,
-        id: usize,
-        encoding: Option<std::string::String>,
-        endian: Option<Endianess>,
-        signed: Option<Signedness>,
-        #[serde(rename(deserialize = "$value"))]
-        items: Option<Vec<StructureElement>>,
+        pub name: std::string::String,
+        pub id: usize,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub encoding: Option<std::string::String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub endian: Option<Endianess>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub signed: Option<Signedness>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub repeatmin: Option<Length>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub repeatmax: Option<Length>,
+        #[serde(
+            rename(deserialize = "$value"),
+            alias = "items",
+            skip_serializing_if = "Option::is_none"
+        )]
+        pub items: Option<Vec<StructureElement>>,
     }
 
     #[derive(Debug, Serialize, Deserialize, PartialEq)]
@@ -48,15 +77,74 @@ pub mod grammar {
         Number(Number),
         String(String),
         Structure(Structure),
+        StructRef(StructRef),
     }
 
     #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    #[serde(deny_unknown_fields)]
+    pub struct StructRef {
+        pub name: std::string::String,
+        pub id: usize,
+        /// Reference to the structure in the form `id:N`
+        pub structure: std::string::String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub repeatmin: Option<Length>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub repeatmax: Option<Length>,
+    }
+
+    impl StructureElement {
+        pub fn name(&self) -> &str {
+            match self {
+                StructureElement::Number(number) => &number.name,
+                StructureElement::String(string) => &string.name,
+                StructureElement::Structure(structure) => &structure.name,
+                StructureElement::StructRef(structref) => &structref.name,
+            }
+        }
+
+        /// Minimum and maximum number of repetitions, an element without them occurs once.
+        pub fn repeat(&self) -> (Option<&Length>, Option<&Length>) {
+            let (min, max) = match self {
+                StructureElement::Number(number) => (&number.repeatmin, &number.repeatmax),
+                StructureElement::String(string) => (&string.repeatmin, &string.repeatmax),
+                StructureElement::Structure(structure) => {
+                    (&structure.repeatmin, &structure.repeatmax)
+                }
+                StructureElement::StructRef(structref) => {
+                    (&structref.repeatmin, &structref.repeatmax)
+                }
+            };
+            (min.as_ref(), max.as_ref())
+        }
+    }
+
+    impl StructRef {
+        /// Id of the referenced structure, if `structure` is a valid reference.
+        pub fn structure_id(&self) -> Option<usize> {
+            reference(&self.structure)
+        }
+    }
+
+    /// Parses an element reference of the form `id:N`.
+    fn reference(value: &str) -> Option<usize> {
+        value.strip_prefix("id:")?.parse().ok()
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    #[serde(deny_unknown_fields)]
     pub struct String {
-        name: std::string::String,
-        id: usize,
-        length: Option<usize>,
-        r#type: StringType,
-        delimiter: Option<std::string::String>,
+        pub name: std::string::String,
+        pub id: usize,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub length: Option<Length>,
+        pub r#type: StringType,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub delimiter: Option<std::string::String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub repeatmin: Option<Length>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub repeatmax: Option<Length>,
     }
 
     #[derive(Debug, Serialize, Deserialize, PartialEq)]
@@ -88,14 +176,142 @@ pub mod grammar {
     }
 
     #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    #[serde(deny_unknown_fields)]
     pub struct Number {
-        name: std::string::String,
-        id: usize,
+        pub name: std::string::String,
+        pub id: usize,
         #[serde(rename = "type")]
-        r#type: NumberType,
-        length: usize,
-        #[serde(rename = "lengthunit")]
-        unit: Option<Unit>,
+        pub r#type: NumberType,
+        pub length: Length,
+        #[serde(rename = "lengthunit", skip_serializing_if = "Option::is_none")]
+        pub unit: Option<Unit>,
+        /// Overrides the endianess of the structure
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub endian: Option<Endianess>,
+        /// Overrides the signedness of the structure
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub signed: Option<Signedness>,
+        /// Named values (enumeration) of the number
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub fixedvalues: Option<FixedValues>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub repeatmin: Option<Length>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub repeatmax: Option<Length>,
+    }
+
+    /// A length or repeat count, either a number or an [expression](crate::expression)
+    /// which is evaluated while decoding.
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum Length {
+        Fixed(usize),
+        Expression(std::string::String),
+    }
+
+    impl Length {
+        pub fn fixed(&self) -> Option<usize> {
+            match self {
+                Length::Fixed(length) => Some(*length),
+                Length::Expression(_) => None,
+            }
+        }
+
+        pub fn expression(&self) -> Result<Expression, ExpressionError> {
+            match self {
+                Length::Fixed(length) => Ok(Expression::Integer(*length as i64)),
+                Length::Expression(expression) => expression.parse(),
+            }
+        }
+
+        /// Whether this is a repeat count without limit, `-1` or `unlimited`.
+        pub fn is_unlimited(&self) -> bool {
+            match self {
+                Length::Fixed(_) => false,
+                Length::Expression(expression) => {
+                    let expression = expression.trim();
+                    expression == "-1" || expression.eq_ignore_ascii_case("unlimited")
+                }
+            }
+        }
+    }
+
+    impl From<usize> for Length {
+        fn from(length: usize) -> Self {
+            Length::Fixed(length)
+        }
+    }
+
+    impl fmt::Display for Length {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                Length::Fixed(length) => write!(f, "{}", length),
+                Length::Expression(expression) => write!(f, "{}", expression),
+            }
+        }
+    }
+
+    impl Serialize for Length {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            match self {
+                Length::Fixed(length) => serializer.serialize_u64(*length as u64),
+                Length::Expression(expression) => serializer.serialize_str(expression),
+            }
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Length {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            struct LengthVisitor;
+
+            impl<'de> Visitor<'de> for LengthVisitor {
+                type Value = Length;
+
+                fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    write!(f, "a length or an expression")
+                }
+
+                fn visit_u64<E: de::Error>(self, value: u64) -> Result<Length, E> {
+                    Ok(Length::Fixed(value as usize))
+                }
+
+                fn visit_i64<E: de::Error>(self, value: i64) -> Result<Length, E> {
+                    Ok(Length::Expression(value.to_string()))
+                }
+
+                fn visit_str<E: de::Error>(self, value: &str) -> Result<Length, E> {
+                    Ok(match value.trim().parse() {
+                        Ok(length) => Length::Fixed(length),
+                        Err(_) => Length::Expression(value.to_string()),
+                    })
+                }
+            }
+
+            deserializer.deserialize_any(LengthVisitor)
+        }
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    #[serde(deny_unknown_fields)]
+    pub struct FixedValues {
+        #[serde(rename = "fixedvalue", default)]
+        pub values: Vec<FixedValue>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    #[serde(deny_unknown_fields)]
+    pub struct FixedValue {
+        pub name: std::string::String,
+        pub value: std::string::String,
+    }
+
+    impl FixedValue {
+        /// Numeric value, Synalyze It stores it in decimal or hex (`0x` prefix).
+        pub fn number(&self) -> Option<i64> {
+            match self.value.strip_prefix("0x") {
+                Some(hex) => i64::from_str_radix(hex, 16).ok(),
+                None => self.value.parse().ok(),
+            }
+        }
     }
 
     #[derive(Debug, Serialize, Deserialize, PartialEq)]
@@ -114,6 +330,23 @@ pub mod grammar {
         Unsigned,
     }
 
+    /// A grammar `name` whose start structure `structure` contains the xml `items`.
+    #[cfg(test)]
+    pub(crate) fn fixture(name: &str, structure: &str, items: &str) -> std::string::String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<ufwb version="1.17">
+    <grammar name="{}" start="id:1" author="Nicola Coretti" complete="yes">
+        <description>test grammar</description>
+        <structure name="{}" id="1" encoding="UTF-8" endian="big" signed="no">
+{}
+        </structure>
+    </grammar>
+</ufwb>"#,
+            name, structure, items
+        )
+    }
+
     #[cfg(test)]
     mod tests {
         use super::*;
@@ -141,7 +374,7 @@ pub mod grammar {
 
         #[test]
         fn test_fixed_length_string() -> Result<(), DeError> {
-            let expected = String { name: std::string::String::from("FixedLengthString"), id: 8, r#type: StringType::FixedLength, length: Some(10), delimiter: None };
+            let expected = String { name: std::string::String::from("FixedLengthString"), id: 8, r#type: StringType::FixedLength, length: Some(Length::Fixed(10)), delimiter: None, repeatmin: None, repeatmax: None };
             let xml = r#"
             <?xml version="1.0" encoding="UTF-8"?>
             <string name="FixedLengthString" id="8" type="fixed-length" length="10"/>
@@ -153,7 +386,7 @@ pub mod grammar {
 
         #[test]
         fn test_zero_terminated_string() -> Result<(), DeError> {
-            let expected = String { name: std::string::String::from("ZeroTerminated"), id: 10, r#type: StringType::ZeroTerminated, length: None, delimiter: None };
+            let expected = String { name: std::string::String::from("ZeroTerminated"), id: 10, r#type: StringType::ZeroTerminated, length: None, delimiter: None, repeatmin: None, repeatmax: None };
             let xml = r#"
             <?xml version="1.0" encoding="UTF-8"?>
             <string name="ZeroTerminated" id="10" type="zero-terminated"/>
@@ -165,7 +398,7 @@ pub mod grammar {
 
         #[test]
         fn test_delimiter_terminated_string() -> Result<(), DeError> {
-            let expected = String { name: std::string::String::from("DelimiterTerminated"), id: 11, r#type: StringType::DelimiterTerminated, length: None, delimiter: Some(std::string::String::from("0A0A")) };
+            let expected = String { name: std::string::String::from("DelimiterTerminated"), id: 11, r#type: StringType::DelimiterTerminated, length: None, delimiter: Some(std::string::String::from("0A0A")), repeatmin: None, repeatmax: None };
             let xml = r#"
             <?xml version="1.0" encoding="UTF-8"?>
             <string name="DelimiterTerminated" id="11" type="delimiter-terminated" delimiter="0A0A"/>
@@ -177,7 +410,7 @@ pub mod grammar {
 
         #[test]
         fn test_length_prefixed_string() -> Result<(), DeError> {
-            let expected = String { name: std::string::String::from("LengthPrefixed"), id: 13, r#type: StringType::PrefixedLength, length: None, delimiter: None };
+            let expected = String { name: std::string::String::from("LengthPrefixed"), id: 13, r#type: StringType::PrefixedLength, length: None, delimiter: None, repeatmin: None, repeatmax: None };
             let xml = r#"
             <?xml version="1.0" encoding="UTF-8"?>
             <string name="LengthPrefixed" id="13" type="pascal"/>
@@ -189,7 +422,7 @@ pub mod grammar {
 
         #[test]
         fn test_integer_number_with_byte_length() -> Result<(), DeError> {
-            let expected = Number { name: std::string::String::from("IntegerWithByteLenght1"), id: 3, r#type: NumberType::Integer, length: 1, unit: None };
+            let expected = Number { name: std::string::String::from("IntegerWithByteLenght1"), id: 3, r#type: NumberType::Integer, length: Length::Fixed(1), unit: None, endian: None, signed: None, fixedvalues: None, repeatmin: None, repeatmax: None };
             let xml = r#"
             <?xml version="1.0" encoding="UTF-8"?>
             <number name="IntegerWithByteLenght1" id="3" type="integer" length="1"/>
@@ -201,7 +434,7 @@ pub mod grammar {
 
         #[test]
         fn test_float_number_with_byte_length() -> Result<(), DeError> {
-            let expected = Number { name: std::string::String::from("FloatingPointByteLength2"), id: 15, r#type: NumberType::Float, length: 2, unit: None };
+            let expected = Number { name: std::string::String::from("FloatingPointByteLength2"), id: 15, r#type: NumberType::Float, length: Length::Fixed(2), unit: None, endian: None, signed: None, fixedvalues: None, repeatmin: None, repeatmax: None };
             let xml = r#"
             <?xml version="1.0" encoding="UTF-8"?>
             <number name="FloatingPointByteLength2" id="15" type="float" length="2"/>
@@ -213,7 +446,7 @@ pub mod grammar {
 
         #[test]
         fn test_integer_number_with_bit_length() -> Result<(), DeError> {
-            let expected = Number { name: std::string::String::from("IntegerWithBitLength8"), id: 10, r#type: NumberType::Integer, length: 8, unit: Some(Unit::Bit) };
+            let expected = Number { name: std::string::String::from("IntegerWithBitLength8"), id: 10, r#type: NumberType::Integer, length: Length::Fixed(8), unit: Some(Unit::Bit), endian: None, signed: None, fixedvalues: None, repeatmin: None, repeatmax: None };
             let xml = r#"
             <?xml version="1.0" encoding="UTF-8"?>
             <number name="IntegerWithBitLength8" id="10" type="integer" length="8" lengthunit="bit"/>
@@ -225,7 +458,7 @@ pub mod grammar {
 
         #[test]
         fn test_float_number_with_bit_length() -> Result<(), DeError> {
-            let expected = Number { name: std::string::String::from("FloatingPointBitLength16"), id: 18, r#type: NumberType::Float, length: 16, unit: Some(Unit::Bit) };
+            let expected = Number { name: std::string::String::from("FloatingPointBitLength16"), id: 18, r#type: NumberType::Float, length: Length::Fixed(16), unit: Some(Unit::Bit), endian: None, signed: None, fixedvalues: None, repeatmin: None, repeatmax: None };
             let xml = r#"
             <?xml version="1.0" encoding="UTF-8"?>
             <number name="FloatingPointBitLength16" id="18" type="float" length="16" lengthunit="bit"/>
@@ -235,6 +468,53 @@ pub mod grammar {
             Ok(())
         }
 
+        #[test]
+        fn test_number_with_fixed_values() -> Result<(), DeError> {
+            let expected = Number {
+                name: std::string::String::from("Opcode"),
+                id: 4,
+                r#type: NumberType::Integer,
+                length: Length::Fixed(2),
+                unit: None,
+                endian: Some(Endianess::Little),
+                signed: Some(Signedness::Unsigned),
+                fixedvalues: Some(FixedValues {
+                    values: vec![
+                        FixedValue { name: std::string::String::from("read"), value: std::string::String::from("1") },
+                        FixedValue { name: std::string::String::from("write"), value: std::string::String::from("0x2") },
+                    ],
+                }),
+                repeatmin: None,
+                repeatmax: None,
+            };
+            let xml = r#"
+            <?xml version="1.0" encoding="UTF-8"?>
+            <number name="Opcode" id="4" type="integer" length="2" endian="little" signed="no">
+                <fixedvalues>
+                    <fixedvalue name="read" value="1"/>
+                    <fixedvalue name="write" value="0x2"/>
+                </fixedvalues>
+            </number>
+            "#;
+            let number: Number = from_str(xml)?;
+            assert_eq!(expected, number);
+            let values = number.fixedvalues.unwrap().values;
+            assert_eq!(values.iter().map(|v| v.number()).collect::<Vec<_>>(), vec![Some(1), Some(2)]);
+            Ok(())
+        }
+
+        #[test]
+        fn test_structref() -> Result<(), DeError> {
+            let expected = StructRef { name: std::string::String::from("Header"), id: 7, structure: std::string::String::from("id:2"), repeatmin: None, repeatmax: None };
+            let xml = r#"
+            <?xml version="1.0" encoding="UTF-8"?>
+            <structref name="Header" id="7" structure="id:2"/>
+            "#;
+            let structref: StructRef = from_str(xml)?;
+            assert_eq!(expected, structref);
+            Ok(())
+        }
+
         #[test]
         fn can_parse_ufwb_structure() -> Result<(), DeError> {
             let xml = r#"
@@ -272,4 +552,8 @@ pub mod grammar {
     }
 }
 
+/// Writes [Synalyze It](https://www.synalysis.net)/[Hexinator](https://hexinator.com) grammar files.
+pub mod xml;
 
+/// Checks [Synalyze It](https://www.synalysis.net)/[Hexinator](https://hexinator.com) grammar files for common mistakes.
+pub mod lint;
@@ -0,0 +1,522 @@
+use super::grammar::{Length, Number, NumberType, StringType, StructureElement, Ufwb, Unit};
+use quick_xml::de::{from_str, DeError};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// Location (1-based) of an element within a grammar file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// Problems which can be detected within a grammar.
+#[derive(Debug, PartialEq)]
+pub enum Issue {
+    DuplicateId {
+        id: usize,
+    },
+    UnresolvedStart {
+        start: String,
+    },
+    InvalidLength {
+        name: String,
+        length: usize,
+    },
+    MissingLength {
+        name: String,
+    },
+    UnresolvedStructRef {
+        name: String,
+        structure: String,
+    },
+    MissingDelimiter {
+        name: String,
+    },
+    InvalidDelimiter {
+        name: String,
+        delimiter: String,
+    },
+    FloatWithBitLength {
+        name: String,
+    },
+    InvalidExpression {
+        name: String,
+        expression: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Issue::DuplicateId { id } => write!(f, "id {} is used more than once", id),
+            Issue::UnresolvedStart { start } => {
+                write!(f, "start \"{}\" does not reference a structure", start)
+            }
+            Issue::InvalidLength { name, length } => {
+                write!(f, "\"{}\" has an invalid length of {}", name, length)
+            }
+            Issue::MissingLength { name } => write!(f, "\"{}\" has no length", name),
+            Issue::UnresolvedStructRef { name, structure } => write!(
+                f,
+                "\"{}\" references \"{}\" which is not a structure",
+                name, structure
+            ),
+            Issue::MissingDelimiter { name } => write!(f, "\"{}\" has no delimiter", name),
+            Issue::InvalidDelimiter { name, delimiter } => write!(
+                f,
+                "\"{}\" has a delimiter \"{}\" which is not valid hex",
+                name, delimiter
+            ),
+            Issue::FloatWithBitLength { name } => {
+                write!(f, "float \"{}\" uses a bit length", name)
+            }
+            Issue::InvalidExpression {
+                name,
+                expression,
+                message,
+            } => write!(
+                f,
+                "\"{}\" uses an invalid expression \"{}\", {}",
+                name, expression, message
+            ),
+        }
+    }
+}
+
+/// A detected issue and, if known, where it is located.
+#[derive(Debug, PartialEq)]
+pub struct Report {
+    pub position: Option<Position>,
+    pub issue: Issue,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.position {
+            Some(position) => write!(f, "{}: {}", position, self.issue),
+            None => write!(f, "{}", self.issue),
+        }
+    }
+}
+
+/// Positions of the grammar elements within the xml source.
+///
+/// Elements are looked up by their id, because ids may be used more than once
+/// all positions of an id are kept in document order.
+#[derive(Debug, Default)]
+pub struct Positions {
+    grammar: Option<Position>,
+    elements: HashMap<usize, Vec<Position>>,
+}
+
+impl Positions {
+    pub fn from_xml(xml: &str) -> Result<Self, quick_xml::Error> {
+        let mut positions = Positions::default();
+        let mut reader = Reader::from_str(xml);
+        let mut buf = Vec::new();
+        loop {
+            // text is not trimmed, so every event starts where the previous one ended
+            let offset = reader.buffer_position();
+            match reader.read_event(&mut buf)? {
+                Event::Start(ref e) | Event::Empty(ref e) => {
+                    let position = position_of(xml, offset);
+                    if e.name() == b"grammar" {
+                        positions.grammar = Some(position);
+                    }
+                    for attribute in e.attributes() {
+                        let attribute = attribute?;
+                        if attribute.key != b"id" {
+                            continue;
+                        }
+                        let value = attribute.unescaped_value()?;
+                        if let Some(id) = std::str::from_utf8(&value)
+                            .ok()
+                            .and_then(|v| v.parse().ok())
+                        {
+                            positions.elements.entry(id).or_default().push(position);
+                        }
+                    }
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+        Ok(positions)
+    }
+
+    /// Position of the `occurrence`th (0-based) element with the given id.
+    fn element(&self, id: usize, occurrence: usize) -> Option<Position> {
+        self.elements
+            .get(&id)
+            .and_then(|positions| positions.get(occurrence))
+            .cloned()
+    }
+}
+
+fn position_of(text: &str, offset: usize) -> Position {
+    let before = &text.as_bytes()[..offset];
+    let line = before.iter().filter(|b| **b == b'\n').count() + 1;
+    let line_start = before
+        .iter()
+        .rposition(|b| *b == b'\n')
+        .map(|p| p + 1)
+        .unwrap_or(0);
+    Position {
+        line,
+        column: offset - line_start + 1,
+    }
+}
+
+/// Loads the grammar from `xml` and checks it, issues will carry the position of the affected element.
+pub fn lint(xml: &str) -> Result<Vec<Report>, DeError> {
+    let ufwb: Ufwb = from_str(xml)?;
+    let positions = Positions::from_xml(xml)?;
+    Ok(check(&ufwb, &positions))
+}
+
+/// Checks an already loaded grammar, use [`Positions::default`] if the source is not available.
+pub fn check(ufwb: &Ufwb, positions: &Positions) -> Vec<Report> {
+    let mut checker = Checker {
+        positions,
+        structures: HashSet::new(),
+        occurrences: HashMap::new(),
+        reports: Vec::new(),
+    };
+    let root = &ufwb.grammar.structure;
+    checker.structures.insert(root.id);
+    collect_structures(&root.items, &mut checker.structures);
+
+    if !matches!(ufwb.grammar.start_id(), Some(id) if checker.structures.contains(&id)) {
+        checker.reports.push(Report {
+            position: positions.grammar,
+            issue: Issue::UnresolvedStart {
+                start: ufwb.grammar.start.clone(),
+            },
+        });
+    }
+    checker.visit(root.id);
+    checker.check_items(&root.items);
+    checker.reports
+}
+
+fn collect_structures(items: &Option<Vec<StructureElement>>, ids: &mut HashSet<usize>) {
+    for item in items.iter().flatten() {
+        if let StructureElement::Structure(structure) = item {
+            ids.insert(structure.id);
+            collect_structures(&structure.items, ids);
+        }
+    }
+}
+
+struct Checker<'a> {
+    positions: &'a Positions,
+    structures: HashSet<usize>,
+    occurrences: HashMap<usize, usize>,
+    reports: Vec<Report>,
+}
+
+impl<'a> Checker<'a> {
+    /// Registers the next occurrence of `id` and returns its position.
+    fn visit(&mut self, id: usize) -> Option<Position> {
+        let occurrence = self.occurrences.entry(id).or_insert(0);
+        let position = self.positions.element(id, *occurrence);
+        *occurrence += 1;
+        if *occurrence > 1 {
+            self.reports.push(Report {
+                position,
+                issue: Issue::DuplicateId { id },
+            });
+        }
+        position
+    }
+
+    fn report(&mut self, position: Option<Position>, issue: Issue) {
+        self.reports.push(Report { position, issue });
+    }
+
+    fn check_items(&mut self, items: &Option<Vec<StructureElement>>) {
+        for item in items.iter().flatten() {
+            let position = match item {
+                StructureElement::Structure(structure) => self.visit(structure.id),
+                StructureElement::StructRef(structref) => self.visit(structref.id),
+                StructureElement::Number(number) => self.visit(number.id),
+                StructureElement::String(string) => self.visit(string.id),
+            };
+            let (repeatmin, repeatmax) = item.repeat();
+            for repeat in repeatmin.iter().chain(repeatmax.iter()) {
+                self.check_expression(item.name(), repeat, position);
+            }
+            match item {
+                StructureElement::Structure(structure) => {
+                    self.check_items(&structure.items);
+                }
+                StructureElement::StructRef(structref) => {
+                    if !matches!(structref.structure_id(), Some(id) if self.structures.contains(&id))
+                    {
+                        self.report(
+                            position,
+                            Issue::UnresolvedStructRef {
+                                name: structref.name.clone(),
+                                structure: structref.structure.clone(),
+                            },
+                        );
+                    }
+                }
+                StructureElement::Number(number) => {
+                    self.check_number(number, position);
+                }
+                StructureElement::String(string) => {
+                    let name = string.name.clone();
+                    match string.r#type {
+                        StringType::FixedLength => match &string.length {
+                            None => self.report(position, Issue::MissingLength { name }),
+                            Some(Length::Fixed(0)) => {
+                                self.report(position, Issue::InvalidLength { name, length: 0 })
+                            }
+                            Some(length) => self.check_expression(&name, length, position),
+                        },
+                        StringType::DelimiterTerminated => match &string.delimiter {
+                            None => self.report(position, Issue::MissingDelimiter { name }),
+                            Some(delimiter) if !is_hex(delimiter) => self.report(
+                                position,
+                                Issue::InvalidDelimiter {
+                                    name,
+                                    delimiter: delimiter.clone(),
+                                },
+                            ),
+                            Some(_) => {}
+                        },
+                        StringType::ZeroTerminated | StringType::PrefixedLength => {}
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_number(&mut self, number: &Number, position: Option<Position>) {
+        let name = number.name.clone();
+        let length = match number.length {
+            Length::Fixed(length) => length,
+            Length::Expression(_) => {
+                return self.check_expression(&name, &number.length, position);
+            }
+        };
+        let in_bits = number.unit == Some(Unit::Bit);
+        let valid = match number.r#type {
+            NumberType::Integer if in_bits => (1..=64).contains(&length),
+            NumberType::Integer => (1..=8).contains(&length),
+            NumberType::Float if in_bits => {
+                self.report(position, Issue::FloatWithBitLength { name });
+                return;
+            }
+            NumberType::Float => [2, 4, 8].contains(&length),
+        };
+        if !valid {
+            self.report(position, Issue::InvalidLength { name, length });
+        }
+    }
+
+    /// Expressions are evaluated while decoding, only their syntax can be checked upfront.
+    fn check_expression(&mut self, name: &str, length: &Length, position: Option<Position>) {
+        if let Err(error) = length.expression() {
+            self.report(
+                position,
+                Issue::InvalidExpression {
+                    name: name.to_string(),
+                    expression: length.to_string(),
+                    message: error.to_string(),
+                },
+            );
+        }
+    }
+}
+
+/// Delimiters are stored as a non empty sequence of hex encoded bytes, e.g. `0A0D`.
+fn is_hex(delimiter: &str) -> bool {
+    !delimiter.is_empty()
+        && delimiter
+            .as_bytes()
+            .chunks(2)
+            .all(|pair| pair.len() == 2 && pair.iter().all(u8::is_ascii_hexdigit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grammar(items: &str) -> String {
+        crate::synalize::grammar::fixture("Lint", "Lint", items)
+    }
+
+    #[test]
+    fn valid_grammar_has_no_issues() -> Result<(), DeError> {
+        let xml = grammar(
+            r#"            <structure name="Header" id="2"/>
+            <structref name="Ref" id="3" structure="id:2"/>
+            <number name="Integer" id="4" type="integer" length="4"/>
+            <string name="Delimited" id="5" type="delimiter-terminated" delimiter="0A0D"/>"#,
+        );
+        assert_eq!(lint(&xml)?, vec![]);
+        Ok(())
+    }
+
+    #[test]
+    fn duplicate_id() -> Result<(), DeError> {
+        let xml = grammar(
+            r#"            <number name="First" id="2" type="integer" length="1"/>
+            <number name="Second" id="2" type="integer" length="1"/>"#,
+        );
+        let expected = vec![Report {
+            position: Some(Position {
+                line: 7,
+                column: 13,
+            }),
+            issue: Issue::DuplicateId { id: 2 },
+        }];
+        assert_eq!(lint(&xml)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn unresolved_start() -> Result<(), DeError> {
+        let xml = grammar("").replace("start=\"id:1\"", "start=\"id:9\"");
+        let expected = vec![Report {
+            position: Some(Position { line: 3, column: 5 }),
+            issue: Issue::UnresolvedStart {
+                start: String::from("id:9"),
+            },
+        }];
+        assert_eq!(lint(&xml)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_lengths() -> Result<(), DeError> {
+        let xml = grammar(
+            r#"            <number name="Zero" id="2" type="integer" length="0"/>
+            <number name="TooWide" id="3" type="integer" length="65" lengthunit="bit"/>
+            <number name="Float" id="4" type="float" length="3"/>
+            <string name="Fixed" id="5" type="fixed-length" length="0"/>
+            <string name="NoLength" id="6" type="fixed-length"/>"#,
+        );
+        let issues: Vec<Issue> = lint(&xml)?.into_iter().map(|r| r.issue).collect();
+        let expected = vec![
+            Issue::InvalidLength {
+                name: String::from("Zero"),
+                length: 0,
+            },
+            Issue::InvalidLength {
+                name: String::from("TooWide"),
+                length: 65,
+            },
+            Issue::InvalidLength {
+                name: String::from("Float"),
+                length: 3,
+            },
+            Issue::InvalidLength {
+                name: String::from("Fixed"),
+                length: 0,
+            },
+            Issue::MissingLength {
+                name: String::from("NoLength"),
+            },
+        ];
+        assert_eq!(issues, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn unresolved_structref() -> Result<(), DeError> {
+        let xml = grammar(
+            r#"            <number name="Number" id="2" type="integer" length="1"/>
+            <structref name="Ref" id="3" structure="id:2"/>"#,
+        );
+        let expected = vec![Report {
+            position: Some(Position {
+                line: 7,
+                column: 13,
+            }),
+            issue: Issue::UnresolvedStructRef {
+                name: String::from("Ref"),
+                structure: String::from("id:2"),
+            },
+        }];
+        assert_eq!(lint(&xml)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_delimiter() -> Result<(), DeError> {
+        let xml = grammar(
+            r#"            <string name="Odd" id="2" type="delimiter-terminated" delimiter="0A0"/>
+            <string name="NotHex" id="3" type="delimiter-terminated" delimiter="ZZ"/>"#,
+        );
+        let issues: Vec<Issue> = lint(&xml)?.into_iter().map(|r| r.issue).collect();
+        let expected = vec![
+            Issue::InvalidDelimiter {
+                name: String::from("Odd"),
+                delimiter: String::from("0A0"),
+            },
+            Issue::InvalidDelimiter {
+                name: String::from("NotHex"),
+                delimiter: String::from("ZZ"),
+            },
+        ];
+        assert_eq!(issues, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn float_with_bit_length() -> Result<(), DeError> {
+        let xml = grammar(
+            r#"            <number name="Float" id="2" type="float" length="32" lengthunit="bit"/>"#,
+        );
+        let expected = vec![Report {
+            position: Some(Position {
+                line: 6,
+                column: 13,
+            }),
+            issue: Issue::FloatWithBitLength {
+                name: String::from("Float"),
+            },
+        }];
+        assert_eq!(lint(&xml)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_expressions() -> Result<(), DeError> {
+        let xml = grammar(
+            r#"            <number name="Count" id="2" type="integer" length="1"/>
+            <number name="Valid" id="3" type="integer" length="Count / 2" repeatmax="Count"/>
+            <string name="Text" id="4" type="fixed-length" length="Count *"/>
+            <number name="Repeated" id="5" type="integer" length="1" repeatmax="(Count"/>"#,
+        );
+        let issues: Vec<Issue> = lint(&xml)?.into_iter().map(|r| r.issue).collect();
+        let expected = vec![
+            Issue::InvalidExpression {
+                name: String::from("Text"),
+                expression: String::from("Count *"),
+                message: String::from("unexpected end at position 7"),
+            },
+            Issue::InvalidExpression {
+                name: String::from("Repeated"),
+                expression: String::from("(Count"),
+                message: String::from("unexpected end at position 6"),
+            },
+        ];
+        assert_eq!(issues, expected);
+        Ok(())
+    }
+}
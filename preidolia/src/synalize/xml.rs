@@ -0,0 +1,281 @@
+use super::grammar::{
+    Endianess, Grammar, Length, Number, NumberType, RootStructure, Signedness, String, StringType,
+    StructRef, Structure, StructureElement, Ufwb, Unit,
+};
+use quick_xml::events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event};
+use quick_xml::{Error, Writer};
+use std::io::Write;
+
+/// Serializes a grammar to a xml string which can be opened by Synalyze It/Hexinator.
+pub fn to_string(ufwb: &Ufwb) -> Result<std::string::String, Error> {
+    let mut buffer = Vec::new();
+    to_writer(ufwb, &mut buffer)?;
+    std::string::String::from_utf8(buffer).map_err(|e| Error::Utf8(e.utf8_error()))
+}
+
+/// Serializes a grammar as xml into `writer`.
+///
+/// The elements are written in the order of the model, attributes in the order Synalyze It uses.
+/// Nothing is dropped, grammars with attributes the model doesn't know are refused when they
+/// are parsed.
+pub fn to_writer<W: Write>(ufwb: &Ufwb, writer: W) -> Result<(), Error> {
+    let mut writer = Writer::new_with_indent(writer, b' ', 4);
+    writer.write_event(Event::Decl(BytesDecl::new(b"1.0", Some(b"UTF-8"), None)))?;
+    let mut element = BytesStart::borrowed_name(b"ufwb");
+    element.push_attribute(("version", ufwb.version.as_str()));
+    writer.write_event(Event::Start(element))?;
+    write_grammar(&mut writer, &ufwb.grammar)?;
+    writer.write_event(Event::End(BytesEnd::borrowed(b"ufwb")))?;
+    writer.write(b"\n")
+}
+
+fn write_grammar<W: Write>(writer: &mut Writer<W>, grammar: &Grammar) -> Result<(), Error> {
+    let mut element = BytesStart::borrowed_name(b"grammar");
+    element.push_attribute(("name", grammar.name.as_str()));
+    element.push_attribute(("start", grammar.start.as_str()));
+    element.push_attribute(("author", grammar.author.as_str()));
+    if let Some(email) = &grammar.email {
+        element.push_attribute(("email", email.as_str()));
+    }
+    element.push_attribute(("complete", grammar.complete.as_str()));
+    writer.write_event(Event::Start(element))?;
+
+    writer.write_event(Event::Start(BytesStart::borrowed_name(b"description")))?;
+    writer.write_event(Event::Text(BytesText::from_plain_str(&grammar.description)))?;
+    writer.write_event(Event::End(BytesEnd::borrowed(b"description")))?;
+    write_root_structure(writer, &grammar.structure)?;
+
+    writer.write_event(Event::End(BytesEnd::borrowed(b"grammar")))
+}
+
+fn write_root_structure<W: Write>(
+    writer: &mut Writer<W>,
+    structure: &RootStructure,
+) -> Result<(), Error> {
+    let mut element = BytesStart::borrowed_name(b"structure");
+    element.push_attribute(("name", structure.name.as_str()));
+    element.push_attribute(("id", structure.id.to_string().as_str()));
+    element.push_attribute(("encoding", structure.encoding.as_str()));
+    element.push_attribute(("endian", endianess(&structure.endian)));
+    element.push_attribute(("signed", signedness(&structure.signed)));
+    write_items(writer, element, &structure.items)
+}
+
+fn write_structure<W: Write>(writer: &mut Writer<W>, structure: &Structure) -> Result<(), Error> {
+    let mut element = BytesStart::borrowed_name(b"structure");
+    element.push_attribute(("name", structure.name.as_str()));
+    element.push_attribute(("id", structure.id.to_string().as_str()));
+    push_repeat(&mut element, &structure.repeatmin, &structure.repeatmax);
+    if let Some(encoding) = &structure.encoding {
+        element.push_attribute(("encoding", encoding.as_str()));
+    }
+    if let Some(endian) = &structure.endian {
+        element.push_attribute(("endian", endianess(endian)));
+    }
+    if let Some(signed) = &structure.signed {
+        element.push_attribute(("signed", signedness(signed)));
+    }
+    write_items(writer, element, &structure.items)
+}
+
+/// Writes a structure element, structures without items are written as empty element.
+fn write_items<W: Write>(
+    writer: &mut Writer<W>,
+    element: BytesStart,
+    items: &Option<Vec<StructureElement>>,
+) -> Result<(), Error> {
+    let items = match items {
+        Some(items) if !items.is_empty() => items,
+        _ => return writer.write_event(Event::Empty(element)),
+    };
+    writer.write_event(Event::Start(element))?;
+    for item in items {
+        match item {
+            StructureElement::Number(number) => write_number(writer, number)?,
+            StructureElement::String(string) => write_string(writer, string)?,
+            StructureElement::Structure(structure) => write_structure(writer, structure)?,
+            StructureElement::StructRef(structref) => write_structref(writer, structref)?,
+        }
+    }
+    writer.write_event(Event::End(BytesEnd::borrowed(b"structure")))
+}
+
+fn write_number<W: Write>(writer: &mut Writer<W>, number: &Number) -> Result<(), Error> {
+    let mut element = BytesStart::borrowed_name(b"number");
+    element.push_attribute(("name", number.name.as_str()));
+    element.push_attribute(("id", number.id.to_string().as_str()));
+    push_repeat(&mut element, &number.repeatmin, &number.repeatmax);
+    element.push_attribute((
+        "type",
+        match number.r#type {
+            NumberType::Float => "float",
+            NumberType::Integer => "integer",
+        },
+    ));
+    element.push_attribute(("length", number.length.to_string().as_str()));
+    if let Some(unit) = &number.unit {
+        element.push_attribute((
+            "lengthunit",
+            match unit {
+                Unit::Bit => "bit",
+                Unit::Byte => "byte",
+            },
+        ));
+    }
+    if let Some(endian) = &number.endian {
+        element.push_attribute(("endian", endianess(endian)));
+    }
+    if let Some(signed) = &number.signed {
+        element.push_attribute(("signed", signedness(signed)));
+    }
+    let values = match &number.fixedvalues {
+        Some(fixedvalues) => &fixedvalues.values,
+        None => return writer.write_event(Event::Empty(element)),
+    };
+    writer.write_event(Event::Start(element))?;
+    writer.write_event(Event::Start(BytesStart::borrowed_name(b"fixedvalues")))?;
+    for value in values {
+        let mut element = BytesStart::borrowed_name(b"fixedvalue");
+        element.push_attribute(("name", value.name.as_str()));
+        element.push_attribute(("value", value.value.as_str()));
+        writer.write_event(Event::Empty(element))?;
+    }
+    writer.write_event(Event::End(BytesEnd::borrowed(b"fixedvalues")))?;
+    writer.write_event(Event::End(BytesEnd::borrowed(b"number")))
+}
+
+fn write_string<W: Write>(writer: &mut Writer<W>, string: &String) -> Result<(), Error> {
+    let mut element = BytesStart::borrowed_name(b"string");
+    element.push_attribute(("name", string.name.as_str()));
+    element.push_attribute(("id", string.id.to_string().as_str()));
+    push_repeat(&mut element, &string.repeatmin, &string.repeatmax);
+    element.push_attribute((
+        "type",
+        match string.r#type {
+            StringType::FixedLength => "fixed-length",
+            StringType::ZeroTerminated => "zero-terminated",
+            StringType::DelimiterTerminated => "delimiter-terminated",
+            StringType::PrefixedLength => "pascal",
+        },
+    ));
+    if let Some(length) = &string.length {
+        element.push_attribute(("length", length.to_string().as_str()));
+    }
+    if let Some(delimiter) = &string.delimiter {
+        element.push_attribute(("delimiter", delimiter.as_str()));
+    }
+    writer.write_event(Event::Empty(element))
+}
+
+fn write_structref<W: Write>(writer: &mut Writer<W>, structref: &StructRef) -> Result<(), Error> {
+    let mut element = BytesStart::borrowed_name(b"structref");
+    element.push_attribute(("name", structref.name.as_str()));
+    element.push_attribute(("id", structref.id.to_string().as_str()));
+    push_repeat(&mut element, &structref.repeatmin, &structref.repeatmax);
+    element.push_attribute(("structure", structref.structure.as_str()));
+    writer.write_event(Event::Empty(element))
+}
+
+/// Synalyze It writes the repetitions right after the id, as all elements can be repeated.
+fn push_repeat(element: &mut BytesStart, repeatmin: &Option<Length>, repeatmax: &Option<Length>) {
+    if let Some(repeatmin) = repeatmin {
+        element.push_attribute(("repeatmin", repeatmin.to_string().as_str()));
+    }
+    if let Some(repeatmax) = repeatmax {
+        element.push_attribute(("repeatmax", repeatmax.to_string().as_str()));
+    }
+}
+
+fn endianess(endian: &Endianess) -> &'static str {
+    match endian {
+        Endianess::Big => "big",
+        Endianess::Little => "little",
+    }
+}
+
+fn signedness(signed: &Signedness) -> &'static str {
+    match signed {
+        Signedness::Signed => "yes",
+        Signedness::Unsigned => "no",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quick_xml::de::from_str;
+
+    fn assert_round_trip(xml: &str) {
+        let ufwb: Ufwb = from_str(xml).unwrap();
+        assert_eq!(to_string(&ufwb).unwrap(), xml);
+    }
+
+    #[test]
+    fn round_trip_numbers_grammar() {
+        assert_round_trip(include_str!("../../resources/grammars/Numbers.grammar"));
+    }
+
+    #[test]
+    fn round_trip_strings_grammar() {
+        assert_round_trip(include_str!("../../resources/grammars/Strings.grammar"));
+    }
+
+    #[test]
+    fn round_trip_test_grammar() {
+        assert_round_trip(include_str!("../../resources/grammars/TestGrammar.grammar"));
+    }
+
+    #[test]
+    fn round_trip_nested_structures_fixed_values_and_expressions() {
+        assert_round_trip(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<ufwb version="1.17">
+    <grammar name="Nested" start="id:1" author="Nicola Coretti" complete="yes">
+        <description>nested &amp; referenced structures</description>
+        <structure name="Root" id="1" encoding="UTF-8" endian="big" signed="no">
+            <structure name="Header" id="2" endian="little" signed="yes">
+                <number name="Length" id="3" type="integer" length="2"/>
+                <number name="Kind" id="5" type="integer" length="1" endian="big" signed="no">
+                    <fixedvalues>
+                        <fixedvalue name="request" value="1"/>
+                        <fixedvalue name="response" value="2"/>
+                    </fixedvalues>
+                </number>
+            </structure>
+            <structref name="Trailer" id="4" structure="id:2"/>
+            <number name="Count" id="6" type="integer" length="1"/>
+            <string name="Name" id="7" repeatmax="Count" type="fixed-length" length="Header.Length * 2"/>
+            <number name="Checksum" id="8" repeatmin="0" repeatmax="Count &gt; 1" type="integer" length="prev.Count"/>
+        </structure>
+    </grammar>
+</ufwb>
+"#,
+        );
+    }
+
+    #[test]
+    fn unknown_attributes_are_refused_instead_of_dropped() {
+        let grammar = |items: &str| crate::synalize::grammar::fixture("Unknown", "Root", items);
+        let known = r#"            <number name="Kind" id="2" type="integer" length="1"/>"#;
+        assert_round_trip(&format!("{}\n", grammar(known)));
+        for items in &[
+            r#"<number name="Kind" id="2" type="integer" length="1" fillcolor="FF0000"/>"#,
+            r#"<number name="Kind" id="2" type="integer" length="1" minval="1" maxval="2"/>"#,
+            r#"<string name="Name" id="2" type="zero-terminated" mustmatch="yes"/>"#,
+            r#"<structure name="Body" id="2" order="variable"/>"#,
+        ] {
+            let error = from_str::<Ufwb>(&grammar(items)).unwrap_err();
+            assert!(error.to_string().contains("unknown field"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn round_trip_json() {
+        let xml = include_str!("../../resources/grammars/Strings.grammar");
+        let ufwb: Ufwb = from_str(xml).unwrap();
+        let json = serde_json::to_string(&ufwb).unwrap();
+        let from_json: Ufwb = serde_json::from_str(&json).unwrap();
+        assert_eq!(from_json, ufwb);
+        assert_eq!(to_string(&from_json).unwrap(), xml);
+    }
+}
@@ -26,6 +26,18 @@ mod cli {
         #[structopt(name = "count", short = "c")]
         #[structopt(help = "amount of messages to encode before exiting")]
         pub count: Option<usize>,
+
+        #[structopt(short = "r", long = "recover")]
+        #[structopt(
+            help = "report packets which can't be decoded on stderr and continue with the next byte"
+        )]
+        pub recover: bool,
+
+        #[structopt(short = "s", long = "spans")]
+        #[structopt(
+            help = "wrap every packet as {offset, length, value} to locate it within the input"
+        )]
+        pub spans: bool,
     }
 }
 
@@ -33,20 +45,38 @@ fn main() -> Result<()> {
     setup_panic!();
     let args = cli::Decode::from_args();
     let mut output = BufWriter::new(args.output);
+    let recovery = if args.recover {
+        preidolia::parsers::Recovery::SkipByte
+    } else {
+        preidolia::parsers::Recovery::Stop
+    };
     let parser = preidolia::parsers::ParsingIterator::new(
-        preidolia::parsers::Parser::new(&protocols::tftp::parsers::tftp),
+        preidolia::parsers::Parser::new(&protocols::tftp::parsers::tftp).with_recovery(recovery),
         args.input,
-    );
-    let tftp_packets: Box<dyn Iterator<Item = Result<protocols::tftp::TftpPacket>>> =
-        if let Some(count) = args.count {
-            Box::new(parser.take(count))
-        } else {
-            Box::new(parser)
-        };
+    )
+    .spanned();
+    let tftp_packets: Box<
+        dyn Iterator<Item = Result<preidolia::parsers::Spanned<protocols::tftp::TftpPacket>>>,
+    > = if let Some(count) = args.count {
+        Box::new(parser.take(count))
+    } else {
+        Box::new(parser)
+    };
 
     for packet in tftp_packets {
-        let p = packet?;
-        writeln!(&mut output, "{}", serde_json::to_string(&p)?)?;
+        let p = match packet {
+            Err(e) if args.recover => {
+                eprintln!("{}", e);
+                continue;
+            }
+            packet => packet?,
+        };
+        let json = if args.spans {
+            serde_json::to_string(&p)?
+        } else {
+            serde_json::to_string(&p.value)?
+        };
+        writeln!(&mut output, "{}", json)?;
         output.flush()?;
     }
     Ok(())
@@ -1,6 +1,68 @@
+use nom::error::ErrorKind;
 use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 use tobytes::{ByteView, ToBytes};
 
+pub mod backend;
+pub mod server;
+
+/// Reasons why bytes are not a valid tftp packet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TftpParseError {
+    UnknownOpcode(u16),
+    UnknownMode(String),
+    /// A file name, mode or error message is not valid UTF-8.
+    InvalidUtf8(std::str::Utf8Error),
+    /// A string is not terminated by a 0 byte.
+    MissingTerminator,
+    /// The packet ends within its opcode, block number or error code.
+    Truncated,
+    /// Any other error of the underlying nom parsers.
+    Nom(ErrorKind),
+}
+
+impl TftpParseError {
+    /// The closest error kind of nom, for parsers which can't report a [TftpParseError].
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            TftpParseError::UnknownOpcode(_) => ErrorKind::Switch,
+            TftpParseError::UnknownMode(_) => ErrorKind::Tag,
+            TftpParseError::InvalidUtf8(_) => ErrorKind::MapRes,
+            TftpParseError::MissingTerminator => ErrorKind::TakeUntil,
+            TftpParseError::Truncated => ErrorKind::Eof,
+            TftpParseError::Nom(kind) => *kind,
+        }
+    }
+}
+
+impl std::fmt::Display for TftpParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TftpParseError::UnknownOpcode(opcode) => write!(f, "Unknown opcode {}", opcode),
+            TftpParseError::UnknownMode(mode) => write!(f, "Unknown mode \"{}\"", mode),
+            TftpParseError::InvalidUtf8(e) => write!(f, "Invalid UTF-8, {}", e),
+            TftpParseError::MissingTerminator => write!(f, "String without 0 terminator"),
+            TftpParseError::Truncated => write!(f, "Packet is truncated"),
+            TftpParseError::Nom(kind) => write!(f, "Invalid packet, {:?}", kind),
+        }
+    }
+}
+
+impl std::error::Error for TftpParseError {}
+
+impl<'a> nom::error::ParseError<&'a [u8]> for TftpParseError {
+    fn from_error_kind(_input: &'a [u8], kind: ErrorKind) -> Self {
+        match kind {
+            ErrorKind::Eof => TftpParseError::Truncated,
+            kind => TftpParseError::Nom(kind),
+        }
+    }
+
+    fn append(_input: &'a [u8], _kind: ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
 /// Tftp transfer modes
 #[derive(PartialEq, Debug, Eq, Serialize, Deserialize)]
 #[serde(rename = "mode")]
@@ -11,14 +73,21 @@ pub enum Mode {
     /// also called binary in older implementations
     #[serde(rename = "octet")]
     Octet,
+    /// Netascii sent to a user instead of a file, obsolete
+    #[serde(rename = "mail")]
+    Mail,
 }
 
-impl Mode {
-    pub fn from(string: &str) -> Self {
-        match string {
-            "octet" => Mode::Octet,
-            "netascii" => Mode::Netascii,
-            _ => panic!(),
+impl FromStr for Mode {
+    type Err = TftpParseError;
+
+    /// Modes are case insensitive (RFC 1350).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "octet" => Ok(Mode::Octet),
+            "netascii" => Ok(Mode::Netascii),
+            "mail" => Ok(Mode::Mail),
+            _ => Err(TftpParseError::UnknownMode(s.to_string())),
         }
     }
 }
@@ -28,6 +97,7 @@ impl ByteView for Mode {
         match self {
             Mode::Octet => "octet\0".as_bytes(),
             Mode::Netascii => "netascii\0".as_bytes(),
+            Mode::Mail => "mail\0".as_bytes(),
         }
         .get(index)
         .cloned()
@@ -66,23 +136,61 @@ pub enum Error {
     /// 7: No such user
     #[serde(rename = "no_such_user")]
     NoSuchUser { message: String },
+    /// Any code not defined by RFC 1350, e.g. 8 for a rejected option (RFC 2347)
+    #[serde(rename = "unknown")]
+    Unknown { code: u16, message: String },
+}
+
+impl Error {
+    pub fn new(code: u16, message: String) -> Self {
+        match code {
+            0 => Error::Undefinied { message },
+            1 => Error::FileNotFound { message },
+            2 => Error::AccessViolation { message },
+            3 => Error::DiskFull { message },
+            4 => Error::IllegalTftpOperation { message },
+            5 => Error::UnkownTransferId { message },
+            6 => Error::FileAlreadyExists { message },
+            7 => Error::NoSuchUser { message },
+            code => Error::Unknown { code, message },
+        }
+    }
+
+    pub fn code(&self) -> u16 {
+        match *self {
+            Error::Undefinied { .. } => 0,
+            Error::FileNotFound { .. } => 1,
+            Error::AccessViolation { .. } => 2,
+            Error::DiskFull { .. } => 3,
+            Error::IllegalTftpOperation { .. } => 4,
+            Error::UnkownTransferId { .. } => 5,
+            Error::FileAlreadyExists { .. } => 6,
+            Error::NoSuchUser { .. } => 7,
+            Error::Unknown { code, .. } => code,
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            Error::Undefinied { message }
+            | Error::FileNotFound { message }
+            | Error::AccessViolation { message }
+            | Error::DiskFull { message }
+            | Error::IllegalTftpOperation { message }
+            | Error::UnkownTransferId { message }
+            | Error::FileAlreadyExists { message }
+            | Error::NoSuchUser { message }
+            | Error::Unknown { message, .. } => message,
+        }
+    }
 }
 
 impl ByteView for Error {
     fn byte_at(&self, index: usize) -> Option<u8> {
-        let (id, msg) = match *self {
-            Error::Undefinied { ref message } => (0u16, message),
-            Error::FileNotFound { ref message } => (1u16, message),
-            Error::AccessViolation { ref message } => (2u16, message),
-            Error::DiskFull { ref message } => (3, message),
-            Error::IllegalTftpOperation { ref message } => (4u16, message),
-            Error::UnkownTransferId { ref message } => (5u16, message),
-            Error::FileAlreadyExists { ref message } => (6u16, message),
-            Error::NoSuchUser { ref message } => (7u16, message),
-        };
-        id.to_be_bytes()
+        self.code()
+            .to_be_bytes()
             .iter()
-            .chain(msg.as_bytes().iter())
+            .chain(self.message().as_bytes().iter())
             .chain(std::iter::once(&0u8))
             .nth(index)
             .cloned()
@@ -93,6 +201,83 @@ impl ByteView for Error {
     }
 }
 
+/// Options of requests (RFC 2347), the ones accepted by the server are acknowledged by
+/// [TftpPacket::OptionAck].
+#[derive(PartialEq, Debug, Eq, Clone, Serialize, Deserialize)]
+#[serde(rename = "option")]
+pub enum TftpOption {
+    /// Bytes of data per packet, 8-65464 (RFC 2348)
+    #[serde(rename = "blksize")]
+    BlockSize(u16),
+    /// Seconds to wait before retransmitting, 1-255 (RFC 2349)
+    #[serde(rename = "timeout")]
+    Timeout(u8),
+    /// Size of the file in bytes, 0 in read requests to ask for it (RFC 2349)
+    #[serde(rename = "tsize")]
+    TransferSize(u64),
+    /// Data packets sent before an ack is awaited, 1-65535 (RFC 7440)
+    #[serde(rename = "windowsize")]
+    WindowSize(u16),
+    /// Any other option, or a known one with an invalid value
+    #[serde(rename = "unknown")]
+    Unknown { name: String, value: String },
+}
+
+impl TftpOption {
+    /// Interprets an option, its name is case insensitive.
+    pub fn new(name: &str, value: &str) -> Self {
+        let number = |range: std::ops::RangeInclusive<u64>| {
+            value.parse::<u64>().ok().filter(|v| range.contains(v))
+        };
+        let option = match name.to_ascii_lowercase().as_str() {
+            "blksize" => number(8..=65464).map(|v| TftpOption::BlockSize(v as u16)),
+            "timeout" => number(1..=255).map(|v| TftpOption::Timeout(v as u8)),
+            "tsize" => number(0..=u64::MAX).map(TftpOption::TransferSize),
+            "windowsize" => number(1..=65535).map(|v| TftpOption::WindowSize(v as u16)),
+            _ => None,
+        };
+        option.unwrap_or_else(|| TftpOption::Unknown {
+            name: name.to_string(),
+            value: value.to_string(),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            TftpOption::BlockSize(_) => "blksize",
+            TftpOption::Timeout(_) => "timeout",
+            TftpOption::TransferSize(_) => "tsize",
+            TftpOption::WindowSize(_) => "windowsize",
+            TftpOption::Unknown { name, .. } => name,
+        }
+    }
+
+    pub fn value(&self) -> String {
+        match self {
+            TftpOption::BlockSize(size) => size.to_string(),
+            TftpOption::Timeout(seconds) => seconds.to_string(),
+            TftpOption::TransferSize(size) => size.to_string(),
+            TftpOption::WindowSize(size) => size.to_string(),
+            TftpOption::Unknown { value, .. } => value.clone(),
+        }
+    }
+}
+
+impl ByteView for TftpOption {
+    fn byte_at(&self, index: usize) -> Option<u8> {
+        self.name()
+            .bytes()
+            .chain(std::iter::once(0u8))
+            .chain(self.value().into_bytes())
+            .chain(std::iter::once(0u8))
+            .nth(index)
+    }
+
+    fn byte_size(&self) -> usize {
+        self.name().len() + self.value().len() + 2
+    }
+}
+
 // TODO NiCo: add mode for rrq and wrq -> right now it allways will be octett
 // TODO NiCo: optimize memory efficiency -> String -> &str, Vec<u8> -> &[u8]
 /// Defines all available types of tftp packets
@@ -105,6 +290,8 @@ pub enum TftpPacket {
         #[serde(rename = "file_name")]
         filename: String,
         mode: Mode,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        options: Vec<TftpOption>,
     },
     /// Opcode 0x02
     #[serde(rename = "write_request")]
@@ -112,6 +299,8 @@ pub enum TftpPacket {
         #[serde(rename = "file_name")]
         filename: String,
         mode: Mode,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        options: Vec<TftpOption>,
     },
     /// Opcode 0x03
     #[serde(rename = "data")]
@@ -122,34 +311,68 @@ pub enum TftpPacket {
     /// Opcode 0x05
     #[serde(rename = "error")]
     Error { error: Error },
+    /// Opcode 0x06
+    #[serde(rename = "option_ack")]
+    OptionAck { options: Vec<TftpOption> },
+}
+
+impl TftpPacket {
+    /// Parses the packet of a datagram, bytes following the packet are ignored.
+    pub fn parse(datagram: &[u8]) -> Result<Self, TftpParseError> {
+        match parsers::packet(datagram) {
+            Ok((_, packet)) => Ok(packet),
+            Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => Err(e),
+            // Datagrams are complete, only strings can be longer than the bytes at hand.
+            Err(nom::Err::Incomplete(_)) => {
+                let header = match datagram.get(..2) {
+                    Some([0, 1]) | Some([0, 2]) | Some([0, 6]) => 2,
+                    _ => 4,
+                };
+                if datagram.len() < header {
+                    Err(TftpParseError::Truncated)
+                } else {
+                    Err(TftpParseError::MissingTerminator)
+                }
+            }
+        }
+    }
 }
 
 impl ByteView for TftpPacket {
     fn byte_at(&self, index: usize) -> Option<u8> {
         match self {
-            TftpPacket::ReadRequest { filename, mode } => 1u16
+            TftpPacket::ReadRequest {
+                filename,
+                mode,
+                options,
+            } => 1u16
                 .to_be_bytes()
                 .iter()
                 .cloned()
                 .chain(filename.as_bytes().iter().cloned())
                 .chain(std::iter::once(0u8))
                 .chain(mode.to_bytes())
+                .chain(options.iter().flat_map(ToBytes::to_bytes))
                 .nth(index),
-            TftpPacket::WriteRequest { filename, mode } => 2u16
+            TftpPacket::WriteRequest {
+                filename,
+                mode,
+                options,
+            } => 2u16
                 .to_be_bytes()
                 .iter()
                 .cloned()
                 .chain(filename.as_bytes().iter().cloned())
                 .chain(std::iter::once(0u8))
                 .chain(mode.to_bytes())
+                .chain(options.iter().flat_map(ToBytes::to_bytes))
                 .nth(index),
-            TftpPacket::Data { block, data } => 3u16
-                .to_be_bytes()
-                .iter()
-                .cloned()
-                .chain(block.to_be_bytes().iter().cloned())
-                .chain(data.iter().cloned())
-                .nth(index),
+            // Indexed directly, data can be large and nth walks up to the index
+            TftpPacket::Data { block, data } => match index {
+                0..=1 => 3u16.to_be_bytes().get(index).cloned(),
+                2..=3 => block.to_be_bytes().get(index - 2).cloned(),
+                _ => data.get(index - 4).cloned(),
+            },
             TftpPacket::Ack { block } => 4u16
                 .to_be_bytes()
                 .iter()
@@ -162,6 +385,12 @@ impl ByteView for TftpPacket {
                 .cloned()
                 .chain(error.to_bytes())
                 .nth(index),
+            TftpPacket::OptionAck { options } => 6u16
+                .to_be_bytes()
+                .iter()
+                .cloned()
+                .chain(options.iter().flat_map(ToBytes::to_bytes))
+                .nth(index),
         }
     }
 
@@ -180,6 +409,7 @@ mod tests {
         let rrq = TftpPacket::ReadRequest {
             filename: String::from("file.txt"),
             mode: Mode::Octet,
+            options: vec![],
         };
         let expected: Vec<u8> = vec![
             0x00, 0x01, // opcode
@@ -197,6 +427,7 @@ mod tests {
         let wrq = TftpPacket::WriteRequest {
             filename: String::from("file.txt"),
             mode: Mode::Octet,
+            options: vec![],
         };
         let expected: Vec<u8> = vec![
             0x00, 0x02, // opcode
@@ -252,61 +483,253 @@ mod tests {
 
         assert_eq!(expected, data.to_bytes().collect::<Vec<u8>>());
     }
+
+    #[test]
+    fn serialize_read_request_with_options() {
+        let rrq = TftpPacket::ReadRequest {
+            filename: String::from("f"),
+            mode: Mode::Octet,
+            options: vec![TftpOption::BlockSize(1428), TftpOption::TransferSize(0)],
+        };
+        let mut expected: Vec<u8> = vec![0x00, 0x01, 0x66, 0x00];
+        expected.extend_from_slice(b"octet\0blksize\x001428\0tsize\x000\0");
+
+        assert_eq!(expected, rrq.to_bytes().collect::<Vec<u8>>());
+        assert_eq!(expected.len(), rrq.byte_size());
+    }
+
+    #[test]
+    fn serialize_option_ack() {
+        let oack = TftpPacket::OptionAck {
+            options: vec![
+                TftpOption::WindowSize(16),
+                TftpOption::Timeout(3),
+                TftpOption::Unknown {
+                    name: String::from("Foo"),
+                    value: String::from("bar"),
+                },
+            ],
+        };
+        let mut expected: Vec<u8> = vec![0x00, 0x06];
+        expected.extend_from_slice(b"windowsize\x0016\0timeout\x003\0Foo\0bar\0");
+
+        assert_eq!(expected, oack.to_bytes().collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn options_are_validated() {
+        assert_eq!(
+            TftpOption::new("BLKSIZE", "512"),
+            TftpOption::BlockSize(512)
+        );
+        assert_eq!(TftpOption::new("tsize", "0"), TftpOption::TransferSize(0));
+        assert_eq!(
+            TftpOption::new("windowsize", "8"),
+            TftpOption::WindowSize(8)
+        );
+        for (name, value) in &[
+            ("blksize", "4"),
+            ("blksize", "65465"),
+            ("timeout", "0"),
+            ("windowsize", "65536"),
+            ("tsize", "-1"),
+            ("multicast", ""),
+        ] {
+            assert_eq!(
+                TftpOption::new(name, value),
+                TftpOption::Unknown {
+                    name: name.to_string(),
+                    value: value.to_string()
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn options_in_json() {
+        let json = r#"{"write_request":{"file_name":"f","mode":"octet","options":[{"blksize":1428},{"unknown":{"name":"foo","value":"bar"}}]}}"#;
+        let wrq: TftpPacket = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            wrq,
+            TftpPacket::WriteRequest {
+                filename: String::from("f"),
+                mode: Mode::Octet,
+                options: vec![TftpOption::BlockSize(1428), TftpOption::new("foo", "bar")],
+            }
+        );
+        assert_eq!(serde_json::to_string(&wrq).unwrap(), json);
+
+        let json = r#"{"read_request":{"file_name":"f","mode":"mail"}}"#;
+        let rrq: TftpPacket = serde_json::from_str(json).unwrap();
+        assert_eq!(serde_json::to_string(&rrq).unwrap(), json);
+
+        let json = r#"{"option_ack":{"options":[{"tsize":42},{"timeout":5}]}}"#;
+        let oack: TftpPacket = serde_json::from_str(json).unwrap();
+        assert_eq!(serde_json::to_string(&oack).unwrap(), json);
+    }
+
+    #[test]
+    fn serialize_unknown_error() {
+        let error = Error::new(8, String::from("no"));
+        assert_eq!(
+            error,
+            Error::Unknown {
+                code: 8,
+                message: String::from("no")
+            }
+        );
+        let expected: Vec<u8> = vec![0x00, 0x08, 0x6e, 0x6f, 0x00];
+        assert_eq!(expected, error.to_bytes().collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn modes_are_case_insensitive() {
+        assert_eq!("OCTET".parse(), Ok(Mode::Octet));
+        assert_eq!("NetAscii".parse(), Ok(Mode::Netascii));
+        assert_eq!("mail".parse(), Ok(Mode::Mail));
+        assert_eq!(
+            "binary".parse::<Mode>(),
+            Err(TftpParseError::UnknownMode(String::from("binary")))
+        );
+        assert_eq!(
+            b"mail\0".to_vec(),
+            Mode::Mail.to_bytes().collect::<Vec<u8>>()
+        );
+    }
+
+    #[test]
+    fn parse_invalid_datagrams() {
+        let parse = |datagram: &[u8]| TftpPacket::parse(datagram).unwrap_err();
+        assert_eq!(
+            parse(&[0x00, 0x09, 0x00, 0x01]),
+            TftpParseError::UnknownOpcode(9)
+        );
+        assert_eq!(
+            parse(b"\x00\x01file.txt\0binary\0"),
+            TftpParseError::UnknownMode(String::from("binary"))
+        );
+        assert!(matches!(
+            parse(b"\x00\x02\xfffile\0octet\0"),
+            TftpParseError::InvalidUtf8(_)
+        ));
+        assert_eq!(
+            parse(b"\x00\x01file.txt"),
+            TftpParseError::MissingTerminator
+        );
+        assert_eq!(
+            parse(b"\x00\x01file.txt\0octet"),
+            TftpParseError::MissingTerminator
+        );
+        assert_eq!(
+            parse(b"\x00\x05\x00\x01oops"),
+            TftpParseError::MissingTerminator
+        );
+        assert_eq!(parse(&[0x00, 0x04, 0x00]), TftpParseError::Truncated);
+        assert_eq!(parse(&[0x00]), TftpParseError::Truncated);
+        assert_eq!(
+            TftpPacket::parse(b"\x00\x01file.txt\0Octet\0"),
+            Ok(TftpPacket::ReadRequest {
+                filename: String::from("file.txt"),
+                mode: Mode::Octet,
+                options: vec![],
+            })
+        );
+    }
 }
 
 pub mod parsers {
-    use super::{Error, Mode, TftpPacket};
+    use super::{Error, Mode, TftpOption, TftpPacket, TftpParseError};
+    use nom::bytes::streaming::take_until;
     use nom::number::complete::be_u8;
     use nom::number::streaming::be_u16;
-    use nom::{do_parse, many0, map, map_res, named, switch, take, take_until};
+    use nom::{do_parse, many0, named, IResult};
 
-    named!(string<&[u8], &str>,
-        do_parse!(
-            string: map_res!(take_until!("\0"), ::std::str::from_utf8) >>
-            take!(1) >>
-            (string)
-        )
-    );
+    fn string(input: &[u8]) -> IResult<&[u8], &str, TftpParseError> {
+        let (rest, bytes) = take_until("\0")(input)?;
+        let string = std::str::from_utf8(bytes)
+            .map_err(|e| nom::Err::Error(TftpParseError::InvalidUtf8(e)))?;
+        Ok((&rest[1..], string))
+    }
 
     // FIXME: either parse 512 byte or to the enf if < 512
-    named!(data<&[u8], Vec<u8>>, many0!(be_u8));
-    named!(opcode<&[u8], u16>, do_parse!(value: be_u16 >> (value)));
-    named!(block<&[u8], u16>, do_parse!(value: be_u16 >> (value)));
-    named!(error_code<&[u8], u16>, do_parse!( value: be_u16 >> (value)));
-    named!(mode<&[u8], Mode>, map!(string, Mode::from));
-
-    named!(error<&[u8], Error>,
-        switch!(error_code,
-            0 => do_parse!( msg: string >> (Error::Undefinied { message: String::from(msg) }))                  |
-            1 => do_parse!( msg: string >> (Error::FileNotFound { message: String::from(msg) }))                |
-            2 => do_parse!( msg: string >> (Error::AccessViolation { message: String::from(msg) }))             |
-            3 => do_parse!( msg: string >> (Error::DiskFull { message: String::from(msg) }))                    |
-            4 => do_parse!( msg: string >> (Error::IllegalTftpOperation { message: String::from(msg) }))        |
-            5 => do_parse!( msg: string >> (Error::UnkownTransferId { message: String::from(msg) }))            |
-            6 => do_parse!( msg: string >> (Error::FileAlreadyExists { message: String::from(msg) }))           |
-            7 => do_parse!( msg: string >> (Error::NoSuchUser { message: String::from(msg) }))
-         )
-    );
-
-    // FIXME NiCo: what happens if unknonw value occurs?
-    named!(pub tftp<&[u8], TftpPacket>,
-               switch!(opcode,
-                1 => do_parse!(
-                    name: string >>
-                    mode: string >>
-                    (TftpPacket::ReadRequest { filename: String::from(name), mode: Mode::from(mode) }))         |
-                2 => do_parse!(
-                    name: string >>
-                    mode: string >>
-                    (TftpPacket::WriteRequest { filename: String::from(name), mode: Mode::from(mode) }))        |
-                3 => do_parse!(
-                    id: block >>
-                    payload: data >>
-                    (TftpPacket::Data { block: id, data: payload }))                    |
-                4 => do_parse!(id : block >> (TftpPacket::Ack { block : id }))          |
-                5 => do_parse!(err : error >> (TftpPacket::Error { error: err }))
-                )
-    );
+    named!(data<&[u8], Vec<u8>, TftpParseError>, many0!(be_u8));
+    named!(opcode<&[u8], u16, TftpParseError>, do_parse!(value: be_u16 >> (value)));
+    named!(block<&[u8], u16, TftpParseError>, do_parse!(value: be_u16 >> (value)));
+    named!(error_code<&[u8], u16, TftpParseError>, do_parse!( value: be_u16 >> (value)));
+
+    fn mode(input: &[u8]) -> IResult<&[u8], Mode, TftpParseError> {
+        let (rest, mode) = string(input)?;
+        Ok((rest, mode.parse().map_err(nom::Err::Error)?))
+    }
+
+    fn error(input: &[u8]) -> IResult<&[u8], Error, TftpParseError> {
+        let (rest, code) = error_code(input)?;
+        let (rest, message) = string(rest)?;
+        Ok((rest, Error::new(code, String::from(message))))
+    }
+
+    /// Parses options up to the end of the input or the next packet, which starts with a 0 byte
+    /// unlike option names.
+    fn options(mut input: &[u8]) -> IResult<&[u8], Vec<TftpOption>, TftpParseError> {
+        let mut options = Vec::new();
+        while input.first().is_some_and(|byte| *byte != 0) {
+            let (rest, name) = string(input)?;
+            let (rest, value) = string(rest)?;
+            options.push(TftpOption::new(name, value));
+            input = rest;
+        }
+        Ok((input, options))
+    }
+
+    /// Parses a tftp packet, incomplete packets are reported as such to wait for more input.
+    pub fn packet(input: &[u8]) -> IResult<&[u8], TftpPacket, TftpParseError> {
+        let (rest, opcode) = opcode(input)?;
+        match opcode {
+            1 | 2 => {
+                let (rest, name) = string(rest)?;
+                let (rest, mode) = mode(rest)?;
+                let (rest, options) = options(rest)?;
+                let filename = String::from(name);
+                let packet = if opcode == 1 {
+                    TftpPacket::ReadRequest {
+                        filename,
+                        mode,
+                        options,
+                    }
+                } else {
+                    TftpPacket::WriteRequest {
+                        filename,
+                        mode,
+                        options,
+                    }
+                };
+                Ok((rest, packet))
+            }
+            3 => {
+                let (rest, block) = block(rest)?;
+                let (rest, data) = data(rest)?;
+                Ok((rest, TftpPacket::Data { block, data }))
+            }
+            4 => {
+                let (rest, block) = block(rest)?;
+                Ok((rest, TftpPacket::Ack { block }))
+            }
+            5 => {
+                let (rest, error) = error(rest)?;
+                Ok((rest, TftpPacket::Error { error }))
+            }
+            6 => {
+                let (rest, options) = options(rest)?;
+                Ok((rest, TftpPacket::OptionAck { options }))
+            }
+            opcode => Err(nom::Err::Error(TftpParseError::UnknownOpcode(opcode))),
+        }
+    }
+
+    /// [packet] with the error type of nom, e.g. to be driven by a [preidolia::parsers::Parser].
+    pub fn tftp(input: &[u8]) -> IResult<&[u8], TftpPacket> {
+        packet(input).map_err(|e| e.map(|e| nom::error::Error::new(input, e.kind())))
+    }
 
     #[cfg(test)]
     mod tests {
@@ -370,6 +793,13 @@ pub mod parsers {
                 mode(&b"netascii\0"[..]),
                 IResult::Ok((&b""[..], Mode::Netascii))
             );
+            assert_eq!(mode(&b"MAIL\0"[..]), IResult::Ok((&b""[..], Mode::Mail)));
+            assert_eq!(
+                mode(&b"image\0"[..]),
+                Err(nom::Err::Error(TftpParseError::UnknownMode(String::from(
+                    "image"
+                ))))
+            );
         }
 
         #[test]
@@ -389,6 +819,83 @@ pub mod parsers {
             assert_eq!(error(&input), expected);
         }
 
+        #[test]
+        fn parse_unknown_error() {
+            let expected = IResult::Ok((
+                &b""[..],
+                Error::Unknown {
+                    code: 8,
+                    message: String::from("file.txt"),
+                },
+            ));
+            let input: Vec<u8> = vec![
+                0x00, 0x08, // error code
+                0x66, 0x69, 0x6c, 0x65, 0x2e, 0x74, 0x78, 0x74, // error msg (file.txt)
+                0x00, // 0 terminator
+            ];
+
+            assert_eq!(error(&input), expected);
+        }
+
+        #[test]
+        fn parse_tftp_unknown_opcode() {
+            let input: Vec<u8> = vec![
+                0x00, 0x07, // opcode
+                0x00, 0x02, // blockid
+            ];
+
+            assert_eq!(
+                packet(&input),
+                Err(nom::Err::Error(TftpParseError::UnknownOpcode(7)))
+            );
+            assert_eq!(
+                tftp(&input),
+                Err(nom::Err::Error(nom::error::Error::new(
+                    &input[..],
+                    nom::error::ErrorKind::Switch
+                )))
+            );
+        }
+
+        #[test]
+        fn parse_tftp_rrq_with_options() {
+            let input =
+                b"\x00\x01file.txt\0octet\0BlkSize\x001024\0windowsize\x004\0\x00\x04\x00\x01";
+            let expected = IResult::Ok((
+                &b"\x00\x04\x00\x01"[..],
+                TftpPacket::ReadRequest {
+                    filename: String::from("file.txt"),
+                    mode: Mode::Octet,
+                    options: vec![TftpOption::BlockSize(1024), TftpOption::WindowSize(4)],
+                },
+            ));
+
+            assert_eq!(tftp(input), expected);
+        }
+
+        #[test]
+        fn parse_tftp_option_ack() {
+            let input = b"\x00\x06tsize\x00123\0";
+            let expected = IResult::Ok((
+                &b""[..],
+                TftpPacket::OptionAck {
+                    options: vec![TftpOption::TransferSize(123)],
+                },
+            ));
+
+            assert_eq!(tftp(input), expected);
+            assert_eq!(
+                TftpPacket::parse(b"\x00\x06tsize\x00123"),
+                Err(TftpParseError::MissingTerminator)
+            );
+        }
+
+        #[test]
+        fn parse_tftp_incomplete() {
+            let input = b"\x00\x01file.txt";
+            assert!(matches!(packet(input), Err(nom::Err::Incomplete(_))));
+        }
+
         #[test]
         fn parse_tftp_rrq() {
             let expected = IResult::Ok((
@@ -396,6 +903,7 @@ pub mod parsers {
                 TftpPacket::ReadRequest {
                     filename: String::from("file.txt"),
                     mode: Mode::Octet,
+                    options: vec![],
                 },
             ));
             let input: Vec<u8> = vec![
@@ -416,6 +924,7 @@ pub mod parsers {
                 TftpPacket::WriteRequest {
                     filename: String::from("file.txt"),
                     mode: Mode::Octet,
+                    options: vec![],
                 },
             ));
             let input: Vec<u8> = vec![
@@ -0,0 +1,361 @@
+//! Where the files of the tftp [server](super::server) are read from and written to.
+use super::Error;
+use bricks::cli::{AtomicFile, Mode};
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::task::JoinHandle;
+
+pub type Reader = Box<dyn AsyncRead + Send + Unpin>;
+pub type Writer = Box<dyn AsyncWrite + Send + Unpin>;
+/// The pending result of a [Backend] call.
+pub type Pending<'a, T> = Pin<Box<dyn Future<Output = Result<T, Error>> + Send + 'a>>;
+
+/// The files served, errors are sent to the client as they are.
+///
+/// Both calls are awaited by the server, they must not block the runtime.
+pub trait Backend: Send + Sync + 'static {
+    /// The file of a read request and its size, if it is known.
+    fn open<'a>(&'a self, filename: &'a str) -> Pending<'a, (Reader, Option<u64>)>;
+
+    /// The file of a write request, it is complete once the writer is shut down.
+    fn create<'a>(&'a self, filename: &'a str) -> Pending<'a, Writer>;
+}
+
+/// Serves the regular files below a directory, existing files are replaced once a write
+/// request completed.
+#[derive(Debug, Clone)]
+pub struct Directory {
+    root: PathBuf,
+}
+
+impl Directory {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Directory { root: root.into() }
+    }
+
+    /// The path of a file name, relative to the root even if it starts with a `/`. File names
+    /// leaving the root or naming the root itself are refused.
+    fn path(&self, filename: &str) -> Result<PathBuf, Error> {
+        let relative = Path::new(filename.trim_start_matches('/'));
+        let inside = relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+        if !inside {
+            return Err(Error::AccessViolation {
+                message: format!("\"{}\" is outside of the served directory", filename),
+            });
+        }
+        if !relative
+            .components()
+            .any(|c| matches!(c, Component::Normal(_)))
+        {
+            return Err(Error::AccessViolation {
+                message: format!("\"{}\" does not name a file", filename),
+            });
+        }
+        Ok(self.root.join(relative))
+    }
+}
+
+fn error(e: io::Error) -> Error {
+    let message = e.to_string();
+    match e.kind() {
+        io::ErrorKind::NotFound => Error::FileNotFound { message },
+        io::ErrorKind::PermissionDenied => Error::AccessViolation { message },
+        io::ErrorKind::AlreadyExists => Error::FileAlreadyExists { message },
+        _ => Error::Undefinied { message },
+    }
+}
+
+/// Refuses anything but regular files, opening a fifo or a device could block forever.
+fn regular_file(filename: &str, metadata: &std::fs::Metadata) -> Result<(), Error> {
+    if metadata.is_file() {
+        Ok(())
+    } else {
+        Err(Error::FileNotFound {
+            message: format!("\"{}\" is not a file", filename),
+        })
+    }
+}
+
+impl Backend for Directory {
+    fn open<'a>(&'a self, filename: &'a str) -> Pending<'a, (Reader, Option<u64>)> {
+        Box::pin(async move {
+            let path = self.path(filename)?;
+            regular_file(
+                filename,
+                &tokio::fs::symlink_metadata(&path).await.map_err(error)?,
+            )?;
+            let file = tokio::fs::File::open(&path).await.map_err(error)?;
+            // the path could have been replaced in the meantime
+            let metadata = file.metadata().await.map_err(error)?;
+            regular_file(filename, &metadata)?;
+            let reader: Reader = Box::new(file);
+            Ok((reader, Some(metadata.len())))
+        })
+    }
+
+    fn create<'a>(&'a self, filename: &'a str) -> Pending<'a, Writer> {
+        Box::pin(async move {
+            let destination = self.path(filename)?;
+            if let Ok(metadata) = tokio::fs::symlink_metadata(&destination).await {
+                regular_file(filename, &metadata)?;
+            }
+            let atomic = tokio::task::spawn_blocking(move || {
+                AtomicFile::create(&destination, Mode::Truncate, true)
+            })
+            .await
+            .map_err(|e| error(e.into()))?
+            .map_err(|e| match e.downcast::<io::Error>() {
+                Ok(e) => error(e),
+                Err(e) => Error::Undefinied {
+                    message: e.to_string(),
+                },
+            })?;
+            let file = atomic.file().try_clone().map_err(error)?;
+            let writer: Writer = Box::new(DirectoryFile {
+                file: tokio::fs::File::from_std(file),
+                atomic: Some(atomic),
+                persisting: None,
+            });
+            Ok(writer)
+        })
+    }
+}
+
+/// A file being written next to its destination, it replaces the destination once it is shut
+/// down and is removed if it is dropped before.
+struct DirectoryFile {
+    file: tokio::fs::File,
+    atomic: Option<AtomicFile>,
+    persisting: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl AsyncWrite for DirectoryFile {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().file).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().file).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let file = self.get_mut();
+        if file.persisting.is_none() {
+            match Pin::new(&mut file.file).poll_shutdown(cx) {
+                Poll::Ready(Ok(())) => {}
+                poll => return poll,
+            }
+            let atomic = match file.atomic.take() {
+                Some(atomic) => atomic,
+                None => return Poll::Ready(Ok(())),
+            };
+            file.persisting = Some(tokio::task::spawn_blocking(move || atomic.persist()));
+        }
+        let persisting = file.persisting.as_mut().unwrap();
+        match Pin::new(persisting).poll(cx) {
+            Poll::Ready(result) => {
+                file.persisting = None;
+                Poll::Ready(result.map_err(io::Error::from).and_then(|result| result))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Serves files kept in memory, clones share the files.
+#[derive(Debug, Clone, Default)]
+pub struct Memory {
+    files: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Memory::default()
+    }
+
+    pub fn insert(&self, filename: &str, content: Vec<u8>) {
+        self.files
+            .lock()
+            .unwrap()
+            .insert(filename.to_string(), content);
+    }
+
+    pub fn get(&self, filename: &str) -> Option<Vec<u8>> {
+        self.files.lock().unwrap().get(filename).cloned()
+    }
+}
+
+impl Backend for Memory {
+    fn open<'a>(&'a self, filename: &'a str) -> Pending<'a, (Reader, Option<u64>)> {
+        Box::pin(async move {
+            let content = self.get(filename).ok_or_else(|| Error::FileNotFound {
+                message: format!("\"{}\" does not exist", filename),
+            })?;
+            let size = content.len() as u64;
+            let reader: Reader = Box::new(io::Cursor::new(content));
+            Ok((reader, Some(size)))
+        })
+    }
+
+    fn create<'a>(&'a self, filename: &'a str) -> Pending<'a, Writer> {
+        Box::pin(async move {
+            let writer: Writer = Box::new(MemoryFile {
+                filename: filename.to_string(),
+                content: Vec::new(),
+                files: self.files.clone(),
+            });
+            Ok(writer)
+        })
+    }
+}
+
+/// A file being written, it replaces the one of the same name once it is shut down.
+struct MemoryFile {
+    filename: String,
+    content: Vec<u8>,
+    files: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+}
+
+impl AsyncWrite for MemoryFile {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut().content.extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let file = self.get_mut();
+        let content = std::mem::take(&mut file.content);
+        file.files
+            .lock()
+            .unwrap()
+            .insert(file.filename.clone(), content);
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn directories_serve_the_files_below_them() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::create_dir(root.path().join("boot")).unwrap();
+        let directory = Directory::new(root.path());
+
+        let mut writer = directory.create("/boot/image").await.unwrap();
+        writer.write_all(b"kernel").await.unwrap();
+        writer.shutdown().await.unwrap();
+        let (mut reader, size) = directory.open("boot/image").await.unwrap();
+        let mut content = Vec::new();
+        reader.read_to_end(&mut content).await.unwrap();
+        assert_eq!((content, size), (b"kernel".to_vec(), Some(6)));
+
+        assert!(matches!(
+            directory.open("../etc/passwd").await,
+            Err(Error::AccessViolation { .. })
+        ));
+        assert!(matches!(
+            directory.create("boot/../../escape").await,
+            Err(Error::AccessViolation { .. })
+        ));
+        assert!(matches!(
+            directory.open("missing").await,
+            Err(Error::FileNotFound { .. })
+        ));
+        assert!(matches!(
+            directory.open("boot").await,
+            Err(Error::FileNotFound { .. })
+        ));
+        for filename in &["", "/", ".", "./"] {
+            assert!(matches!(
+                directory.create(filename).await,
+                Err(Error::AccessViolation { .. })
+            ));
+        }
+        assert_eq!(std::fs::read_dir(root.path()).unwrap().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn only_regular_files_are_served() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        std::fs::write(root.join("file"), b"content").unwrap();
+        std::os::unix::fs::symlink(root.join("file"), root.join("link")).unwrap();
+        let status = std::process::Command::new("mkfifo")
+            .arg(root.join("fifo"))
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let directory = Directory::new(root);
+
+        for filename in &["link", "fifo"] {
+            assert!(matches!(
+                directory.open(filename).await,
+                Err(Error::FileNotFound { .. })
+            ));
+            assert!(matches!(
+                directory.create(filename).await,
+                Err(Error::FileNotFound { .. })
+            ));
+        }
+        assert_eq!(std::fs::read(root.join("file")).unwrap(), b"content");
+    }
+
+    #[tokio::test]
+    async fn directory_files_are_replaced_once_written() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        std::fs::write(root.join("config"), b"old").unwrap();
+        let directory = Directory::new(root);
+        let read = || std::fs::read(root.join("config")).unwrap();
+
+        let mut writer = directory.create("config").await.unwrap();
+        writer.write_all(b"partial").await.unwrap();
+        writer.flush().await.unwrap();
+        assert_eq!(read(), b"old");
+        // an aborted transfer leaves the file as it was
+        drop(writer);
+        assert_eq!(read(), b"old");
+        assert_eq!(std::fs::read_dir(root).unwrap().count(), 1);
+
+        let mut writer = directory.create("config").await.unwrap();
+        writer.write_all(b"new").await.unwrap();
+        writer.shutdown().await.unwrap();
+        assert_eq!(read(), b"new");
+        assert_eq!(std::fs::read_dir(root).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn memory_files_are_replaced_once_written() {
+        let memory = Memory::new();
+        memory.insert("config", b"old".to_vec());
+        let mut writer = memory.create("config").await.unwrap();
+        writer.write_all(b"new").await.unwrap();
+        assert_eq!(memory.get("config"), Some(b"old".to_vec()));
+        writer.shutdown().await.unwrap();
+        assert_eq!(memory.get("config"), Some(b"new".to_vec()));
+    }
+}
@@ -0,0 +1,775 @@
+//! A tftp server (RFC 1350) serving the files of a [Backend].
+//!
+//! Every request is served from a socket of its own, its port is the transfer id (TID) of the
+//! session. The block size, timeout, transfer size and window size options are negotiated
+//! (RFC 2347, 2348, 2349 and 7440). Blocks are only resent on timeouts and never on duplicate
+//! acks, which would double the packets sent from then on (Sorcerer's Apprentice Syndrome).
+//!
+//! `netascii` files are transferred as they are, `mail` requests are refused.
+use super::backend::{Backend, Reader, Writer};
+use super::{Error, Mode, TftpOption, TftpPacket};
+use std::collections::VecDeque;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tobytes::ToBytes;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{ToSocketAddrs, UdpSocket};
+use tokio::time::Instant;
+
+/// Block size unless another one is negotiated.
+pub const DEFAULT_BLOCK_SIZE: u16 = 512;
+/// Largest block size allowed by RFC 2348.
+pub const MAX_BLOCK_SIZE: u16 = 65464;
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Time to wait for the client before resending, unless it negotiates another one.
+    pub timeout: Duration,
+    /// Times a packet is resent before a session is given up.
+    pub retries: u32,
+    /// Largest block size granted to clients.
+    pub max_block_size: u16,
+    /// Largest window size granted to clients.
+    pub max_window_size: u16,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            timeout: Duration::from_secs(1),
+            retries: 5,
+            max_block_size: MAX_BLOCK_SIZE,
+            max_window_size: 64,
+        }
+    }
+}
+
+pub struct Server<B: Backend> {
+    socket: UdpSocket,
+    backend: Arc<B>,
+    config: Config,
+}
+
+impl<B: Backend> Server<B> {
+    /// Listens for requests at `address`, tftp's well known port is 69.
+    pub async fn bind<A: ToSocketAddrs>(address: A, backend: B) -> io::Result<Self> {
+        Ok(Server {
+            socket: UdpSocket::bind(address).await?,
+            backend: Arc::new(backend),
+            config: Config::default(),
+        })
+    }
+
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Serves requests until receiving fails, every session runs in a task of its own.
+    pub async fn run(self) -> io::Result<()> {
+        let mut buffer = vec![0u8; usize::from(MAX_BLOCK_SIZE) + 4];
+        loop {
+            let (length, peer) = self.socket.recv_from(&mut buffer).await?;
+            let error = match TftpPacket::parse(&buffer[..length]) {
+                Ok(request) => match self.serve(request, peer).await {
+                    Ok(()) => continue,
+                    Err(error) => error,
+                },
+                Err(e) => Error::IllegalTftpOperation {
+                    message: e.to_string(),
+                },
+            };
+            let error: Vec<u8> = TftpPacket::Error { error }.to_bytes().collect();
+            // Like the packet it answers, the error may get lost.
+            let _ = self.socket.send_to(&error, peer).await;
+        }
+    }
+
+    /// Starts a session for `request`.
+    async fn serve(&self, request: TftpPacket, peer: SocketAddr) -> Result<(), Error> {
+        let (filename, mode, options, read) = match request {
+            TftpPacket::ReadRequest {
+                filename,
+                mode,
+                options,
+            } => (filename, mode, options, true),
+            TftpPacket::WriteRequest {
+                filename,
+                mode,
+                options,
+            } => (filename, mode, options, false),
+            _ => {
+                return Err(Error::IllegalTftpOperation {
+                    message: String::from("Expected a read or write request"),
+                })
+            }
+        };
+        if mode == Mode::Mail {
+            return Err(Error::IllegalTftpOperation {
+                message: String::from("The mail mode is not supported"),
+            });
+        }
+        let ip = self.socket.local_addr().map_err(undefined)?.ip();
+        let socket = UdpSocket::bind((ip, 0)).await.map_err(undefined)?;
+        let session = Session::new(socket, peer, &self.config);
+        let backend = self.backend.clone();
+        let config = self.config.clone();
+        // Failed sessions only concern their client.
+        if read {
+            tokio::spawn(
+                async move { session.send(&*backend, &filename, &options, &config).await },
+            );
+        } else {
+            tokio::spawn(async move {
+                session
+                    .receive(&*backend, &filename, &options, &config)
+                    .await
+            });
+        }
+        Ok(())
+    }
+}
+
+fn undefined(e: io::Error) -> Error {
+    Error::Undefinied {
+        message: e.to_string(),
+    }
+}
+
+/// The transfer of a file with one client.
+struct Session {
+    socket: UdpSocket,
+    peer: SocketAddr,
+    timeout: Duration,
+    retries: u32,
+    block_size: usize,
+    window_size: usize,
+    buffer: Vec<u8>,
+}
+
+impl Session {
+    fn new(socket: UdpSocket, peer: SocketAddr, config: &Config) -> Self {
+        Session {
+            socket,
+            peer,
+            timeout: config.timeout,
+            retries: config.retries,
+            block_size: usize::from(DEFAULT_BLOCK_SIZE),
+            window_size: 1,
+            buffer: vec![0u8; usize::from(MAX_BLOCK_SIZE) + 4],
+        }
+    }
+
+    /// Adopts the options of a request within the limits of `config` and returns the ones to
+    /// acknowledge. `size` is the transfer size to acknowledge, if any.
+    fn negotiate(
+        &mut self,
+        options: &[TftpOption],
+        size: Option<u64>,
+        config: &Config,
+    ) -> Vec<TftpOption> {
+        let mut granted = Vec::new();
+        for option in options {
+            match *option {
+                TftpOption::BlockSize(requested) => {
+                    let block_size = requested.min(config.max_block_size);
+                    self.block_size = usize::from(block_size);
+                    granted.push(TftpOption::BlockSize(block_size));
+                }
+                TftpOption::Timeout(seconds) => {
+                    self.timeout = Duration::from_secs(u64::from(seconds));
+                    granted.push(TftpOption::Timeout(seconds));
+                }
+                TftpOption::TransferSize(_) => {
+                    granted.extend(size.map(TftpOption::TransferSize));
+                }
+                TftpOption::WindowSize(requested) => {
+                    let window_size = requested.min(config.max_window_size).max(1);
+                    self.window_size = usize::from(window_size);
+                    granted.push(TftpOption::WindowSize(window_size));
+                }
+                TftpOption::Unknown { .. } => {}
+            }
+        }
+        granted
+    }
+
+    async fn send_packet(&self, packet: &TftpPacket) -> io::Result<()> {
+        let bytes: Vec<u8> = packet.to_bytes().collect();
+        self.socket.send_to(&bytes, self.peer).await?;
+        Ok(())
+    }
+
+    async fn fail(&self, error: Error) -> io::Result<()> {
+        self.send_packet(&TftpPacket::Error { error }).await
+    }
+
+    /// The next packet of the client, `None` if it doesn't send one before `deadline`. Packets
+    /// from other ports are answered with an error, invalid packets are ignored.
+    ///
+    /// The deadline is kept across packets which don't make progress, otherwise a client
+    /// could postpone retransmissions and keep the session alive forever.
+    async fn receive_packet(&mut self, deadline: Instant) -> io::Result<Option<TftpPacket>> {
+        loop {
+            let received =
+                tokio::time::timeout_at(deadline, self.socket.recv_from(&mut self.buffer));
+            let (length, from) = match received.await {
+                Ok(received) => received?,
+                Err(_) => return Ok(None),
+            };
+            if from != self.peer {
+                let error = Error::UnkownTransferId {
+                    message: format!("Expected packets from {}", self.peer),
+                };
+                let error: Vec<u8> = TftpPacket::Error { error }.to_bytes().collect();
+                self.socket.send_to(&error, from).await?;
+                continue;
+            }
+            if let Ok(packet) = TftpPacket::parse(&self.buffer[..length]) {
+                return Ok(Some(packet));
+            }
+        }
+    }
+
+    /// Sends a file, a window of blocks at a time, until the final short block is acked.
+    async fn send<B: Backend>(
+        mut self,
+        backend: &B,
+        filename: &str,
+        options: &[TftpOption],
+        config: &Config,
+    ) -> io::Result<()> {
+        let (mut reader, size) = match backend.open(filename).await {
+            Ok(file) => file,
+            Err(error) => return self.fail(error).await,
+        };
+        let granted = self.negotiate(options, size, config);
+        if !granted.is_empty() && !self.acknowledge_options(granted).await? {
+            return Ok(());
+        }
+        // Blocks sent but not acked yet, the first `sent` of them have been sent.
+        let mut window = VecDeque::new();
+        let mut sent = 0;
+        let mut next: u16 = 1;
+        let mut read_all = false;
+        let mut retries = 0;
+        let mut deadline = Instant::now();
+        loop {
+            while window.len() < self.window_size && !read_all {
+                let data = match read_block(&mut reader, self.block_size).await {
+                    Ok(data) => data,
+                    Err(e) => return self.fail(undefined(e)).await,
+                };
+                read_all = data.len() < self.block_size;
+                window.push_back(TftpPacket::Data { block: next, data });
+                next = next.wrapping_add(1);
+            }
+            if window.is_empty() {
+                return Ok(());
+            }
+            if sent < window.len() {
+                for packet in window.iter().skip(sent) {
+                    self.send_packet(packet).await?;
+                }
+                sent = window.len();
+                deadline = Instant::now() + self.timeout;
+            }
+            match self.receive_packet(deadline).await? {
+                None if retries < self.retries => {
+                    retries += 1;
+                    sent = 0;
+                }
+                None => return Ok(()),
+                Some(TftpPacket::Ack { block }) => {
+                    let acked = window.iter().position(
+                        |packet| matches!(packet, TftpPacket::Data { block: b, .. } if *b == block),
+                    );
+                    // Acks of blocks outside of the window are duplicates.
+                    if let Some(acked) = acked {
+                        window.drain(..=acked);
+                        retries = 0;
+                        // Blocks after the acked one got lost, if there are any (RFC 7440).
+                        sent = 0;
+                    }
+                }
+                Some(TftpPacket::Error { .. }) => return Ok(()),
+                Some(_) => {}
+            }
+        }
+    }
+
+    /// Sends the options acknowledgement of a read request until the client acks it with
+    /// block 0, returns false if it doesn't.
+    async fn acknowledge_options(&mut self, options: Vec<TftpOption>) -> io::Result<bool> {
+        let oack = TftpPacket::OptionAck { options };
+        for _ in 0..=self.retries {
+            self.send_packet(&oack).await?;
+            let deadline = Instant::now() + self.timeout;
+            while let Some(packet) = self.receive_packet(deadline).await? {
+                match packet {
+                    TftpPacket::Ack { block: 0 } => return Ok(true),
+                    TftpPacket::Error { .. } => return Ok(false),
+                    _ => {}
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    /// Receives a file, acking every window of blocks, until a short block arrives.
+    async fn receive<B: Backend>(
+        mut self,
+        backend: &B,
+        filename: &str,
+        options: &[TftpOption],
+        config: &Config,
+    ) -> io::Result<()> {
+        let mut writer = match backend.create(filename).await {
+            Ok(writer) => writer,
+            Err(error) => return self.fail(error).await,
+        };
+        let size = options.iter().find_map(|option| match option {
+            TftpOption::TransferSize(size) => Some(*size),
+            _ => None,
+        });
+        let granted = self.negotiate(options, size, config);
+        // The last reply, resent on timeouts.
+        let mut reply = if granted.is_empty() {
+            TftpPacket::Ack { block: 0 }
+        } else {
+            TftpPacket::OptionAck { options: granted }
+        };
+        self.send_packet(&reply).await?;
+        let mut expected: u16 = 1;
+        // Blocks received since the last ack.
+        let mut received = 0;
+        let mut retries = 0;
+        // Only postponed by blocks received in order.
+        let mut deadline = Instant::now() + self.timeout;
+        loop {
+            match self.receive_packet(deadline).await? {
+                None if retries < self.retries => {
+                    retries += 1;
+                    received = 0;
+                    self.send_packet(&reply).await?;
+                    deadline = Instant::now() + self.timeout;
+                }
+                None => return Ok(()),
+                Some(TftpPacket::Data { data, .. }) if data.len() > self.block_size => {
+                    let error = Error::IllegalTftpOperation {
+                        message: format!(
+                            "Blocks carry at most {} bytes, got {}",
+                            self.block_size,
+                            data.len()
+                        ),
+                    };
+                    return self.fail(error).await;
+                }
+                Some(TftpPacket::Data { block, data }) if block == expected => {
+                    if let Err(e) = writer.write_all(&data).await {
+                        return self.fail(write_error(e)).await;
+                    }
+                    retries = 0;
+                    received += 1;
+                    expected = expected.wrapping_add(1);
+                    deadline = Instant::now() + self.timeout;
+                    let last = data.len() < self.block_size;
+                    if last || received == self.window_size {
+                        reply = TftpPacket::Ack { block };
+                        self.send_packet(&reply).await?;
+                        received = 0;
+                    }
+                    if last {
+                        return self.finish(writer, &reply).await;
+                    }
+                }
+                // A block or an ack got lost, the client resumes after the last block
+                // received in order.
+                Some(TftpPacket::Data { .. }) => {
+                    reply = TftpPacket::Ack {
+                        block: expected.wrapping_sub(1),
+                    };
+                    self.send_packet(&reply).await?;
+                    received = 0;
+                }
+                Some(TftpPacket::Error { .. }) => return Ok(()),
+                Some(_) => {}
+            }
+        }
+    }
+
+    /// Completes a received file, then lingers to ack the final block again in case the ack
+    /// got lost.
+    async fn finish(mut self, mut writer: Writer, ack: &TftpPacket) -> io::Result<()> {
+        if let Err(e) = writer.shutdown().await {
+            return self.fail(write_error(e)).await;
+        }
+        let deadline = Instant::now() + self.timeout;
+        while let Some(packet) = self.receive_packet(deadline).await? {
+            if let TftpPacket::Data { .. } = packet {
+                self.send_packet(ack).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_error(e: io::Error) -> Error {
+    Error::DiskFull {
+        message: e.to_string(),
+    }
+}
+
+/// Reads a block of `size` bytes, shorter only at the end of the file.
+async fn read_block(reader: &mut Reader, size: usize) -> io::Result<Vec<u8>> {
+    let mut block = Vec::with_capacity(size);
+    reader.take(size as u64).read_to_end(&mut block).await?;
+    Ok(block)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tftp::backend::Memory;
+
+    /// A client talking to a server on localhost.
+    struct Client {
+        socket: UdpSocket,
+        server: SocketAddr,
+    }
+
+    impl Client {
+        async fn new(memory: &Memory, config: Config) -> Self {
+            let server = Server::bind("127.0.0.1:0", memory.clone())
+                .await
+                .unwrap()
+                .with_config(config);
+            let address = server.local_addr().unwrap();
+            tokio::spawn(server.run());
+            Client {
+                socket: UdpSocket::bind("127.0.0.1:0").await.unwrap(),
+                server: address,
+            }
+        }
+
+        async fn send(&self, packet: TftpPacket, to: SocketAddr) {
+            let bytes: Vec<u8> = packet.to_bytes().collect();
+            self.socket.send_to(&bytes, to).await.unwrap();
+        }
+
+        async fn request(&self, packet: TftpPacket) {
+            self.send(packet, self.server).await
+        }
+
+        /// The next packet, `None` if none arrives within `wait`.
+        async fn receive_within(&self, wait: Duration) -> Option<(TftpPacket, SocketAddr)> {
+            let mut buffer = vec![0u8; usize::from(MAX_BLOCK_SIZE) + 4];
+            let received = tokio::time::timeout(wait, self.socket.recv_from(&mut buffer));
+            let (length, from) = received.await.ok()?.unwrap();
+            Some((TftpPacket::parse(&buffer[..length]).unwrap(), from))
+        }
+
+        async fn receive(&self) -> (TftpPacket, SocketAddr) {
+            self.receive_within(Duration::from_secs(5)).await.unwrap()
+        }
+
+        /// Receives data until the final short block, acking every `window` blocks.
+        async fn download(&self, block_size: usize, window: usize) -> Vec<u8> {
+            let mut content = Vec::new();
+            let mut received = 0;
+            loop {
+                let (packet, session) = self.receive().await;
+                let (block, data) = match packet {
+                    TftpPacket::Data { block, data } => (block, data),
+                    packet => panic!("Expected data, got {:?}", packet),
+                };
+                assert_eq!(usize::from(block), content.len() / block_size + 1);
+                content.extend_from_slice(&data);
+                received += 1;
+                let last = data.len() < block_size;
+                if last || received == window {
+                    self.send(TftpPacket::Ack { block }, session).await;
+                    received = 0;
+                }
+                if last {
+                    return content;
+                }
+            }
+        }
+    }
+
+    fn config(timeout: Duration) -> Config {
+        Config {
+            timeout,
+            ..Config::default()
+        }
+    }
+
+    fn content(length: usize) -> Vec<u8> {
+        (0..length).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn rrq(filename: &str, options: Vec<TftpOption>) -> TftpPacket {
+        TftpPacket::ReadRequest {
+            filename: String::from(filename),
+            mode: Mode::Octet,
+            options,
+        }
+    }
+
+    fn wrq(filename: &str, options: Vec<TftpOption>) -> TftpPacket {
+        TftpPacket::WriteRequest {
+            filename: String::from(filename),
+            mode: Mode::Octet,
+            options,
+        }
+    }
+
+    #[tokio::test]
+    async fn files_are_read_in_lock_step() {
+        let memory = Memory::new();
+        memory.insert("short", content(1300));
+        memory.insert("even", content(1024));
+        let client = Client::new(&memory, Config::default()).await;
+
+        client.request(rrq("short", vec![])).await;
+        assert_eq!(client.download(512, 1).await, content(1300));
+        // Files of whole blocks end with an empty one.
+        client.request(rrq("even", vec![])).await;
+        assert_eq!(client.download(512, 1).await, content(1024));
+    }
+
+    #[tokio::test]
+    async fn sessions_have_a_transfer_id_of_their_own() {
+        let memory = Memory::new();
+        memory.insert("file", content(10));
+        let client = Client::new(&memory, Config::default()).await;
+
+        client.request(rrq("file", vec![])).await;
+        let (_, session) = client.receive().await;
+        assert_ne!(session.port(), client.server.port());
+        // Acks from another port are refused, without disturbing the session.
+        let intruder = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let ack: Vec<u8> = TftpPacket::Ack { block: 1 }.to_bytes().collect();
+        intruder.send_to(&ack, session).await.unwrap();
+        let mut buffer = [0u8; 512];
+        let (length, _) = intruder.recv_from(&mut buffer).await.unwrap();
+        assert!(matches!(
+            TftpPacket::parse(&buffer[..length]),
+            Ok(TftpPacket::Error {
+                error: Error::UnkownTransferId { .. }
+            })
+        ));
+        client.send(TftpPacket::Ack { block: 1 }, session).await;
+        assert!(client
+            .receive_within(Duration::from_millis(200))
+            .await
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn options_are_negotiated() {
+        let memory = Memory::new();
+        memory.insert("image", content(5000));
+        let config = Config {
+            max_window_size: 4,
+            ..Config::default()
+        };
+        let client = Client::new(&memory, config).await;
+
+        client
+            .request(rrq(
+                "image",
+                vec![
+                    TftpOption::BlockSize(600),
+                    TftpOption::TransferSize(0),
+                    TftpOption::WindowSize(8),
+                    TftpOption::new("multicast", ""),
+                ],
+            ))
+            .await;
+        let (oack, session) = client.receive().await;
+        assert_eq!(
+            oack,
+            TftpPacket::OptionAck {
+                options: vec![
+                    TftpOption::BlockSize(600),
+                    TftpOption::TransferSize(5000),
+                    TftpOption::WindowSize(4),
+                ]
+            }
+        );
+        client.send(TftpPacket::Ack { block: 0 }, session).await;
+        assert_eq!(client.download(600, 4).await, content(5000));
+    }
+
+    #[tokio::test]
+    async fn blocks_are_resent_on_timeouts_only() {
+        let memory = Memory::new();
+        memory.insert("file", content(2000));
+        let client = Client::new(&memory, config(Duration::from_millis(300))).await;
+
+        client.request(rrq("file", vec![])).await;
+        let (first, session) = client.receive().await;
+        // Not acking the block makes the server send it again.
+        assert_eq!(client.receive().await.0, first);
+        client.send(TftpPacket::Ack { block: 1 }, session).await;
+        let (second, _) = client.receive().await;
+        assert!(matches!(second, TftpPacket::Data { block: 2, .. }));
+        // The duplicate ack of the first block must not be answered with the second block
+        // again, only a timeout does.
+        client.send(TftpPacket::Ack { block: 1 }, session).await;
+        assert!(client
+            .receive_within(Duration::from_millis(150))
+            .await
+            .is_none());
+        assert_eq!(client.receive().await.0, second);
+    }
+
+    #[tokio::test]
+    async fn stray_packets_do_not_postpone_retransmissions() {
+        let memory = Memory::new();
+        memory.insert("file", content(2000));
+        let timeout = Duration::from_millis(300);
+        let client = Client::new(&memory, config(timeout)).await;
+
+        client.request(rrq("file", vec![])).await;
+        let (first, session) = client.receive().await;
+        let start = std::time::Instant::now();
+        let resent = loop {
+            assert!(start.elapsed() < 3 * timeout, "The block wasn't resent");
+            client.send(TftpPacket::Ack { block: 0 }, session).await;
+            if let Some((packet, _)) = client.receive_within(Duration::from_millis(50)).await {
+                break packet;
+            }
+        };
+        assert_eq!(resent, first);
+        assert!(start.elapsed() < 2 * timeout);
+    }
+
+    #[tokio::test]
+    async fn files_are_written() {
+        let memory = Memory::new();
+        let client = Client::new(&memory, Config::default()).await;
+        let file = content(1100);
+
+        client.request(wrq("upload", vec![])).await;
+        let (ack, session) = client.receive().await;
+        assert_eq!(ack, TftpPacket::Ack { block: 0 });
+        for (i, block) in file.chunks(512).enumerate() {
+            let data = TftpPacket::Data {
+                block: i as u16 + 1,
+                data: block.to_vec(),
+            };
+            client.send(data, session).await;
+            let (ack, _) = client.receive().await;
+            assert_eq!(
+                ack,
+                TftpPacket::Ack {
+                    block: i as u16 + 1
+                }
+            );
+        }
+        assert_eq!(memory.get("upload"), Some(file));
+    }
+
+    #[tokio::test]
+    async fn blocks_larger_than_negotiated_are_refused() {
+        let memory = Memory::new();
+        let client = Client::new(&memory, config(Duration::from_millis(200))).await;
+
+        let options = vec![TftpOption::BlockSize(100)];
+        client.request(wrq("upload", options.clone())).await;
+        let (oack, session) = client.receive().await;
+        assert_eq!(oack, TftpPacket::OptionAck { options });
+        let data = TftpPacket::Data {
+            block: 1,
+            data: content(101),
+        };
+        client.send(data, session).await;
+        assert!(matches!(
+            client.receive().await.0,
+            TftpPacket::Error {
+                error: Error::IllegalTftpOperation { .. }
+            }
+        ));
+        // The session ended, it neither resends nor stores anything.
+        assert!(client
+            .receive_within(Duration::from_millis(500))
+            .await
+            .is_none());
+        assert_eq!(memory.get("upload"), None);
+    }
+
+    #[tokio::test]
+    async fn windows_of_blocks_are_written() {
+        let memory = Memory::new();
+        let client = Client::new(&memory, Config::default()).await;
+        let file = content(300);
+
+        let options = vec![TftpOption::BlockSize(100), TftpOption::WindowSize(2)];
+        client.request(wrq("upload", options.clone())).await;
+        let (oack, session) = client.receive().await;
+        assert_eq!(oack, TftpPacket::OptionAck { options });
+        let data = |block: u16| TftpPacket::Data {
+            block,
+            data: file
+                .chunks(100)
+                .nth(usize::from(block) - 1)
+                .unwrap_or(&[])
+                .to_vec(),
+        };
+        // The second block gets lost, the server acks the first one to resume from there.
+        client.send(data(1), session).await;
+        client.send(data(3), session).await;
+        assert_eq!(client.receive().await.0, TftpPacket::Ack { block: 1 });
+        client.send(data(2), session).await;
+        client.send(data(3), session).await;
+        assert_eq!(client.receive().await.0, TftpPacket::Ack { block: 3 });
+        client.send(data(4), session).await;
+        assert_eq!(client.receive().await.0, TftpPacket::Ack { block: 4 });
+        assert_eq!(memory.get("upload"), Some(file));
+    }
+
+    #[tokio::test]
+    async fn failures_are_reported() {
+        let memory = Memory::new();
+        let client = Client::new(&memory, Config::default()).await;
+
+        client.request(rrq("missing", vec![])).await;
+        assert!(matches!(
+            client.receive().await.0,
+            TftpPacket::Error {
+                error: Error::FileNotFound { .. }
+            }
+        ));
+        client
+            .request(TftpPacket::ReadRequest {
+                filename: String::from("root"),
+                mode: Mode::Mail,
+                options: vec![],
+            })
+            .await;
+        let (error, from) = client.receive().await;
+        assert!(matches!(
+            error,
+            TftpPacket::Error {
+                error: Error::IllegalTftpOperation { .. }
+            }
+        ));
+        assert_eq!(from, client.server);
+        client.request(TftpPacket::Ack { block: 1 }).await;
+        assert!(matches!(
+            client.receive().await.0,
+            TftpPacket::Error {
+                error: Error::IllegalTftpOperation { .. }
+            }
+        ));
+    }
+}